@@ -0,0 +1,94 @@
+use crate::{Ray, Vec3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn min(&self) -> Vec3 {
+        self.min
+    }
+
+    pub fn max(&self) -> Vec3 {
+        self.max
+    }
+
+    pub fn surrounding(self, other: Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            Vec3::new(a.x(), a.y(), a.z()),
+            Vec3::new(b.x(), a.y(), a.z()),
+            Vec3::new(a.x(), b.y(), a.z()),
+            Vec3::new(b.x(), b.y(), a.z()),
+            Vec3::new(a.x(), a.y(), b.z()),
+            Vec3::new(b.x(), a.y(), b.z()),
+            Vec3::new(a.x(), b.y(), b.z()),
+            Vec3::new(b.x(), b.y(), b.z()),
+        ]
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.clip(ray, t_min, t_max).is_some()
+    }
+
+    /// Part of the `[t_min, t_max]` ray interval that lies inside the box.
+    pub fn clip(&self, ray: &Ray, mut t_min: f64, mut t_max: f64) -> Option<(f64, f64)> {
+        let origin = ray.origin();
+        let direction = ray.direction();
+        let axes = [
+            (origin.x(), direction.x(), self.min.x(), self.max.x()),
+            (origin.y(), direction.y(), self.min.y(), self.max.y()),
+            (origin.z(), direction.z(), self.min.z(), self.max.z()),
+        ];
+        for &(o, d, min, max) in &axes {
+            let inv_d = 1.0 / d;
+            let mut t0 = (min - o) * inv_d;
+            let mut t1 = (max - o) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max <= t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_surrounding() {
+        let a = Aabb::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0));
+        let b = Aabb::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(0.5, 2.0, 0.5));
+        let expected = Aabb::new(Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 2.0, 1.0));
+        assert_eq!(a.surrounding(b), expected);
+    }
+
+    #[test]
+    fn test_hit() {
+        let aabb = Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let through = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let past = Ray::new(Vec3::new(2.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        assert!(aabb.hit(&through, 0.0, f64::MAX));
+        assert!(!aabb.hit(&through, 0.0, 3.0));
+        assert!(!aabb.hit(&past, 0.0, f64::MAX));
+    }
+}
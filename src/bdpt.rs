@@ -0,0 +1,647 @@
+use crate::light::power_heuristic;
+use crate::{Camera, HitRecord, Ray, Scene, Vec3};
+use rand::Rng;
+
+/// Bidirectional path tracer (Veach, "Robust Monte Carlo Methods for Light
+/// Transport Simulation", 1997, chapter 10).
+///
+/// Each camera sample traces one subpath from the camera and one from a
+/// light, then connects every pair of their prefixes, weighting the
+/// strategies with the balance heuristic. Infinite lights start no light
+/// subpaths, so paths reaching them only weigh sampling the light against
+/// sampling the BSDF, with the power heuristic as in `PathTracer`.
+/// Connections of light subpaths to the camera land on arbitrary pixels and
+/// are splatted to the film.
+/// Participating media are ignored and their boundaries passed through.
+pub struct BidirectionalPathTracer {
+    max_depth: u32,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Copy)]
+enum Kind<'s> {
+    Camera,
+    /// Point on a light, either starting a light subpath or sampled towards
+    /// a camera vertex; `delta` for lights at a single point.
+    Light {
+        light: usize,
+        delta: bool,
+    },
+    /// Surface point reached from the direction `wo`.
+    Surface {
+        hit: HitRecord<'s>,
+        wo: Vec3,
+    },
+}
+
+#[derive(Clone, Copy)]
+struct Vertex<'s> {
+    kind: Kind<'s>,
+    point: Vec3,
+    /// Geometric normal, zero for points without a surface.
+    normal: Vec3,
+    /// Time of the camera ray the path belongs to.
+    time: f64,
+    /// Throughput of the subpath up to this vertex.
+    beta: Vec3,
+    /// Whether the subpath left this vertex through a delta lobe.
+    delta: bool,
+    /// Area density of sampling this vertex from the previous one on its
+    /// subpath, and from the next one when the subpath is traced backwards.
+    pdf_fwd: f64,
+    pdf_rev: f64,
+}
+
+impl<'s> Vertex<'s> {
+    fn camera(camera: &Camera, beta: Vec3, time: f64) -> Self {
+        Self {
+            kind: Kind::Camera,
+            point: camera.origin(),
+            normal: camera.forward(),
+            time,
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn surface(hit: HitRecord<'s>, wo: Vec3, beta: Vec3, time: f64) -> Self {
+        Self {
+            kind: Kind::Surface { hit, wo },
+            point: hit.point(),
+            normal: hit.normal(),
+            time,
+            beta,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        }
+    }
+
+    fn is_on_surface(&self) -> bool {
+        self.normal != Vec3::default()
+    }
+
+    fn is_delta_light(&self) -> bool {
+        match self.kind {
+            Kind::Light { delta, .. } => delta,
+            _ => false,
+        }
+    }
+
+    fn light(&self) -> Option<usize> {
+        match self.kind {
+            Kind::Light { light, .. } => Some(light),
+            Kind::Surface { hit, .. } => hit.light(),
+            Kind::Camera => None,
+        }
+    }
+
+    /// BSDF value for light scattered between unit direction `wi` and the
+    /// previous vertex.
+    fn f(&self, wi: Vec3) -> Vec3 {
+        match self.kind {
+            Kind::Surface { hit, wo } => hit.material().eval(&hit, wo, wi),
+            _ => Vec3::default(),
+        }
+    }
+
+    /// Absolute cosine between unit direction `w` and the normal, one off surfaces.
+    fn cos(&self, w: Vec3) -> f64 {
+        if self.is_on_surface() {
+            self.normal.dot(w).abs()
+        } else {
+            1.0
+        }
+    }
+
+    /// Radiance emitted from this vertex towards `towards`.
+    fn le(&self, towards: &Vertex) -> Vec3 {
+        match self.kind {
+            Kind::Surface { hit, .. } => {
+                let w = (towards.point - self.point).normalize();
+                hit.material().emitted(&hit, w)
+            }
+            _ => Vec3::default(),
+        }
+    }
+
+    /// Converts a solid angle density of leaving this vertex towards `next`
+    /// to an area density at `next`.
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        let w = next.point - self.point;
+        let distance2 = w.squared_length();
+        if distance2 == 0.0 {
+            return 0.0;
+        }
+        pdf * next.cos(w / distance2.sqrt()) / distance2
+    }
+
+    /// Area density of sampling `next` from this vertex, reached from `prev`.
+    fn pdf(&self, scene: &Scene, camera: &Camera, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let wn = (next.point - self.point).normalize();
+        let pdf = match self.kind {
+            Kind::Light { .. } => return self.pdf_light(scene, next),
+            Kind::Camera => camera.pdf_we(wn),
+            Kind::Surface { hit, .. } => match prev {
+                Some(prev) => {
+                    let wp = (prev.point - self.point).normalize();
+                    hit.material().pdf(&hit, wp, wn)
+                }
+                None => return 0.0,
+            },
+        };
+        self.convert_density(pdf, next)
+    }
+
+    /// Area density of light emitted from this point reaching `next`.
+    fn pdf_light(&self, scene: &Scene, next: &Vertex) -> f64 {
+        let light = match self.light() {
+            Some(light) => light,
+            None => return 0.0,
+        };
+        let w = (next.point - self.point).normalize();
+        let (_, pdf_direction) = scene.lights()[light].pdf_le(self.point, self.normal, w);
+        self.convert_density(pdf_direction, next)
+    }
+
+    /// Density of starting a light subpath at this point, emitting towards `next`.
+    fn pdf_light_origin(&self, scene: &Scene, next: &Vertex) -> f64 {
+        let light = match self.light() {
+            Some(light) => light,
+            None => return 0.0,
+        };
+        let w = (next.point - self.point).normalize();
+        let (pdf_position, _) = scene.lights()[light].pdf_le(self.point, self.normal, w);
+        scene.emitter_pmf(light) * pdf_position
+    }
+}
+
+impl BidirectionalPathTracer {
+    /// Paths have at most `max_depth` scattering vertices, as in `PathTracer`.
+    pub fn new(max_depth: u32) -> Self {
+        Self { max_depth }
+    }
+
+    /// Radiance arriving at the camera through image coordinates `(u, v)`.
+    /// Contributions of light subpaths to other image points are passed to `splat`.
+    pub fn li<R: Rng>(
+        &self,
+        (u, v): (f64, f64),
+        camera: &Camera,
+        scene: &Scene,
+        rng: &mut R,
+        splat: &mut dyn FnMut((f64, f64), Vec3),
+    ) -> Vec3 {
+        let ray = camera.get_ray(u, v);
+        let direction = ray.direction().normalize();
+        let one = Vec3::new(1.0, 1.0, 1.0);
+        let mut camera_path = vec![Vertex::camera(camera, one, ray.time())];
+        let mut radiance = self.random_walk(
+            scene,
+            Ray::new(ray.origin(), direction, ray.time()),
+            one,
+            camera.pdf_we(direction),
+            self.max_depth as usize + 1,
+            rng,
+            &mut camera_path,
+        );
+        let light_path = self.light_subpath(scene, ray.time(), rng);
+
+        let max_depth = self.max_depth as usize;
+        for t in 1..=camera_path.len() {
+            // Sampling a point on a light does not need a light subpath.
+            for s in 0..=light_path.len().max(1) {
+                if s + t < 2 || (s == 1 && t == 1) || s + t - 2 > max_depth {
+                    continue;
+                }
+                let connection = Connection {
+                    scene,
+                    camera,
+                    light_path: &light_path,
+                    camera_path: &camera_path,
+                    s,
+                    t,
+                };
+                radiance += connection.contribution(rng, &mut *splat);
+            }
+        }
+        radiance
+    }
+
+    fn light_subpath<'s, R: Rng>(
+        &self,
+        scene: &'s Scene,
+        time: f64,
+        rng: &mut R,
+    ) -> Vec<Vertex<'s>> {
+        let mut path = Vec::new();
+        let (light, pmf) = match scene.sample_emitter(rng.gen()) {
+            Some(choice) => choice,
+            None => return path,
+        };
+        let u_position = (rng.gen(), rng.gen());
+        let emission = match scene.lights()[light].sample_le(u_position, (rng.gen(), rng.gen())) {
+            Some(emission)
+                if emission.pdf_position() > 0.0
+                    && emission.pdf_direction() > 0.0
+                    && emission.radiance() != Vec3::default() =>
+            {
+                emission
+            }
+            _ => return path,
+        };
+        let vertex = Vertex {
+            kind: Kind::Light {
+                light,
+                delta: emission.is_delta(),
+            },
+            point: emission.origin(),
+            normal: emission.normal(),
+            time,
+            beta: emission.radiance(),
+            delta: false,
+            pdf_fwd: pmf * emission.pdf_position(),
+            pdf_rev: 0.0,
+        };
+        let pdf = pmf * emission.pdf_position() * emission.pdf_direction();
+        let beta = emission.radiance() * (vertex.cos(emission.direction()) / pdf);
+        path.push(vertex);
+        let ray = Ray::new(emission.origin(), emission.direction(), time);
+        self.random_walk(
+            scene,
+            ray,
+            beta,
+            emission.pdf_direction(),
+            self.max_depth as usize,
+            rng,
+            &mut path,
+        );
+        path
+    }
+
+    /// Extends `path` by up to `max_vertices` surface vertices along `ray`,
+    /// which leaves the last vertex with solid angle density `pdf`. Returns
+    /// the radiance of infinite lights reaching the start of the path through
+    /// a ray that left the scene, weighted against sampling those lights.
+    #[allow(clippy::too_many_arguments)]
+    fn random_walk<'s, R: Rng>(
+        &self,
+        scene: &'s Scene,
+        mut ray: Ray,
+        mut beta: Vec3,
+        pdf: f64,
+        max_vertices: usize,
+        rng: &mut R,
+        path: &mut Vec<Vertex<'s>>,
+    ) -> Vec3 {
+        let start = path.len();
+        let mut pdf_fwd = pdf;
+        while path.len() - start < max_vertices {
+            let hit = match scene.world().hit(&ray, 0.001, f64::MAX) {
+                Some(hit) => hit,
+                None => {
+                    let from = &path[path.len() - 1];
+                    return beta * escaped(scene, from, pdf_fwd, ray.direction());
+                }
+            };
+            if hit.material().is_interface() {
+                ray = Ray::new(hit.point(), ray.direction(), ray.time());
+                continue;
+            }
+            let wo = -ray.direction();
+            let prev = path.len() - 1;
+            let mut vertex = Vertex::surface(hit, wo, beta, ray.time());
+            vertex.pdf_fwd = path[prev].convert_density(pdf_fwd, &vertex);
+            path.push(vertex);
+            if path.len() - start >= max_vertices {
+                break;
+            }
+
+            let material = hit.material();
+            let sample = match material.sample(&hit, wo, rng.gen(), (rng.gen(), rng.gen())) {
+                Some(sample) if sample.pdf() > 0.0 => sample,
+                _ => break,
+            };
+            let wi = sample.wi();
+            beta *= sample.value() * (wi.dot(hit.normal()).abs() / sample.pdf());
+            if beta == Vec3::default() {
+                break;
+            }
+            let pdf_rev = if sample.lobe().is_delta() {
+                path[prev + 1].delta = true;
+                pdf_fwd = 0.0;
+                0.0
+            } else {
+                pdf_fwd = sample.pdf();
+                material.pdf(&hit, wi, wo)
+            };
+            path[prev].pdf_rev = vertex.convert_density(pdf_rev, &path[prev]);
+            ray = Ray::new(hit.point(), wi, ray.time());
+        }
+        Vec3::default()
+    }
+}
+
+/// Radiance of the infinite lights in unit `direction` seen from `from`,
+/// which sampled the direction with solid angle density `pdf`, weighted
+/// against sampling the lights from there.
+fn escaped(scene: &Scene, from: &Vertex, pdf: f64, direction: Vec3) -> Vec3 {
+    let lights = scene.lights();
+    let mut radiance = Vec3::default();
+    for &idx in scene.infinite_lights() {
+        let le = lights[idx].le(direction);
+        radiance += match from.kind {
+            Kind::Surface { .. } if !from.delta => {
+                let pmf = scene.light_sampler().pmf(from.point, from.normal, idx);
+                let light_pdf = pmf * lights[idx].pdf_li(from.point, direction);
+                le * power_heuristic(pdf, light_pdf)
+            }
+            _ => le,
+        };
+    }
+    radiance
+}
+
+/// Strategy joining the first `s` light subpath vertices to the first `t`
+/// camera subpath vertices.
+struct Connection<'a, 's> {
+    scene: &'s Scene,
+    camera: &'a Camera,
+    light_path: &'a [Vertex<'s>],
+    camera_path: &'a [Vertex<'s>],
+    s: usize,
+    t: usize,
+}
+
+impl<'s> Connection<'_, 's> {
+    fn contribution<R: Rng>(&self, rng: &mut R, splat: &mut dyn FnMut((f64, f64), Vec3)) -> Vec3 {
+        let (s, t) = (self.s, self.t);
+        if s == 0 {
+            self.hit_light()
+        } else if t == 1 {
+            if let Some((uv, value)) = self.connect_camera() {
+                splat(uv, value);
+            }
+            Vec3::default()
+        } else if s == 1 {
+            self.sample_light(rng)
+        } else {
+            self.connect_subpaths()
+        }
+    }
+
+    /// The camera subpath ends on a light.
+    fn hit_light(&self) -> Vec3 {
+        let t = self.t;
+        let pt = &self.camera_path[t - 1];
+        let le = pt.le(&self.camera_path[t - 2]);
+        if le == Vec3::default() {
+            return le;
+        }
+        // Emitters that are not registered lights can only be hit.
+        let weight = if pt.light().is_some() {
+            self.mis_weight(None)
+        } else {
+            1.0
+        };
+        pt.beta * le * weight
+    }
+
+    /// Connects the end of the light subpath to the camera; returns the image
+    /// point it is seen at and the contribution there.
+    fn connect_camera(&self) -> Option<((f64, f64), Vec3)> {
+        let qs = &self.light_path[self.s - 1];
+        let uv = self.camera.project(qs.point)?;
+        let to_camera = self.camera.origin() - qs.point;
+        let distance = to_camera.length();
+        let wi = to_camera / distance;
+        let we = self.camera.we(-wi);
+        if we == 0.0 {
+            return None;
+        }
+        // Density of choosing the camera point, per solid angle at `qs`.
+        let pdf = distance * distance / self.camera.forward().dot(-wi);
+        let sampled = Vertex::camera(self.camera, Vec3::new(we, we, we) / pdf, qs.time);
+        let value = qs.beta * qs.f(wi) * sampled.beta * qs.cos(wi);
+        if value == Vec3::default() || !self.scene.unoccluded(qs.point, wi, distance, qs.time) {
+            return None;
+        }
+        Some((uv, value * self.mis_weight(Some(sampled))))
+    }
+
+    /// Samples a point on a light and connects it to the camera subpath.
+    fn sample_light<R: Rng>(&self, rng: &mut R) -> Vec3 {
+        let pt = &self.camera_path[self.t - 1];
+        let sampler = self.scene.light_sampler();
+        let (idx, pmf) = match sampler.sample(pt.point, pt.normal, rng.gen()) {
+            Some(choice) => choice,
+            None => return Vec3::default(),
+        };
+        let light = &self.scene.lights()[idx];
+        let sample = match light.sample_li(pt.point, (rng.gen(), rng.gen())) {
+            Some(sample) if sample.pdf() > 0.0 && sample.radiance() != Vec3::default() => sample,
+            _ => return Vec3::default(),
+        };
+        let wi = sample.wi();
+        let light_pdf = pmf * sample.pdf();
+        let value = pt.beta * pt.f(wi) * sample.radiance() * (pt.cos(wi) / light_pdf);
+        if value == Vec3::default()
+            || !self
+                .scene
+                .unoccluded(pt.point, wi, sample.distance(), pt.time)
+        {
+            return Vec3::default();
+        }
+        if light.is_infinite() {
+            // Infinite lights do not start light subpaths, so this competes
+            // only with camera subpaths leaving the scene.
+            let weight = match pt.kind {
+                Kind::Surface { hit, wo } if !sample.is_delta() => {
+                    power_heuristic(light_pdf, hit.material().pdf(&hit, wo, wi))
+                }
+                _ => 1.0,
+            };
+            return value * weight;
+        }
+        let mut sampled = Vertex {
+            kind: Kind::Light {
+                light: idx,
+                delta: sample.is_delta(),
+            },
+            point: pt.point + wi * sample.distance(),
+            normal: sample.normal(),
+            time: pt.time,
+            beta: sample.radiance() / light_pdf,
+            delta: false,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+        };
+        sampled.pdf_fwd = sampled.pdf_light_origin(self.scene, pt);
+        value * self.mis_weight(Some(sampled))
+    }
+
+    fn connect_subpaths(&self) -> Vec3 {
+        let qs = &self.light_path[self.s - 1];
+        let pt = &self.camera_path[self.t - 1];
+        let d = pt.point - qs.point;
+        let distance = d.length();
+        let w = d / distance;
+        let value = qs.beta * qs.f(w) * pt.f(-w) * pt.beta;
+        if value == Vec3::default() || !self.scene.unoccluded(qs.point, w, distance, qs.time) {
+            return Vec3::default();
+        }
+        let g = qs.cos(w) * pt.cos(w) / (distance * distance);
+        value * g * self.mis_weight(None)
+    }
+
+    /// Balance heuristic weight of this strategy among all strategies that
+    /// could have produced the same path. `sampled` replaces the camera
+    /// vertex when `t == 1` or the light vertex when `s == 1`.
+    fn mis_weight(&self, sampled: Option<Vertex<'s>>) -> f64 {
+        let (s, t) = (self.s, self.t);
+        if s + t == 2 {
+            return 1.0;
+        }
+        let mut light = self.light_path[..s.min(self.light_path.len())].to_vec();
+        let mut camera = self.camera_path[..t].to_vec();
+        if let Some(sampled) = sampled {
+            if t == 1 {
+                camera[0] = sampled;
+            } else {
+                light = vec![sampled];
+            }
+        }
+
+        // Reverse densities at the connection, which depend on the strategy.
+        let pt = camera[t - 1];
+        let pt_minus = if t > 1 { Some(camera[t - 2]) } else { None };
+        let qs = if s > 0 { Some(light[s - 1]) } else { None };
+        let qs_minus = if s > 1 { Some(light[s - 2]) } else { None };
+        let (scene, cam) = (self.scene, self.camera);
+        camera[t - 1].pdf_rev = match (&qs, &pt_minus) {
+            (Some(qs), _) => qs.pdf(scene, cam, qs_minus.as_ref(), &pt),
+            (None, Some(pt_minus)) => pt.pdf_light_origin(scene, pt_minus),
+            (None, None) => 0.0,
+        };
+        if let Some(pt_minus) = &pt_minus {
+            camera[t - 2].pdf_rev = match &qs {
+                Some(qs) => pt.pdf(scene, cam, Some(qs), pt_minus),
+                None => pt.pdf_light(scene, pt_minus),
+            };
+        }
+        if let Some(qs) = &qs {
+            light[s - 1].pdf_rev = pt.pdf(scene, cam, pt_minus.as_ref(), qs);
+            if let Some(qs_minus) = &qs_minus {
+                light[s - 2].pdf_rev = qs.pdf(scene, cam, Some(&pt), qs_minus);
+            }
+            light[s - 1].delta = false;
+        }
+        camera[t - 1].delta = false;
+
+        // Ratios of the densities of the other strategies to this one's.
+        let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+        let mut sum = 0.0;
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            ratio *= remap(camera[i].pdf_rev) / remap(camera[i].pdf_fwd);
+            if !camera[i].delta && !camera[i - 1].delta {
+                sum += ratio;
+            }
+        }
+        ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light[i].pdf_rev) / remap(light[i].pdf_fwd);
+            let delta_before = if i > 0 {
+                light[i - 1].delta
+            } else {
+                light[0].is_delta_light()
+            };
+            if !light[i].delta && !delta_before {
+                sum += ratio;
+            }
+        }
+        1.0 / (1.0 + sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DiffuseLight, HitList, Lambertian, LightSampling, PathTracer, PointLight, Sphere};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::sync::Arc;
+
+    /// Diffuse sphere inside a dark room.
+    fn room() -> HitList {
+        let mut world = HitList::new();
+        let white = Arc::new(Lambertian::new(Vec3::new(0.7, 0.7, 0.7)));
+        world.push(Sphere::new(Vec3::default(), 10.0, white.clone()));
+        world.push(Sphere::new(Vec3::new(0.0, -1.0, -3.0), 1.0, white));
+        world
+    }
+
+    fn camera() -> Camera {
+        Camera::new(
+            Vec3::new(-2.0, -1.0, -1.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::default(),
+        )
+    }
+
+    /// Compares image averages, so that splats count wherever they land.
+    fn assert_matches_path_tracer(scene: &Scene) {
+        let camera = camera();
+        let n = 50_000;
+        let mut rng = StdRng::seed_from_u64(5);
+        let tracer = PathTracer::new(4, LightSampling::Mis);
+        let mut expected = Vec3::default();
+        for _ in 0..n {
+            let ray = camera.get_ray(rng.gen(), rng.gen());
+            expected += tracer.li(&ray, scene, &mut rng);
+        }
+        expected /= f64::from(n);
+
+        let bdpt = BidirectionalPathTracer::new(4);
+        let mut direct = Vec3::default();
+        let mut splatted = Vec3::default();
+        for _ in 0..n {
+            let uv = (rng.gen(), rng.gen());
+            direct += bdpt.li(uv, &camera, scene, &mut rng, &mut |_, value| {
+                splatted += value
+            });
+        }
+        let estimate = (direct + splatted) / f64::from(n);
+        assert!(
+            (estimate - expected).length() < 0.03 * expected.length(),
+            "{:?} {:?}",
+            estimate,
+            expected
+        );
+        assert!(splatted.length() > 0.0);
+    }
+
+    #[test]
+    fn test_area_light_matches_path_tracer() {
+        let mut world = room();
+        world.push(Sphere::new(
+            Vec3::new(1.5, 1.5, -2.0),
+            0.8,
+            Arc::new(DiffuseLight::new(Vec3::new(10.0, 10.0, 10.0))),
+        ));
+        assert_matches_path_tracer(&Scene::new(world));
+    }
+
+    #[test]
+    fn test_point_light_matches_path_tracer() {
+        let mut scene = Scene::new(room());
+        scene.add_light(Arc::new(PointLight::new(
+            Vec3::new(1.5, 1.5, -2.0),
+            Vec3::new(20.0, 20.0, 20.0),
+        )));
+        assert_matches_path_tracer(&scene);
+    }
+}
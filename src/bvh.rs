@@ -0,0 +1,245 @@
+use crate::hit::HitRecord;
+use crate::light::Light;
+use crate::material::Scatter;
+use crate::{Aabb, Hit, Ray};
+use std::sync::Arc;
+
+/// Bounding volume hierarchy over a set of objects, split at the median
+/// of the longest axis of the object centroids.
+pub struct Bvh {
+    root: Option<(BvhNode, Aabb)>,
+    /// Objects without a bounding box, with their indices.
+    unbounded: Vec<(usize, Box<dyn Hit>)>,
+}
+
+/// Leaves keep the index of their object in the list the tree was built from.
+enum BvhNode {
+    Leaf(usize, Box<dyn Hit>),
+    Branch {
+        bbox: Aabb,
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+}
+
+impl Bvh {
+    /// Builds the hierarchy for rays with times in `[time0, time1]`.
+    /// Objects without a bounding box are kept aside and tested for every ray.
+    pub fn new(objects: Vec<Box<dyn Hit>>, time0: f64, time1: f64) -> Self {
+        let mut unbounded = Vec::new();
+        let mut bounded = Vec::with_capacity(objects.len());
+        for (idx, object) in objects.into_iter().enumerate() {
+            match object.bounding_box(time0, time1) {
+                Some(bbox) => bounded.push((bbox, idx, object)),
+                None => unbounded.push((idx, object)),
+            }
+        }
+        let root = if bounded.is_empty() {
+            None
+        } else {
+            Some(BvhNode::build(bounded))
+        };
+        Self { root, unbounded }
+    }
+}
+
+impl BvhNode {
+    fn build(mut objects: Vec<(Aabb, usize, Box<dyn Hit>)>) -> (Self, Aabb) {
+        if objects.len() == 1 {
+            let (bbox, idx, object) = objects.pop().unwrap();
+            return (BvhNode::Leaf(idx, object), bbox);
+        }
+        let centroid = |bbox: &Aabb| 0.5 * (bbox.min() + bbox.max());
+        let first = centroid(&objects[0].0);
+        let centroids = objects
+            .iter()
+            .fold(Aabb::new(first, first), |acc, (bbox, _, _)| {
+                let c = centroid(bbox);
+                acc.surrounding(Aabb::new(c, c))
+            });
+        let extent = centroids.max() - centroids.min();
+        let axis = if extent.x() >= extent.y() && extent.x() >= extent.z() {
+            0
+        } else if extent.y() >= extent.z() {
+            1
+        } else {
+            2
+        };
+        let key = |bbox: &Aabb| {
+            let c = centroid(bbox);
+            [c.x(), c.y(), c.z()][axis]
+        };
+        let mid = objects.len() / 2;
+        objects.select_nth_unstable_by(mid, |a, b| key(&a.0).total_cmp(&key(&b.0)));
+        let right = objects.split_off(mid);
+        let (left, left_box) = BvhNode::build(objects);
+        let (right, right_box) = BvhNode::build(right);
+        let bbox = left_box.surrounding(right_box);
+        let node = BvhNode::Branch {
+            bbox,
+            left: Box::new(left),
+            right: Box::new(right),
+        };
+        (node, bbox)
+    }
+
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        match self {
+            BvhNode::Leaf(idx, object) => object
+                .hit(ray, t_min, t_max)
+                .map(|hit| hit.with_object(*idx)),
+            BvhNode::Branch { bbox, left, right } => {
+                if !bbox.hit(ray, t_min, t_max) {
+                    return None;
+                }
+                let left_hit = left.hit(ray, t_min, t_max);
+                let closest = left_hit.as_ref().map_or(t_max, |hit| hit.t());
+                right.hit(ray, t_min, closest).or(left_hit)
+            }
+        }
+    }
+
+    fn register_lights(&mut self, lights: &mut Vec<Arc<dyn Light>>) {
+        match self {
+            BvhNode::Leaf(_, object) => object.register_lights(lights),
+            BvhNode::Branch { left, right, .. } => {
+                left.register_lights(lights);
+                right.register_lights(lights);
+            }
+        }
+    }
+
+    fn leaves<'a>(&'a self, leaves: &mut Vec<(usize, &'a dyn Hit)>) {
+        match self {
+            BvhNode::Leaf(idx, object) => leaves.push((*idx, &**object)),
+            BvhNode::Branch { left, right, .. } => {
+                left.leaves(leaves);
+                right.leaves(leaves);
+            }
+        }
+    }
+}
+
+impl Hit for Bvh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let bounded = self
+            .root
+            .as_ref()
+            .and_then(|(root, _)| root.hit(ray, t_min, t_max));
+        let mut closest = bounded.as_ref().map_or(t_max, |hit| hit.t());
+        let mut record = bounded;
+        for (idx, object) in &self.unbounded {
+            if let Some(hit) = object.hit(ray, t_min, closest) {
+                closest = hit.t();
+                record = Some(hit.with_object(*idx));
+            }
+        }
+        record
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.root.as_ref().map(|(_, bbox)| *bbox)
+    }
+
+    fn register_lights(&mut self, lights: &mut Vec<Arc<dyn Light>>) {
+        if let Some((root, _)) = self.root.as_mut() {
+            root.register_lights(lights);
+        }
+        for (_, object) in self.unbounded.iter_mut() {
+            object.register_lights(lights);
+        }
+    }
+
+    fn collect_materials<'a>(&'a self, materials: &mut Vec<&'a dyn Scatter>) {
+        // In the order of the list the tree was built from.
+        let mut objects = Vec::new();
+        if let Some((root, _)) = self.root.as_ref() {
+            root.leaves(&mut objects);
+        }
+        objects.extend(self.unbounded.iter().map(|(idx, object)| (*idx, &**object)));
+        objects.sort_by_key(|&(idx, _)| idx);
+        for (_, object) in objects {
+            object.collect_materials(materials);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HitList, Lambertian, Sphere, Vec3};
+
+    #[test]
+    fn test_matches_hit_list() {
+        let material = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        let mut list = HitList::new();
+        let mut objects: Vec<Box<dyn Hit>> = Vec::new();
+        for i in 0..10 {
+            for j in 0..10 {
+                let center = Vec3::new(f64::from(i), f64::from(j), f64::from((i * j) % 3));
+                list.push(Sphere::new(center, 0.4, material.clone()));
+                objects.push(Box::new(Sphere::new(center, 0.4, material.clone())));
+            }
+        }
+        let bvh = Bvh::new(objects, 0.0, 0.0);
+        for k in 0..100 {
+            let s = f64::from(k) / 10.0;
+            let ray = Ray::new(
+                Vec3::new(-1.0, s, -5.0),
+                Vec3::new(1.0, 0.1 * s - 0.5, 1.0),
+                0.0,
+            );
+            let expected = list
+                .hit(&ray, 0.001, f64::MAX)
+                .map(|hit| (hit.t(), hit.object()));
+            let actual = bvh
+                .hit(&ray, 0.001, f64::MAX)
+                .map(|hit| (hit.t(), hit.object()));
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_nan_centroid_does_not_panic() {
+        let material = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        let objects: Vec<Box<dyn Hit>> = (0..4)
+            .map(|i| {
+                let x = if i == 1 { f64::NAN } else { f64::from(i) };
+                Box::new(Sphere::new(Vec3::new(x, 0.0, 0.0), 0.4, material.clone())) as _
+            })
+            .collect();
+        let bvh = Bvh::new(objects, 0.0, 1.0);
+        let ray = Ray::new(Vec3::new(3.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = bvh.hit(&ray, 0.001, f64::MAX).unwrap();
+        assert_eq!(hit.object(), Some(3));
+    }
+
+    #[test]
+    fn test_materials_in_insertion_order() {
+        let materials: Vec<Arc<dyn Scatter>> = (0..8)
+            .map(|i| Arc::new(Lambertian::new(Vec3::new(0.1 * f64::from(i), 0.0, 0.0))) as _)
+            .collect();
+        // Built in reverse spatial order, so the tree order differs.
+        let objects: Vec<Box<dyn Hit>> = materials
+            .iter()
+            .enumerate()
+            .map(|(i, material)| {
+                let center = Vec3::new(-(i as f64), 0.0, 0.0);
+                Box::new(Sphere::new(center, 0.4, material.clone())) as _
+            })
+            .collect();
+        let bvh = Bvh::new(objects, 0.0, 1.0);
+        let mut found = Vec::new();
+        bvh.collect_materials(&mut found);
+        assert_eq!(found.len(), materials.len());
+        for (found, material) in found.iter().zip(&materials) {
+            assert!(std::ptr::eq(
+                *found as *const dyn Scatter as *const (),
+                &**material as *const dyn Scatter as *const ()
+            ));
+        }
+    }
+}
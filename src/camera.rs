@@ -1,26 +1,167 @@
 use crate::{Ray, Vec3};
+use rand::Rng;
 
 pub struct Camera {
     lower_left_corner: Vec3,
     horizontal: Vec3,
     vertical: Vec3,
     origin: Vec3,
+    time0: f64,
+    time1: f64,
 }
 
 impl Camera {
     pub fn new(lower_left_corner: Vec3, horizontal: Vec3, vertical: Vec3, origin: Vec3) -> Self {
+        Self::with_shutter(lower_left_corner, horizontal, vertical, origin, 0.0, 0.0)
+    }
+
+    /// Camera whose shutter is open from `time0` to `time1`; `get_ray` gives
+    /// every ray a random time in it.
+    pub fn with_shutter(
+        lower_left_corner: Vec3,
+        horizontal: Vec3,
+        vertical: Vec3,
+        origin: Vec3,
+        time0: f64,
+        time1: f64,
+    ) -> Self {
         Self {
             lower_left_corner,
             horizontal,
             vertical,
             origin,
+            time0,
+            time1,
+        }
+    }
+
+    pub fn time0(&self) -> f64 {
+        self.time0
+    }
+
+    pub fn time1(&self) -> f64 {
+        self.time1
+    }
+
+    pub fn origin(&self) -> Vec3 {
+        self.origin
+    }
+
+    /// Image coordinates `(u, v)`, as passed to `get_ray`, where the ray from
+    /// the origin through `point` crosses the image, if it does.
+    pub fn project(&self, point: Vec3) -> Option<(f64, f64)> {
+        let n = self.horizontal.cross(self.vertical);
+        let direction = point - self.origin;
+        let denom = n.dot(direction);
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let s = n.dot(self.lower_left_corner - self.origin) / denom;
+        if s <= 0.0 {
+            return None;
+        }
+        let p = self.origin + s * direction - self.lower_left_corner;
+        let area2 = n.squared_length();
+        let u = p.cross(self.vertical).dot(n) / area2;
+        let v = self.horizontal.cross(p).dot(n) / area2;
+        if (0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v) {
+            Some((u, v))
+        } else {
+            None
+        }
+    }
+
+    /// Unit normal of the image plane, pointing away from the origin.
+    pub fn forward(&self) -> Vec3 {
+        let n = self.horizontal.cross(self.vertical).normalize();
+        if n.dot(self.lower_left_corner - self.origin) < 0.0 {
+            -n
+        } else {
+            n
+        }
+    }
+
+    /// Importance emitted along unit `direction`, normalized to integrate to
+    /// one over the image when weighted by the cosine to `forward`; zero for
+    /// directions outside the image.
+    pub fn we(&self, direction: Vec3) -> f64 {
+        let pdf = self.pdf_we(direction);
+        if pdf > 0.0 {
+            pdf / self.forward().dot(direction)
+        } else {
+            0.0
+        }
+    }
+
+    /// Solid angle density of the directions of `get_ray` for uniform image coordinates.
+    pub fn pdf_we(&self, direction: Vec3) -> f64 {
+        let forward = self.forward();
+        let cos = forward.dot(direction);
+        if cos <= 0.0 || self.project(self.origin + direction).is_none() {
+            return 0.0;
         }
+        let distance = forward.dot(self.lower_left_corner - self.origin);
+        let area = self.horizontal.cross(self.vertical).length();
+        distance * distance / (area * cos * cos * cos)
     }
 
     pub fn get_ray(&self, u: f64, v: f64) -> Ray {
+        let time = self.time0 + rand::thread_rng().gen::<f64>() * (self.time1 - self.time0);
+        self.get_ray_at(u, v, time)
+    }
+
+    /// Like `get_ray`, at the given time rather than a random one in the shutter interval.
+    pub fn get_ray_at(&self, u: f64, v: f64, time: f64) -> Ray {
         Ray::new(
             self.origin,
             self.lower_left_corner + u * self.horizontal + v * self.vertical - self.origin,
+            time,
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera::new(
+            Vec3::new(-2.0, -1.0, -1.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::default(),
+        )
+    }
+
+    #[test]
+    fn test_project_inverts_get_ray() {
+        let camera = camera();
+        let ray = camera.get_ray(0.3, 0.8);
+        let (u, v) = camera.project(ray.point_at_parameter(2.5)).unwrap();
+        assert!((u - 0.3).abs() < 1e-12 && (v - 0.8).abs() < 1e-12);
+        assert_eq!(camera.project(Vec3::new(0.0, 0.0, 1.0)), None);
+        assert_eq!(camera.project(Vec3::new(5.0, 0.0, -1.0)), None);
+    }
+
+    #[test]
+    fn test_importance_is_normalized() {
+        // Integrate pdf_we over the image plane, converting area to solid angle.
+        let camera = camera();
+        let n = 200;
+        let mut integral = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let u = (f64::from(i) + 0.5) / f64::from(n);
+                let v = (f64::from(j) + 0.5) / f64::from(n);
+                let d = camera.get_ray(u, v).direction();
+                let direction = d.normalize();
+                let cos = camera.forward().dot(direction);
+                let dw = 8.0 / f64::from(n * n) * cos / d.squared_length();
+                integral += camera.pdf_we(direction) * dw;
+                let we_cos = camera.we(direction) * cos;
+                assert!((we_cos - camera.pdf_we(direction)).abs() < 1e-9);
+            }
+        }
+        assert!((integral - 1.0).abs() < 1e-9, "{}", integral);
+    }
+}
@@ -0,0 +1,309 @@
+use crate::hit::HitRecord;
+use crate::material::Scatter;
+use crate::{Aabb, Bvh, Hit, Ray, Vec3};
+use std::sync::Arc;
+
+const MAX_DEPTH: i32 = 10;
+
+/// Cubic Bézier fiber whose width changes linearly from `width0` to `width1`.
+///
+/// It is intersected as a flat ribbon that always faces the ray, but reports
+/// normals as if it were a cylinder so it shades like a round fiber.
+pub struct Curve {
+    control_points: [Vec3; 4],
+    width0: f64,
+    width1: f64,
+    material: Arc<dyn Scatter>,
+}
+
+impl Curve {
+    pub fn new(
+        control_points: [Vec3; 4],
+        width0: f64,
+        width1: f64,
+        material: Arc<dyn Scatter>,
+    ) -> Self {
+        Self {
+            control_points,
+            width0,
+            width1,
+            material,
+        }
+    }
+
+    pub fn point(&self, u: f64) -> Vec3 {
+        eval_bezier(&self.control_points, u)
+    }
+
+    pub fn tangent(&self, u: f64) -> Vec3 {
+        let [p0, p1, p2, p3] = self.control_points;
+        let a = p1 - p0;
+        let b = p2 - p1;
+        let c = p3 - p2;
+        3.0 * ((1.0 - u) * (1.0 - u) * a + 2.0 * u * (1.0 - u) * b + u * u * c)
+    }
+
+    pub fn width(&self, u: f64) -> f64 {
+        self.width0 + u * (self.width1 - self.width0)
+    }
+
+    fn intersect(
+        &self,
+        cp: &[Vec3; 4],
+        u0: f64,
+        u1: f64,
+        depth: i32,
+        z_min: f64,
+        z_max: f64,
+    ) -> Option<(f64, f64, f64)> {
+        let half_width = 0.5 * self.width0.max(self.width1);
+        let bounds = cp[1..].iter().fold(Aabb::new(cp[0], cp[0]), |acc, &p| {
+            acc.surrounding(Aabb::new(p, p))
+        });
+        if bounds.min().x() - half_width > 0.0
+            || bounds.max().x() + half_width < 0.0
+            || bounds.min().y() - half_width > 0.0
+            || bounds.max().y() + half_width < 0.0
+            || bounds.min().z() - half_width > z_max
+            || bounds.max().z() + half_width < z_min
+        {
+            return None;
+        }
+
+        if depth > 0 {
+            let (left, right) = split_bezier(cp);
+            let u_mid = 0.5 * (u0 + u1);
+            let left_hit = self.intersect(&left, u0, u_mid, depth - 1, z_min, z_max);
+            let z_max = left_hit.map_or(z_max, |(z, _, _)| z);
+            return self
+                .intersect(&right, u_mid, u1, depth - 1, z_min, z_max)
+                .or(left_hit);
+        }
+
+        // Closest point of the segment to the ray, which runs along +z through the origin.
+        let segment = cp[3] - cp[0];
+        let segment_2d = segment.x() * segment.x() + segment.y() * segment.y();
+        let w = if segment_2d > 0.0 {
+            (-(cp[0].x() * segment.x() + cp[0].y() * segment.y()) / segment_2d).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let point = eval_bezier(cp, w);
+        let distance_2d = (point.x() * point.x() + point.y() * point.y()).sqrt();
+        let u = u0 + w * (u1 - u0);
+        let radius = 0.5 * self.width(u);
+        if distance_2d > radius || point.z() < z_min || point.z() > z_max {
+            return None;
+        }
+        // Signed offset across the ribbon in [-1, 1].
+        let side = segment.x() * point.y() - segment.y() * point.x();
+        let v = if side > 0.0 { 1.0 } else { -1.0 } * distance_2d / radius;
+        Some((point.z(), u, v))
+    }
+}
+
+impl Hit for Curve {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let length = ray.direction().length();
+        let dz = ray.direction() / length;
+        let (dx, dy) = dz.orthonormal_basis();
+        let to_ray_space = |p: Vec3| {
+            let d = p - ray.origin();
+            Vec3::new(d.dot(dx), d.dot(dy), d.dot(dz))
+        };
+        let cp = [
+            to_ray_space(self.control_points[0]),
+            to_ray_space(self.control_points[1]),
+            to_ray_space(self.control_points[2]),
+            to_ray_space(self.control_points[3]),
+        ];
+
+        // Subdivision depth needed for the segments to be flat relative to the width.
+        let l0 = (0..2)
+            .map(|i| {
+                let d = cp[i] - 2.0 * cp[i + 1] + cp[i + 2];
+                d.x().abs().max(d.y().abs()).max(d.z().abs())
+            })
+            .fold(0.0, f64::max);
+        let epsilon = self.width0.max(self.width1) * 0.05;
+        let depth = if l0 > 0.0 {
+            let r0 = (std::f64::consts::SQRT_2 * 6.0 * l0 / (8.0 * epsilon)).log2() * 0.5;
+            (r0.round() as i32).clamp(0, MAX_DEPTH)
+        } else {
+            0
+        };
+
+        let (z, u, v) = self.intersect(&cp, 0.0, 1.0, depth, t_min * length, t_max * length)?;
+        let t = z / length;
+        let tangent = self.tangent(u).normalize();
+        // Along the tangent every perpendicular direction faces the ray equally.
+        let across = dz - dz.dot(tangent) * tangent;
+        let facing = if across.squared_length() > 1e-18 {
+            -across.normalize()
+        } else {
+            tangent.orthonormal_basis().0
+        };
+        let binormal = facing.cross(tangent);
+        // Shades as if round, but the hit stays on the ray.
+        let normal = (1.0 - v * v).max(0.0).sqrt() * facing + v * binormal;
+        let point = ray.point_at_parameter(t);
+        Some(
+            HitRecord::with_uv(t, point, normal, (u, 0.5 * (v + 1.0)), &*self.material)
+                .with_tangent(tangent),
+        )
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        let cp = &self.control_points;
+        let half_width = 0.5 * self.width0.max(self.width1);
+        let margin = Vec3::new(half_width, half_width, half_width);
+        let bounds = cp[1..].iter().fold(Aabb::new(cp[0], cp[0]), |acc, &p| {
+            acc.surrounding(Aabb::new(p, p))
+        });
+        Some(Aabb::new(bounds.min() - margin, bounds.max() + margin))
+    }
+
+    fn collect_materials<'a>(&'a self, materials: &mut Vec<&'a dyn Scatter>) {
+        materials.push(&*self.material);
+    }
+}
+
+/// Bulk storage for many fibers sharing one material, e.g. a head of hair or a lawn.
+pub struct CurveSet {
+    curves: Vec<Curve>,
+    material: Arc<dyn Scatter>,
+}
+
+impl CurveSet {
+    pub fn new(material: Arc<dyn Scatter>) -> Self {
+        Self {
+            curves: Vec::new(),
+            material,
+        }
+    }
+
+    pub fn push(&mut self, control_points: [Vec3; 4], width0: f64, width1: f64) {
+        self.curves.push(Curve::new(
+            control_points,
+            width0,
+            width1,
+            self.material.clone(),
+        ));
+    }
+
+    pub fn len(&self) -> usize {
+        self.curves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.curves.is_empty()
+    }
+
+    pub fn into_bvh(self, time0: f64, time1: f64) -> Bvh {
+        let objects = self
+            .curves
+            .into_iter()
+            .map(|curve| Box::new(curve) as Box<dyn Hit>)
+            .collect();
+        Bvh::new(objects, time0, time1)
+    }
+}
+
+fn eval_bezier(cp: &[Vec3; 4], u: f64) -> Vec3 {
+    let s = 1.0 - u;
+    s * s * s * cp[0] + 3.0 * s * s * u * cp[1] + 3.0 * s * u * u * cp[2] + u * u * u * cp[3]
+}
+
+fn split_bezier(cp: &[Vec3; 4]) -> ([Vec3; 4], [Vec3; 4]) {
+    let p01 = 0.5 * (cp[0] + cp[1]);
+    let p12 = 0.5 * (cp[1] + cp[2]);
+    let p23 = 0.5 * (cp[2] + cp[3]);
+    let p012 = 0.5 * (p01 + p12);
+    let p123 = 0.5 * (p12 + p23);
+    let mid = 0.5 * (p012 + p123);
+    ([cp[0], p01, p012, mid], [mid, p123, p23, cp[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+
+    fn make_curve() -> Curve {
+        Curve::new(
+            [
+                Vec3::new(-1.0, 0.0, 0.0),
+                Vec3::new(-0.3, 0.5, 0.0),
+                Vec3::new(0.3, 0.5, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+            ],
+            0.1,
+            0.02,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn test_hit_middle() {
+        let curve = make_curve();
+        let apex = curve.point(0.5);
+        let ray = Ray::new(
+            apex + Vec3::new(0.0, 0.0, 5.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            0.0,
+        );
+        let hit = curve.hit(&ray, 0.001, f64::MAX).unwrap();
+        assert!((hit.t() - 5.0).abs() < 1e-6);
+        assert!((hit.uv().0 - 0.5).abs() < 1e-2);
+        assert!((hit.normal() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-2);
+        assert!(hit.tangent().dot(Vec3::new(1.0, 0.0, 0.0)) > 0.99);
+    }
+
+    #[test]
+    fn test_hit_point_is_on_ray() {
+        let curve = make_curve();
+        // Off the center line, where the round normal tilts sideways.
+        let ray = Ray::new(
+            curve.point(0.5) + Vec3::new(0.0, 0.015, 5.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            0.0,
+        );
+        let hit = curve.hit(&ray, 0.001, f64::MAX).unwrap();
+        assert!(hit.normal().y() > 0.1, "{:?}", hit.normal());
+        assert!((hit.point() - ray.point_at_parameter(hit.t())).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_hit_along_tangent() {
+        let curve = Curve::new(
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -1.0),
+                Vec3::new(0.0, 0.0, -2.0),
+                Vec3::new(0.0, 0.0, -3.0),
+            ],
+            0.1,
+            0.1,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = curve.hit(&ray, 0.001, f64::MAX).unwrap();
+        let normal = hit.normal();
+        assert!((normal.length() - 1.0).abs() < 1e-9, "{:?}", normal);
+        assert!(normal.z().abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_width_tapers() {
+        let curve = make_curve();
+        let direction = Vec3::new(0.0, 0.0, -1.0);
+        let near_start = curve.point(0.05) + Vec3::new(0.0, 0.04, 5.0);
+        let near_end = curve.point(0.95) + Vec3::new(0.0, 0.04, 5.0);
+        assert!(curve
+            .hit(&Ray::new(near_start, direction, 0.0), 0.001, f64::MAX)
+            .is_some());
+        assert!(curve
+            .hit(&Ray::new(near_end, direction, 0.0), 0.001, f64::MAX)
+            .is_none());
+    }
+}
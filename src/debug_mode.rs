@@ -0,0 +1,233 @@
+use crate::material::{cosine_hemisphere, Frame};
+use crate::{Camera, HitRecord, Ray, Scene, Vec3};
+use rand::Rng;
+
+/// Views of the geometry and materials seen from the camera, for finding out
+/// what a scene is doing. Rays that escape the scene are black.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugMode {
+    /// Fraction of the hemisphere around the first hit left unoccluded within
+    /// the given radius.
+    AmbientOcclusion(f64),
+    /// Shading normal at the first hit, mapped from `[-1, 1]` to `[0, 1]`.
+    Normal,
+    /// Distance of the first hit along the camera axis, divided by the given
+    /// distance at which it turns white.
+    Depth(f64),
+    /// Fraction of light reflected and transmitted at the first hit, towards
+    /// the camera, integrated over a fixed set of stratified samples.
+    Albedo,
+    /// Color distinct for every top-level object of the scene; white for
+    /// objects not held in a `HitList` or `Bvh`.
+    ObjectId,
+    /// Color distinct for every material, numbered in the order the scene
+    /// found them; white for materials not part of the scene's world.
+    MaterialId,
+}
+
+/// First hit along `ray`, passing through medium boundaries.
+fn first_hit<'s>(ray: &Ray, scene: &'s Scene) -> Option<HitRecord<'s>> {
+    let mut ray = *ray;
+    loop {
+        let hit = scene.world().hit(&ray, 0.001, f64::MAX)?;
+        if !hit.material().is_interface() {
+            return Some(hit);
+        }
+        ray = Ray::new(hit.point(), ray.direction(), ray.time());
+    }
+}
+
+/// Saturated color of the given hue in `[0, 1)`.
+fn hue_color(hue: f64) -> Vec3 {
+    let h = hue * 6.0;
+    Vec3::new(
+        ((h - 3.0).abs() - 1.0).clamp(0.0, 1.0),
+        (2.0 - (h - 2.0).abs()).clamp(0.0, 1.0),
+        (2.0 - (h - 4.0).abs()).clamp(0.0, 1.0),
+    )
+}
+
+/// Color for an ID; golden ratio steps keep the hues of consecutive IDs apart.
+fn false_color(id: u64) -> Vec3 {
+    hue_color((id as f64 * 0.618_033_988_749_895).fract())
+}
+
+/// Strata for the lobe choice and per direction axis for the directional albedo.
+const ALBEDO_STRATA: (u32, u32) = (4, 8);
+
+/// Reflected and transmitted fraction of light arriving from all directions
+/// towards `wo`, estimated by BSDF sampling at the centers of a fixed grid of
+/// strata, so the same hit always gives the same value.
+fn directional_albedo(hit: &HitRecord, wo: Vec3) -> Vec3 {
+    let material = hit.material();
+    let (lobes, n) = ALBEDO_STRATA;
+    let center = |i: u32, n: u32| (f64::from(i) + 0.5) / f64::from(n);
+    let mut total = Vec3::default();
+    for k in 0..lobes {
+        for i in 0..n * n {
+            let u = (center(i % n, n), center(i / n, n));
+            if let Some(sample) = material.sample(hit, wo, center(k, lobes), u) {
+                if sample.pdf() > 0.0 {
+                    let cos = sample.wi().dot(hit.normal()).abs();
+                    total += sample.value() * (cos / sample.pdf());
+                }
+            }
+        }
+    }
+    total / f64::from(lobes * n * n)
+}
+
+impl DebugMode {
+    /// Value seen along `ray`, which comes from `camera`.
+    pub fn li<R: Rng>(&self, ray: &Ray, camera: &Camera, scene: &Scene, rng: &mut R) -> Vec3 {
+        let hit = match first_hit(ray, scene) {
+            Some(hit) => hit,
+            None => return Vec3::default(),
+        };
+        let wo = -ray.direction().normalize();
+        match *self {
+            DebugMode::AmbientOcclusion(radius) => {
+                let frame = Frame::facing(&hit, wo);
+                let wi = frame.to_world(cosine_hemisphere((rng.gen(), rng.gen())));
+                if scene.unoccluded(hit.point(), wi, radius, ray.time()) {
+                    Vec3::new(1.0, 1.0, 1.0)
+                } else {
+                    Vec3::default()
+                }
+            }
+            DebugMode::Normal => 0.5 * (hit.normal() + Vec3::new(1.0, 1.0, 1.0)),
+            DebugMode::Depth(far) => {
+                let depth = (hit.point() - camera.origin()).dot(camera.forward()) / far;
+                Vec3::new(depth, depth, depth)
+            }
+            DebugMode::Albedo => directional_albedo(&hit, wo),
+            DebugMode::ObjectId => match hit.object() {
+                Some(object) => false_color(object as u64),
+                None => Vec3::new(1.0, 1.0, 1.0),
+            },
+            DebugMode::MaterialId => match scene.material_id(hit.material()) {
+                Some(id) => false_color(id as u64),
+                None => Vec3::new(1.0, 1.0, 1.0),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HitList, Lambertian, Metal, Phong, Sphere};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::sync::Arc;
+
+    fn scene() -> Scene {
+        let red = Arc::new(Lambertian::new(Vec3::new(0.8, 0.2, 0.2)));
+        let mut world = HitList::new();
+        world.push(Sphere::new(Vec3::new(-1.0, 0.0, -2.0), 0.5, red.clone()));
+        world.push(Sphere::new(Vec3::new(1.0, 0.0, -2.0), 0.5, red));
+        world.push(Sphere::new(
+            Vec3::new(0.0, -100.5, -2.0),
+            100.0,
+            Arc::new(Metal::new(Vec3::new(0.5, 0.5, 0.5), 0.0)),
+        ));
+        Scene::new(world)
+    }
+
+    fn camera() -> Camera {
+        Camera::new(
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::default(),
+        )
+    }
+
+    #[test]
+    fn test_first_hit_views() {
+        let (scene, camera) = (scene(), camera());
+        let mut rng = StdRng::seed_from_u64(1);
+        // Straight at the center of the left sphere.
+        let left = Ray::new(Vec3::default(), Vec3::new(-1.0, 0.0, -2.0), 0.0);
+        let sky = Ray::new(Vec3::default(), Vec3::new(0.0, 1.0, 0.0), 0.0);
+        let expected_normal = -left.direction().normalize();
+        let expected_point = Vec3::new(-1.0, 0.0, -2.0) + 0.5 * expected_normal;
+
+        let normal = DebugMode::Normal.li(&left, &camera, &scene, &mut rng);
+        let mapped = 0.5 * (expected_normal + Vec3::new(1.0, 1.0, 1.0));
+        assert!((normal - mapped).length() < 1e-6, "{:?}", normal);
+        let depth = DebugMode::Depth(3.0).li(&left, &camera, &scene, &mut rng);
+        assert!(
+            (depth.x() + expected_point.z() / 3.0).abs() < 1e-6,
+            "{:?}",
+            depth
+        );
+        let albedo = DebugMode::Albedo.li(&left, &camera, &scene, &mut rng);
+        assert!((albedo - Vec3::new(0.8, 0.2, 0.2)).length() < 1e-9);
+
+        for mode in &[DebugMode::Normal, DebugMode::Albedo, DebugMode::ObjectId] {
+            assert_eq!(mode.li(&sky, &camera, &scene, &mut rng), Vec3::default());
+        }
+    }
+
+    #[test]
+    fn test_ids_tell_objects_and_materials_apart() {
+        let (scene, camera) = (scene(), camera());
+        let mut rng = StdRng::seed_from_u64(2);
+        let rays = [
+            Ray::new(Vec3::default(), Vec3::new(-1.0, 0.0, -2.0), 0.0),
+            Ray::new(Vec3::default(), Vec3::new(1.0, 0.0, -2.0), 0.0),
+            Ray::new(Vec3::default(), Vec3::new(0.0, -1.0, -1.0), 0.0),
+        ];
+        let colors = |mode: DebugMode, rng: &mut StdRng| -> Vec<Vec3> {
+            rays.iter()
+                .map(|ray| mode.li(ray, &camera, &scene, rng))
+                .collect()
+        };
+        let objects = colors(DebugMode::ObjectId, &mut rng);
+        assert_ne!(objects[0], objects[1]);
+        assert_ne!(objects[1], objects[2]);
+        assert_ne!(objects[0], objects[2]);
+        let materials = colors(DebugMode::MaterialId, &mut rng);
+        assert_eq!(materials[0], materials[1]);
+        assert_ne!(materials[0], materials[2]);
+        // Numbered in the order the spheres were added, the same on every run.
+        assert_eq!(materials[0], false_color(0));
+        assert_eq!(materials[2], false_color(1));
+    }
+
+    #[test]
+    fn test_albedo_is_deterministic() {
+        let phong = Phong::new(Vec3::new(0.5, 0.3, 0.1), Vec3::new(0.4, 0.4, 0.4), 20.0);
+        let scene = Scene::new(Sphere::new(Vec3::new(0.0, 0.0, -2.0), 0.5, Arc::new(phong)));
+        let camera = camera();
+        let ray = Ray::new(Vec3::default(), Vec3::new(0.1, 0.0, -1.0), 0.0);
+        let mut rng = StdRng::seed_from_u64(4);
+        let albedo = DebugMode::Albedo.li(&ray, &camera, &scene, &mut rng);
+        assert_eq!(
+            DebugMode::Albedo.li(&ray, &camera, &scene, &mut rng),
+            albedo
+        );
+        // Below the diffuse and specular colors put together.
+        assert!(albedo.x() > 0.5 && albedo.x() < 0.9, "{:?}", albedo);
+    }
+
+    #[test]
+    fn test_ambient_occlusion_depends_on_radius() {
+        // Looking at the floor right next to the left sphere.
+        let (scene, camera) = (scene(), camera());
+        let mut rng = StdRng::seed_from_u64(3);
+        let ray = Ray::new(Vec3::new(-1.55, 1.0, -2.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let occlusion = |radius, rng: &mut StdRng| {
+            let mode = DebugMode::AmbientOcclusion(radius);
+            let n = 2000;
+            let total: f64 = (0..n)
+                .map(|_| mode.li(&ray, &camera, &scene, rng).x())
+                .sum();
+            total / f64::from(n)
+        };
+        assert_eq!(occlusion(0.01, &mut rng), 1.0);
+        let near = occlusion(10.0, &mut rng);
+        assert!(near > 0.5 && near < 0.95, "{}", near);
+    }
+}
@@ -0,0 +1,141 @@
+/// Piecewise-constant distribution over `[0, 1)` proportional to `func`.
+pub(crate) struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// Falls back to a uniform distribution when every value is zero.
+    pub fn new(func: Vec<f64>) -> Self {
+        assert!(!func.is_empty());
+        let n = func.len() as f64;
+        let mut cdf = Vec::with_capacity(func.len() + 1);
+        cdf.push(0.0);
+        for value in &func {
+            cdf.push(cdf[cdf.len() - 1] + value.abs() / n);
+        }
+        let integral = cdf[func.len()];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 {
+                *c / integral
+            } else {
+                i as f64 / n
+            };
+        }
+        Self {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Returns the sampled position, its density and the segment it lies in.
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        let offset = self.find_segment(u);
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 {
+            (u - self.cdf[offset]) / width
+        } else {
+            0.0
+        };
+        let x = ((offset as f64 + du) / self.len() as f64).min(1.0 - f64::EPSILON);
+        (x, self.segment_pdf(offset) * self.len() as f64, offset)
+    }
+
+    /// Probability mass of segment `offset`.
+    pub fn segment_pdf(&self, offset: usize) -> f64 {
+        self.cdf[offset + 1] - self.cdf[offset]
+    }
+
+    /// Density at `x` in `[0, 1)`.
+    pub fn pdf(&self, x: f64) -> f64 {
+        let offset = ((x * self.len() as f64) as usize).min(self.len() - 1);
+        self.segment_pdf(offset) * self.len() as f64
+    }
+
+    fn find_segment(&self, u: f64) -> usize {
+        // Last index whose cdf is <= u, skipping zero-width segments.
+        let mut lo = 0;
+        let mut hi = self.len();
+        while lo + 1 < hi {
+            let mid = (lo + hi) / 2;
+            if self.cdf[mid] <= u {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    }
+}
+
+/// Piecewise-constant distribution over `[0, 1)^2` given as rows of values.
+pub(crate) struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `func` holds `height` rows of `width` values each.
+    pub fn new(func: &[f64], width: usize, height: usize) -> Self {
+        assert_eq!(func.len(), width * height);
+        let conditional: Vec<_> = func
+            .chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|d| d.integral()).collect());
+        Self {
+            conditional,
+            marginal,
+        }
+    }
+
+    /// Returns `(x, y)` and the density of the sample.
+    pub fn sample_continuous(&self, (u1, u2): (f64, f64)) -> ((f64, f64), f64) {
+        let (y, pdf_y, row) = self.marginal.sample_continuous(u2);
+        let (x, pdf_x, _) = self.conditional[row].sample_continuous(u1);
+        ((x, y), pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, (x, y): (f64, f64)) -> f64 {
+        let row = ((y * self.marginal.len() as f64) as usize).min(self.marginal.len() - 1);
+        self.marginal.pdf(y) * self.conditional[row].pdf(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distribution_1d() {
+        let distribution = Distribution1D::new(vec![1.0, 0.0, 3.0]);
+        assert_eq!(distribution.integral(), 4.0 / 3.0);
+        let (x, pdf, offset) = distribution.sample_continuous(0.5);
+        assert_eq!(offset, 2);
+        assert!((pdf - 2.25).abs() < 1e-12);
+        assert!((x - (2.0 + 1.0 / 3.0) / 3.0).abs() < 1e-12);
+        assert_eq!(distribution.pdf(0.5), 0.0);
+    }
+
+    #[test]
+    fn test_distribution_2d_pdf_matches_samples() {
+        let func = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let distribution = Distribution2D::new(&func, 3, 2);
+        for &u in &[(0.1, 0.2), (0.7, 0.5), (0.99, 0.9)] {
+            let (point, pdf) = distribution.sample_continuous(u);
+            assert!((distribution.pdf(point) - pdf).abs() < 1e-9);
+        }
+        // Density is the value over the mean value.
+        assert!((distribution.pdf((0.9, 0.9)) - 5.0 / 2.5).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,257 @@
+use crate::distribution::Distribution2D;
+use crate::error::invalid_data;
+use crate::light::{Light, LightSample};
+use crate::material::uniform_sphere;
+use crate::{HdrImage, Vec3};
+use std::f64::consts::PI;
+use std::io::Error;
+use std::sync::Arc;
+
+/// Radiance arriving from infinitely far away, seen by rays that leave the scene.
+pub trait Environment: Send + Sync {
+    /// Radiance arriving from unit `direction`.
+    fn radiance(&self, direction: Vec3) -> Vec3;
+
+    /// Samples a direction towards the environment; the pdf is with respect to solid angle.
+    fn sample(&self, u: (f64, f64)) -> Option<LightSample>;
+
+    fn pdf(&self, direction: Vec3) -> f64;
+}
+
+/// Vertical blend from `bottom` (straight down) to `top` (straight up).
+pub struct GradientEnvironment {
+    bottom: Vec3,
+    top: Vec3,
+}
+
+impl GradientEnvironment {
+    pub fn new(bottom: Vec3, top: Vec3) -> Self {
+        Self { bottom, top }
+    }
+}
+
+impl Default for GradientEnvironment {
+    /// White to light blue sky.
+    fn default() -> Self {
+        Self::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Environment for GradientEnvironment {
+    fn radiance(&self, direction: Vec3) -> Vec3 {
+        let t = 0.5 * (direction.y() + 1.0);
+        (1.0 - t) * self.bottom + t * self.top
+    }
+
+    fn sample(&self, u: (f64, f64)) -> Option<LightSample> {
+        let wi = uniform_sphere(u);
+        Some(LightSample::new(
+            wi,
+            self.radiance(wi),
+            f64::INFINITY,
+            1.0 / (4.0 * PI),
+            false,
+        ))
+    }
+
+    fn pdf(&self, _direction: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+/// Same radiance from every direction.
+pub struct ConstantEnvironment {
+    radiance: Vec3,
+}
+
+impl ConstantEnvironment {
+    pub fn new(radiance: Vec3) -> Self {
+        Self { radiance }
+    }
+}
+
+impl Environment for ConstantEnvironment {
+    fn radiance(&self, _direction: Vec3) -> Vec3 {
+        self.radiance
+    }
+
+    fn sample(&self, u: (f64, f64)) -> Option<LightSample> {
+        Some(LightSample::new(
+            uniform_sphere(u),
+            self.radiance,
+            f64::INFINITY,
+            1.0 / (4.0 * PI),
+            false,
+        ))
+    }
+
+    fn pdf(&self, _direction: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+/// Equirectangular (latitude-longitude) environment image with +y up.
+///
+/// Directions are importance sampled in proportion to texel luminance, so
+/// small bright features such as the sun are found by shadow rays.
+pub struct EnvironmentMap {
+    texels: Vec<Vec3>,
+    width: usize,
+    height: usize,
+    rotation: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    /// `rotation` turns the map around the y axis in degrees; `intensity` scales it.
+    /// Fails for an empty image.
+    pub fn new(image: &HdrImage, rotation: f64, intensity: f64) -> Result<Self, Error> {
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+        if width == 0 || height == 0 {
+            return Err(invalid_data("Environment map image is empty"));
+        }
+        let mut texels = Vec::with_capacity(width * height);
+        for row in 0..image.height() {
+            for col in 0..image.width() {
+                texels.push(image[(col, image.height() - 1 - row)] * intensity);
+            }
+        }
+        // Rows near the poles cover less solid angle.
+        let func: Vec<f64> = texels
+            .iter()
+            .enumerate()
+            .map(|(idx, texel)| {
+                let sin_theta = (PI * ((idx / width) as f64 + 0.5) / height as f64).sin();
+                texel.luminance().max(0.0) * sin_theta
+            })
+            .collect();
+        Ok(Self {
+            distribution: Distribution2D::new(&func, width, height),
+            texels,
+            width,
+            height,
+            rotation: rotation.to_radians(),
+        })
+    }
+
+    /// Image coordinates with v running from the top row.
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let theta = direction.y().clamp(-1.0, 1.0).acos();
+        let phi = (-direction.z()).atan2(direction.x()) + PI - self.rotation;
+        ((phi / (2.0 * PI)).rem_euclid(1.0), theta / PI)
+    }
+
+    fn texel(&self, (u, v): (f64, f64)) -> Vec3 {
+        let col = ((u * self.width as f64) as usize).min(self.width - 1);
+        let row = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.texels[row * self.width + col]
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: Vec3) -> Vec3 {
+        self.texel(self.direction_to_uv(direction))
+    }
+
+    fn sample(&self, u: (f64, f64)) -> Option<LightSample> {
+        let (uv, map_pdf) = self.distribution.sample_continuous(u);
+        let theta = PI * uv.1;
+        let sin_theta = theta.sin();
+        if map_pdf <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+        let phi = 2.0 * PI * uv.0 + self.rotation;
+        let wi = Vec3::new(-sin_theta * phi.cos(), theta.cos(), sin_theta * phi.sin());
+        Some(LightSample::new(
+            wi,
+            self.texel(uv),
+            f64::INFINITY,
+            map_pdf / (2.0 * PI * PI * sin_theta),
+            false,
+        ))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let uv = self.direction_to_uv(direction);
+        let sin_theta = (PI * uv.1).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(uv) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+/// Exposes an environment to direct lighting as one more scene light.
+pub(crate) struct EnvironmentLight(pub Arc<dyn Environment>);
+
+impl Light for EnvironmentLight {
+    fn sample_li(&self, _point: Vec3, u: (f64, f64)) -> Option<LightSample> {
+        self.0.sample(u)
+    }
+
+    fn pdf_li(&self, _point: Vec3, wi: Vec3) -> f64 {
+        self.0.pdf(wi)
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+
+    fn le(&self, direction: Vec3) -> Vec3 {
+        self.0.radiance(direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sun_map() -> HdrImage {
+        let mut image = HdrImage::new(16, 8);
+        for y in 0..8 {
+            for x in 0..16 {
+                image[(x, y)] = Vec3::new(0.1, 0.1, 0.1);
+            }
+        }
+        image[(5, 6)] = Vec3::new(1000.0, 900.0, 800.0);
+        image
+    }
+
+    #[test]
+    fn test_environment_map_rejects_empty_image() {
+        assert!(EnvironmentMap::new(&HdrImage::new(0, 4), 0.0, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_environment_map_sample_matches_pdf_and_radiance() {
+        let map = EnvironmentMap::new(&sun_map(), 30.0, 2.0).unwrap();
+        let mut sun_samples = 0;
+        for i in 0..64 {
+            let u = ((i % 8) as f64 / 8.0 + 0.03, (i / 8) as f64 / 8.0 + 0.05);
+            let sample = map.sample(u).unwrap();
+            assert!((sample.wi().length() - 1.0).abs() < 1e-9);
+            assert!((map.pdf(sample.wi()) - sample.pdf()).abs() < 1e-6 * sample.pdf());
+            assert_eq!(map.radiance(sample.wi()), sample.radiance());
+            if sample.radiance().x() > 1.0 {
+                sun_samples += 1;
+            }
+        }
+        assert!(sun_samples > 48, "{}", sun_samples);
+    }
+
+    #[test]
+    fn test_environment_map_pdf_integrates_to_one() {
+        let map = EnvironmentMap::new(&sun_map(), 0.0, 1.0).unwrap();
+        let n = 400;
+        let mut integral = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let w = uniform_sphere(((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64));
+                integral += map.pdf(w);
+            }
+        }
+        integral *= 4.0 * PI / f64::from(n * n);
+        assert!((integral - 1.0).abs() < 0.02, "{}", integral);
+    }
+}
@@ -0,0 +1,6 @@
+use std::io::{Error, ErrorKind};
+
+/// Error for malformed input files.
+pub(crate) fn invalid_data(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
@@ -0,0 +1,268 @@
+use crate::HdrImage;
+use std::io::{Error, Write};
+
+/// Arbitrary output variable: a quantity written alongside the radiance of
+/// each camera sample, for compositing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aov {
+    /// Distance of the first hit along the camera axis.
+    Depth,
+    /// Shading normal at the first hit.
+    Normal,
+    /// Scattering weight of the first bounce, the albedo for most materials.
+    Albedo,
+    /// World space position of the first hit.
+    Position,
+    /// Index of the first hit top-level object plus one, zero where nothing
+    /// was hit.
+    ObjectId,
+    /// Radiance of paths leaving the first hit through a diffuse lobe.
+    Diffuse,
+    /// Radiance of paths leaving the first hit through any other lobe.
+    Specular,
+    /// Radiance emitted at the first hit, or of the background.
+    Emission,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 8] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::Position,
+        Aov::ObjectId,
+        Aov::Diffuse,
+        Aov::Specular,
+        Aov::Emission,
+    ];
+
+    /// Name of the framebuffer layer holding the variable.
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+            Aov::Diffuse => "diffuse",
+            Aov::Specular => "specular",
+            Aov::Emission => "emission",
+        }
+    }
+}
+
+/// Named image layers of the same size.
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    layers: Vec<(String, HdrImage)>,
+}
+
+impl Framebuffer {
+    /// Name of the layer holding the rendered radiance.
+    pub const BEAUTY: &'static str = "beauty";
+
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            layers: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Layer called `name`, added as a black image if there is none yet.
+    pub fn add_layer(&mut self, name: &str) -> &mut HdrImage {
+        let idx = match self.layers.iter().position(|(other, _)| other == name) {
+            Some(idx) => idx,
+            None => {
+                let image = HdrImage::new(self.width, self.height);
+                self.layers.push((name.to_string(), image));
+                self.layers.len() - 1
+            }
+        };
+        &mut self.layers[idx].1
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&HdrImage> {
+        self.layers
+            .iter()
+            .find(|(other, _)| other == name)
+            .map(|(_, image)| image)
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut HdrImage> {
+        self.layers
+            .iter_mut()
+            .find(|(other, _)| other == name)
+            .map(|(_, image)| image)
+    }
+
+    /// Names and images of the layers, in the order they were added.
+    pub fn layers(&self) -> impl Iterator<Item = (&str, &HdrImage)> {
+        self.layers
+            .iter()
+            .map(|(name, image)| (name.as_str(), image))
+    }
+
+    pub fn into_layer(self, name: &str) -> Option<HdrImage> {
+        self.layers
+            .into_iter()
+            .find(|(other, _)| other == name)
+            .map(|(_, image)| image)
+    }
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/// Writes every layer into one uncompressed scanline OpenEXR file with 32-bit
+/// float channels. The beauty layer becomes the default `R`, `G` and `B`
+/// channels, other layers `<name>.R`, `<name>.G` and `<name>.B`.
+pub fn write_exr<W: Write>(framebuffer: &Framebuffer, output: &mut W) -> Result<(), Error> {
+    let (width, height) = (framebuffer.width, framebuffer.height);
+    // Channels are stored sorted by name.
+    let mut channels: Vec<(String, &HdrImage, usize)> = Vec::new();
+    for (name, image) in framebuffer.layers() {
+        for (component, suffix) in ["R", "G", "B"].iter().enumerate() {
+            let channel = if name == Framebuffer::BEAUTY {
+                suffix.to_string()
+            } else {
+                format!("{}.{}", name, suffix)
+            };
+            channels.push((channel, image, component));
+        }
+    }
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut header = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+    let mut list = Vec::new();
+    for (name, _, _) in &channels {
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+        // FLOAT pixels, not perceptually linear, no subsampling.
+        list.extend_from_slice(&2i32.to_le_bytes());
+        list.extend_from_slice(&[0, 0, 0, 0]);
+        list.extend_from_slice(&1i32.to_le_bytes());
+        list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    list.push(0);
+    attribute(&mut header, "channels", "chlist", &list);
+    attribute(&mut header, "compression", "compression", &[0]);
+    let mut window = Vec::new();
+    for value in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    // One scanline per block, top row first.
+    let line_size = 8 + channels.len() * width as usize * 4;
+    let first_line = header.len() + height as usize * 8;
+    let mut data = header;
+    for line in 0..height as usize {
+        data.extend_from_slice(&((first_line + line * line_size) as u64).to_le_bytes());
+    }
+    for line in 0..height {
+        data.extend_from_slice(&(line as i32).to_le_bytes());
+        data.extend_from_slice(&((line_size - 8) as u32).to_le_bytes());
+        let y = height - 1 - line;
+        for (_, image, component) in &channels {
+            for x in 0..width {
+                let color = image[(x, y)];
+                let value = [color.x(), color.y(), color.z()][*component];
+                data.extend_from_slice(&(value as f32).to_le_bytes());
+            }
+        }
+    }
+    output.write_all(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vec3;
+
+    #[test]
+    fn test_layers_by_name() {
+        let mut framebuffer = Framebuffer::new(2, 2);
+        framebuffer.add_layer(Framebuffer::BEAUTY)[(0, 0)] = Vec3::new(1.0, 2.0, 3.0);
+        framebuffer.add_layer(Aov::Depth.name());
+        framebuffer.add_layer(Framebuffer::BEAUTY)[(1, 1)] = Vec3::new(4.0, 5.0, 6.0);
+        let names: Vec<_> = framebuffer.layers().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["beauty", "depth"]);
+        let beauty = framebuffer.layer(Framebuffer::BEAUTY).unwrap();
+        assert_eq!(beauty[(0, 0)], Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(beauty[(1, 1)], Vec3::new(4.0, 5.0, 6.0));
+        assert!(framebuffer.layer("missing").is_none());
+    }
+
+    #[test]
+    fn test_write_exr_layout() {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.add_layer(Framebuffer::BEAUTY)[(2, 1)] = Vec3::new(0.5, 0.25, 0.125);
+        framebuffer.add_layer(Aov::Normal.name())[(0, 0)] = Vec3::new(0.0, 1.0, 0.0);
+        let mut data = Vec::new();
+        write_exr(&framebuffer, &mut data).unwrap();
+
+        assert_eq!(&data[..4], &[0x76, 0x2f, 0x31, 0x01]);
+        let header = String::from_utf8_lossy(&data);
+        assert!(header.contains("normal.G\0") && header.contains("dataWindow\0box2i"));
+
+        // Header, offsets, then per line: y, size and 6 channels of 3 floats.
+        let line_size = 8 + 6 * 3 * 4;
+        let offset = |line: usize| {
+            let start = data.len() - 2 * line_size - 16 + 8 * line;
+            u64::from_le_bytes([
+                data[start],
+                data[start + 1],
+                data[start + 2],
+                data[start + 3],
+                data[start + 4],
+                data[start + 5],
+                data[start + 6],
+                data[start + 7],
+            ]) as usize
+        };
+        assert_eq!(offset(0), data.len() - 2 * line_size);
+        assert_eq!(offset(1), data.len() - line_size);
+        let float =
+            |at: usize| f32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+        // Channels sort as B, G, R, normal.B, normal.G, normal.R; the top
+        // line holds the beauty pixel, the bottom line the normal.
+        let top = offset(0) + 8;
+        assert_eq!(float(top + 2 * 4), 0.125);
+        assert_eq!(float(top + 3 * 4 + 2 * 4), 0.25);
+        assert_eq!(float(top + 6 * 4 + 2 * 4), 0.5);
+        let bottom = offset(1) + 8;
+        assert_eq!(float(bottom + 12 * 4), 1.0);
+    }
+}
@@ -0,0 +1,596 @@
+use crate::error::invalid_data;
+use crate::hit::HitRecord;
+use crate::medium::{average, HenyeyGreenstein, Medium, MediumSample, BOUNDARY};
+use crate::{Aabb, ConstantMedium, Hit, Ray, Vec3};
+use rand::{Rng, RngCore};
+use std::io::{Error, Read};
+use std::sync::Arc;
+
+/// Cells of the majorant grid along each axis, at most.
+const MAJORANT_RESOLUTION: usize = 16;
+
+/// Dense grid of densities at voxel centers, stored with x varying fastest,
+/// then y, then z, spanning the unit cube.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxelGrid {
+    resolution: [usize; 3],
+    values: Vec<f64>,
+}
+
+impl VoxelGrid {
+    pub fn new(resolution: [usize; 3], values: Vec<f64>) -> Self {
+        assert_eq!(
+            resolution.iter().product::<usize>(),
+            values.len(),
+            "voxel count does not match the resolution"
+        );
+        assert!(!values.is_empty(), "voxel grid is empty");
+        Self { resolution, values }
+    }
+
+    pub fn resolution(&self) -> [usize; 3] {
+        self.resolution
+    }
+
+    /// Value of the voxel at `index`, clamped to the grid.
+    fn voxel(&self, index: [isize; 3]) -> f64 {
+        let [nx, ny, _] = self.resolution;
+        let clamp = |i: isize, axis: usize| i.clamp(0, self.resolution[axis] as isize - 1) as usize;
+        let (x, y, z) = (clamp(index[0], 0), clamp(index[1], 1), clamp(index[2], 2));
+        self.values[(z * ny + y) * nx + x]
+    }
+
+    /// Trilinearly interpolated density at `p` in the unit cube, zero outside it.
+    pub fn density(&self, p: Vec3) -> f64 {
+        let p = [p.x(), p.y(), p.z()];
+        if p.iter().any(|c| !(0.0..=1.0).contains(c)) {
+            return 0.0;
+        }
+        let mut base = [0; 3];
+        let mut frac = [0.0; 3];
+        for axis in 0..3 {
+            let g = p[axis] * self.resolution[axis] as f64 - 0.5;
+            base[axis] = g.floor() as isize;
+            frac[axis] = g - g.floor();
+        }
+        let mut density = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut index = base;
+            for axis in 0..3 {
+                if corner >> axis & 1 == 1 {
+                    index[axis] += 1;
+                    weight *= frac[axis];
+                } else {
+                    weight *= 1.0 - frac[axis];
+                }
+            }
+            density += weight * self.voxel(index);
+        }
+        density
+    }
+
+    /// Bound on the interpolated density within the box from `lo` to `hi`.
+    fn max_density(&self, lo: [f64; 3], hi: [f64; 3]) -> f64 {
+        let mut range = [(0, 0); 3];
+        for axis in 0..3 {
+            let n = self.resolution[axis] as f64;
+            let first = (lo[axis] * n - 0.5).floor() as isize;
+            let last = (hi[axis] * n - 0.5).floor() as isize + 1;
+            range[axis] = (first, last);
+        }
+        let mut max = 0.0f64;
+        for z in range[2].0..=range[2].1 {
+            for y in range[1].0..=range[1].1 {
+                for x in range[0].0..=range[0].1 {
+                    max = max.max(self.voxel([x, y, z]));
+                }
+            }
+        }
+        max
+    }
+}
+
+/// Reads a voxel grid from a minimal raw volume file.
+///
+/// The file starts with the ASCII header `VOXELS <nx> <ny> <nz>` and a single
+/// newline, followed by `nx * ny * nz` little-endian `f32` densities with x
+/// varying fastest.
+pub fn read_voxel_grid<R: Read>(input: &mut R) -> Result<VoxelGrid, Error> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    let header_end = data
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or_else(|| invalid_data("Truncated voxel header"))?;
+    let header = String::from_utf8_lossy(&data[..header_end]);
+    let mut tokens = header.split_whitespace();
+    if tokens.next() != Some("VOXELS") {
+        return Err(invalid_data("Unknown voxel magic number"));
+    }
+    let mut resolution = [0; 3];
+    for n in resolution.iter_mut() {
+        *n = tokens
+            .next()
+            .and_then(|token| token.parse().ok())
+            .filter(|&n| n > 0)
+            .ok_or_else(|| invalid_data("Invalid voxel grid dimension"))?;
+    }
+    let n_bytes = resolution
+        .iter()
+        .try_fold(4usize, |acc, &n| acc.checked_mul(n))
+        .ok_or_else(|| invalid_data("Voxel grid too large"))?;
+    let body = data
+        .get(header_end + 1..(header_end + 1).saturating_add(n_bytes))
+        .ok_or_else(|| invalid_data("Truncated voxel data"))?;
+    let values = body
+        .chunks(4)
+        .map(|c| f64::from(f32::from_le_bytes([c[0], c[1], c[2], c[3]])))
+        .collect::<Vec<_>>();
+    if values
+        .iter()
+        .any(|&density| density.is_nan() || density < 0.0)
+    {
+        return Err(invalid_data("Voxel densities must be non-negative"));
+    }
+    Ok(VoxelGrid::new(resolution, values))
+}
+
+/// Medium whose coefficients are scaled by a voxel grid stretched over `bounds`,
+/// for clouds, smoke and explosions.
+///
+/// Free flights are sampled by delta tracking and transmittance is estimated
+/// by ratio tracking, both against a coarse grid of density maxima so that
+/// empty and thin regions are skipped quickly.
+pub struct GridMedium {
+    grid: VoxelGrid,
+    bounds: Aabb,
+    sigma_a: Vec3,
+    sigma_s: Vec3,
+    phase: HenyeyGreenstein,
+    majorant_resolution: [usize; 3],
+    majorants: Vec<f64>,
+}
+
+impl GridMedium {
+    /// Coefficients are per unit length at density one.
+    pub fn new(grid: VoxelGrid, bounds: Aabb, sigma_a: Vec3, sigma_s: Vec3, g: f64) -> Self {
+        let mut majorant_resolution = [0; 3];
+        for (axis, n) in majorant_resolution.iter_mut().enumerate() {
+            *n = grid.resolution[axis].min(MAJORANT_RESOLUTION);
+        }
+        let [mx, my, mz] = majorant_resolution;
+        let mut majorants = Vec::with_capacity(mx * my * mz);
+        for z in 0..mz {
+            for y in 0..my {
+                for x in 0..mx {
+                    let cell = [x, y, z];
+                    let mut lo = [0.0; 3];
+                    let mut hi = [0.0; 3];
+                    for axis in 0..3 {
+                        let n = majorant_resolution[axis] as f64;
+                        lo[axis] = cell[axis] as f64 / n;
+                        hi[axis] = (cell[axis] + 1) as f64 / n;
+                    }
+                    majorants.push(grid.max_density(lo, hi));
+                }
+            }
+        }
+        Self {
+            grid,
+            bounds,
+            sigma_a,
+            sigma_s,
+            phase: HenyeyGreenstein::new(g),
+            majorant_resolution,
+            majorants,
+        }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    /// Volume filling the grid bounds with this medium, behind an invisible box.
+    pub fn into_volume(self) -> ConstantMedium {
+        ConstantMedium::new(GridBounds(self.bounds), Arc::new(self))
+    }
+
+    fn density(&self, point: Vec3) -> f64 {
+        let (min, max) = (self.bounds.min(), self.bounds.max());
+        let size = max - min;
+        let local = point - min;
+        self.grid.density(Vec3::new(
+            local.x() / size.x(),
+            local.y() / size.y(),
+            local.z() / size.z(),
+        ))
+    }
+
+    fn max_sigma_t(&self) -> f64 {
+        let sigma_t = self.sigma_a + self.sigma_s;
+        sigma_t.x().max(sigma_t.y()).max(sigma_t.z())
+    }
+
+    /// Calls `collision(t, majorant)` at tentative collisions along `ray` before
+    /// `t_max`, sampled against the majorant grid, until it returns false.
+    ///
+    /// The majorant bounds the extinction of every channel per unit length.
+    fn track(
+        &self,
+        ray: &Ray,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+        mut collision: impl FnMut(f64, f64) -> bool,
+    ) {
+        let (t_enter, t_exit) = match self.bounds.clip(ray, 0.0, t_max) {
+            Some(range) => range,
+            None => return,
+        };
+        let (min, size) = (self.bounds.min(), self.bounds.max() - self.bounds.min());
+        let origin = ray.origin() - min;
+        let origin = [origin.x(), origin.y(), origin.z()];
+        let direction = [
+            ray.direction().x(),
+            ray.direction().y(),
+            ray.direction().z(),
+        ];
+        let size = [size.x(), size.y(), size.z()];
+        let scale = self.max_sigma_t() * ray.direction().length();
+
+        // Digital differential analyzer over the majorant cells.
+        let mut cell = [0isize; 3];
+        let mut next_crossing = [f64::INFINITY; 3];
+        let mut delta = [f64::INFINITY; 3];
+        let mut step = [0isize; 3];
+        for axis in 0..3 {
+            let n = self.majorant_resolution[axis];
+            let cells_per_t = direction[axis] / size[axis] * n as f64;
+            let g = (origin[axis] + t_enter * direction[axis]) / size[axis] * n as f64;
+            let index = (g.floor() as isize).clamp(0, n as isize - 1);
+            cell[axis] = index;
+            if cells_per_t > 0.0 {
+                step[axis] = 1;
+                delta[axis] = 1.0 / cells_per_t;
+                next_crossing[axis] = t_enter + (index as f64 + 1.0 - g) / cells_per_t;
+            } else if cells_per_t < 0.0 {
+                step[axis] = -1;
+                delta[axis] = -1.0 / cells_per_t;
+                next_crossing[axis] = t_enter + (index as f64 - g) / cells_per_t;
+            }
+        }
+
+        let [mx, my, _] = self.majorant_resolution;
+        let mut t = t_enter;
+        loop {
+            let axis = (0..3)
+                .min_by(|&a, &b| next_crossing[a].total_cmp(&next_crossing[b]))
+                .unwrap_or(0);
+            let cell_exit = next_crossing[axis].min(t_exit);
+            let index = (cell[2] as usize * my + cell[1] as usize) * mx + cell[0] as usize;
+            let majorant = self.majorants[index] * self.max_sigma_t();
+            let rate = self.majorants[index] * scale;
+            if rate > 0.0 {
+                loop {
+                    t -= (1.0 - rng.gen::<f64>()).ln() / rate;
+                    if t >= cell_exit {
+                        break;
+                    }
+                    if !collision(t, majorant) {
+                        return;
+                    }
+                }
+            }
+            if cell_exit >= t_exit {
+                return;
+            }
+            t = cell_exit;
+            cell[axis] += step[axis];
+            if cell[axis] < 0 || cell[axis] >= self.majorant_resolution[axis] as isize {
+                return;
+            }
+            next_crossing[axis] += delta[axis];
+        }
+    }
+}
+
+fn divide(a: Vec3, b: f64) -> Vec3 {
+    if b > 0.0 {
+        a / b
+    } else {
+        Vec3::default()
+    }
+}
+
+impl Medium for GridMedium {
+    fn transmittance(&self, ray: &Ray, t_max: f64, rng: &mut dyn RngCore) -> Vec3 {
+        // Ratio tracking: every tentative collision keeps the null fraction.
+        let one = Vec3::new(1.0, 1.0, 1.0);
+        let mut transmittance = one;
+        self.track(ray, t_max, rng, |t, majorant| {
+            let sigma_t = self.density(ray.point_at_parameter(t)) * (self.sigma_a + self.sigma_s);
+            transmittance *= one - sigma_t / majorant;
+            transmittance != Vec3::default()
+        });
+        transmittance
+    }
+
+    fn sample(&self, ray: &Ray, t_max: f64, rng: &mut dyn RngCore) -> MediumSample {
+        // Delta tracking with absorption, scattering and null collisions chosen
+        // by their average over the channels, weighted per channel.
+        let mut weight = Vec3::new(1.0, 1.0, 1.0);
+        let mut scattered = None;
+        let mut event = rng.gen::<f64>();
+        self.track(ray, t_max, rng, |t, majorant| {
+            let density = self.density(ray.point_at_parameter(t));
+            let sigma_a = density * self.sigma_a;
+            let sigma_s = density * self.sigma_s;
+            let sigma_n = Vec3::new(majorant, majorant, majorant) - sigma_a - sigma_s;
+            let p_absorb = average(sigma_a) / majorant;
+            let p_scatter = average(sigma_s) / majorant;
+            if event < p_absorb {
+                weight = Vec3::default();
+                return false;
+            }
+            if event < p_absorb + p_scatter {
+                weight *= divide(sigma_s, average(sigma_s));
+                scattered = Some(t);
+                return false;
+            }
+            weight *= divide(sigma_n, average(sigma_n));
+            // Reuse the event number, rescaled to the null interval.
+            event = (event - p_absorb - p_scatter) / (1.0 - p_absorb - p_scatter);
+            true
+        });
+        MediumSample::new(scattered, weight)
+    }
+
+    fn phase(&self) -> HenyeyGreenstein {
+        self.phase
+    }
+}
+
+/// Invisible axis-aligned box enclosing a grid medium.
+struct GridBounds(Aabb);
+
+impl Hit for GridBounds {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t_near, t_far) = self.0.clip(ray, f64::MIN, f64::MAX)?;
+        let t = if t_near > t_min && t_near < t_max {
+            t_near
+        } else if t_far > t_min && t_far < t_max {
+            t_far
+        } else {
+            return None;
+        };
+        let point = ray.point_at_parameter(t);
+        let (min, max) = (self.0.min(), self.0.max());
+        let center = 0.5 * (min + max);
+        let half = 0.5 * (max - min);
+        let offset = point - center;
+        let scaled = [
+            offset.x() / half.x(),
+            offset.y() / half.y(),
+            offset.z() / half.z(),
+        ];
+        let axis = (0..3)
+            .max_by(|&a, &b| scaled[a].abs().total_cmp(&scaled[b].abs()))
+            .unwrap_or(0);
+        let mut normal = [0.0; 3];
+        normal[axis] = scaled[axis].signum();
+        let normal = Vec3::new(normal[0], normal[1], normal[2]);
+        Some(HitRecord::new(t, point, normal, &BOUNDARY))
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConstantEnvironment, LightSampling, PathTracer, Scene};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn encode(resolution: [usize; 3], values: &[f32]) -> Vec<u8> {
+        let mut data = format!(
+            "VOXELS {} {} {}\n",
+            resolution[0], resolution[1], resolution[2]
+        )
+        .into_bytes();
+        for value in values {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data
+    }
+
+    fn unit_box() -> Aabb {
+        Aabb::new(Vec3::default(), Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn test_read_voxel_grid() {
+        let data = encode([2, 1, 2], &[0.0, 1.0, 2.0, 3.0]);
+        let grid = read_voxel_grid(&mut data.as_slice()).unwrap();
+        assert_eq!(grid.resolution(), [2, 1, 2]);
+        assert_eq!(grid.voxel([1, 0, 1]), 3.0);
+        assert!(read_voxel_grid(&mut &data[..data.len() - 1]).is_err());
+        assert!(read_voxel_grid(&mut "VOXELS 2 0 1\n".as_bytes()).is_err());
+        assert!(read_voxel_grid(&mut "PF 1 1 1\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_reject_invalid_voxel_data() {
+        let huge = "VOXELS 4294967296 4294967296 2\n".as_bytes();
+        let err = read_voxel_grid(&mut &huge[..]).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        for density in &[-1.0, f32::NAN] {
+            let data = encode([2, 1, 1], &[0.5, *density]);
+            let err = read_voxel_grid(&mut data.as_slice()).err().unwrap();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_trilinear_density() {
+        let grid = VoxelGrid::new([2, 1, 1], vec![0.0, 1.0]);
+        assert_eq!(grid.density(Vec3::new(0.1, 0.5, 0.5)), 0.0);
+        assert!((grid.density(Vec3::new(0.5, 0.2, 0.9)) - 0.5).abs() < 1e-12);
+        assert!((grid.density(Vec3::new(0.625, 0.5, 0.5)) - 0.75).abs() < 1e-12);
+        assert_eq!(grid.density(Vec3::new(1.1, 0.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn test_majorants_bound_density() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let values = (0..40 * 20 * 20)
+            .map(|_| rng.gen::<f64>().powi(4))
+            .collect();
+        let medium = GridMedium::new(
+            VoxelGrid::new([40, 20, 20], values),
+            unit_box(),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::default(),
+            0.0,
+        );
+        let [mx, my, mz] = medium.majorant_resolution;
+        assert_eq!([mx, my, mz], [16, 16, 16]);
+        for _ in 0..10_000 {
+            let p = Vec3::new(rng.gen(), rng.gen(), rng.gen());
+            let cell = [
+                (p.x() * mx as f64) as usize,
+                (p.y() * my as f64) as usize,
+                (p.z() * mz as f64) as usize,
+            ];
+            let majorant = medium.majorants[(cell[2] * my + cell[1]) * mx + cell[0]];
+            assert!(medium.density(p) <= majorant + 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_constant_grid_matches_homogeneous() {
+        let medium = GridMedium::new(
+            VoxelGrid::new([3, 3, 3], vec![2.0; 27]),
+            Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0)),
+            Vec3::new(0.1, 0.2, 0.4),
+            Vec3::new(0.3, 0.2, 0.1),
+            0.0,
+        );
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -3.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let mut rng = StdRng::seed_from_u64(2);
+        let n = 20_000;
+        let mut transmittance = Vec3::default();
+        for _ in 0..n {
+            transmittance += medium.transmittance(&ray, 10.0, &mut rng);
+        }
+        transmittance /= f64::from(n);
+        let expected = Vec3::new((-1.6f64).exp(), (-1.6f64).exp(), (-2.0f64).exp());
+        assert!(
+            (transmittance - expected).length() < 0.01,
+            "{:?} {:?}",
+            transmittance,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_tracking_estimators_agree() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let values = (0..8 * 8 * 8).map(|_| 4.0 * rng.gen::<f64>()).collect();
+        let medium = GridMedium::new(
+            VoxelGrid::new([8, 8, 8], values),
+            unit_box(),
+            Vec3::new(0.2, 0.4, 0.6),
+            Vec3::new(0.6, 0.3, 0.1),
+            0.0,
+        );
+        let ray = Ray::new(Vec3::new(-0.5, 0.3, 0.4), Vec3::new(2.0, 0.4, 0.2), 0.0);
+        let n = 50_000;
+        let mut ratio = Vec3::default();
+        let mut delta = Vec3::default();
+        let mut scattered = Vec3::default();
+        for _ in 0..n {
+            ratio += medium.transmittance(&ray, 1.0, &mut rng);
+            let sample = medium.sample(&ray, 1.0, &mut rng);
+            match sample.t() {
+                None => delta += sample.weight(),
+                Some(_) => scattered += sample.weight(),
+            }
+        }
+        ratio /= f64::from(n);
+        delta /= f64::from(n);
+        scattered /= f64::from(n);
+        // Optical depth by quadrature along the ray.
+        let steps = 10_000;
+        let mut depth = 0.0;
+        for i in 0..steps {
+            let t = (f64::from(i) + 0.5) / f64::from(steps);
+            depth += medium.density(ray.point_at_parameter(t)) * ray.direction().length();
+        }
+        depth /= f64::from(steps);
+        let sigma_t = Vec3::new(0.8, 0.7, 0.7) * depth;
+        let expected = Vec3::new(
+            (-sigma_t.x()).exp(),
+            (-sigma_t.y()).exp(),
+            (-sigma_t.z()).exp(),
+        );
+        assert!(
+            (ratio - expected).length() < 0.01,
+            "{:?} {:?}",
+            ratio,
+            expected
+        );
+        assert!(
+            (delta - expected).length() < 0.01,
+            "{:?} {:?}",
+            delta,
+            expected
+        );
+        assert!(scattered.x() > 0.0);
+    }
+
+    #[test]
+    fn test_grid_volume_white_furnace() {
+        // A non-absorbing cloud under uniform light neither darkens nor brightens.
+        let mut rng = StdRng::seed_from_u64(4);
+        let values = (0..6 * 6 * 6).map(|_| 3.0 * rng.gen::<f64>()).collect();
+        let medium = GridMedium::new(
+            VoxelGrid::new([6, 6, 6], values),
+            Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0)),
+            Vec3::default(),
+            Vec3::new(1.0, 1.0, 1.0),
+            0.5,
+        );
+        let scene = Scene::with_environment(
+            medium.into_volume(),
+            Arc::new(ConstantEnvironment::new(Vec3::new(1.0, 1.0, 1.0))),
+        );
+        let tracer = PathTracer::new(100, LightSampling::Mis);
+        let ray = Ray::new(Vec3::new(0.1, 0.2, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let n = 10_000;
+        let mut radiance = Vec3::default();
+        for _ in 0..n {
+            radiance += tracer.li(&ray, &scene, &mut rng);
+        }
+        radiance /= f64::from(n);
+        assert!(
+            (radiance - Vec3::new(1.0, 1.0, 1.0)).length() < 0.03,
+            "{:?}",
+            radiance
+        );
+    }
+
+    #[test]
+    fn test_grid_bounds_normals_point_outwards() {
+        let volume = GridBounds(unit_box());
+        let ray = Ray::new(Vec3::new(0.5, 0.5, -1.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let enter = volume.hit(&ray, 0.001, f64::MAX).unwrap();
+        assert!((enter.t() - 1.0).abs() < 1e-9);
+        assert_eq!(enter.normal(), Vec3::new(0.0, 0.0, -1.0));
+        let exit = volume.hit(&ray, 1.001, f64::MAX).unwrap();
+        assert_eq!(exit.normal(), Vec3::new(0.0, 0.0, 1.0));
+        assert!(exit.material().is_interface());
+    }
+}
@@ -0,0 +1,336 @@
+use crate::error::invalid_data;
+use crate::Vec3;
+use std::io::{Error, Read, Write};
+use std::ops::{Index, IndexMut};
+
+type Point = (u32, u32);
+
+/// Image with linear floating point RGB pixels, indexed bottom-up like `Image`.
+pub struct HdrImage {
+    buffer: Box<[Vec3]>,
+    width: u32,
+    height: u32,
+}
+
+impl HdrImage {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            buffer: vec![Vec3::default(); (width * height) as usize].into_boxed_slice(),
+            width,
+            height,
+        }
+    }
+
+    fn from_rows(width: u32, height: u32, rows_top_down: Vec<Vec3>) -> Self {
+        let mut image = Self::new(width, height);
+        for (idx, color) in rows_top_down.into_iter().enumerate() {
+            let x = idx as u32 % width;
+            let y = height - 1 - idx as u32 / width;
+            image[(x, y)] = color;
+        }
+        image
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn point_to_index(&self, (x, y): Point) -> usize {
+        assert!(x < self.width && y < self.height);
+        (y * self.width + x) as usize
+    }
+}
+
+impl Index<Point> for HdrImage {
+    type Output = Vec3;
+
+    fn index(&self, point: Point) -> &Self::Output {
+        &self.buffer[self.point_to_index(point)]
+    }
+}
+
+impl IndexMut<Point> for HdrImage {
+    fn index_mut(&mut self, point: Point) -> &mut Self::Output {
+        &mut self.buffer[self.point_to_index(point)]
+    }
+}
+
+/// Reads a Radiance RGBE (`.hdr`) image, flat or run-length encoded.
+pub fn read_hdr<R: Read>(input: &mut R) -> Result<HdrImage, Error> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    let mut pos = 0;
+    let magic = next_line(&data, &mut pos)?;
+    if !magic.starts_with("#?") {
+        return Err(invalid_data("Missing Radiance header"));
+    }
+    loop {
+        let line = next_line(&data, &mut pos)?;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid_data("Unsupported Radiance pixel format"));
+        }
+    }
+    let resolution = next_line(&data, &mut pos)?;
+    let fields: Vec<_> = resolution.split_whitespace().collect();
+    let (height, width, bottom_up) = match fields.as_slice() {
+        ["-Y", height, "+X", width] => (parse_number(height)?, parse_number(width)?, false),
+        ["+Y", height, "+X", width] => (parse_number(height)?, parse_number(width)?, true),
+        _ => return Err(invalid_data("Unsupported Radiance image orientation")),
+    };
+
+    let pixel_count = width
+        .checked_mul(height)
+        .ok_or_else(|| invalid_data("Radiance image too large"))? as usize;
+    // Run-length encoding lets a short file claim many pixels.
+    let mut pixels = Vec::with_capacity(pixel_count.min(data.len()));
+    let mut scanline = vec![[0u8; 4]; width as usize];
+    for _ in 0..height {
+        read_scanline(&data, &mut pos, &mut scanline)?;
+        pixels.extend(scanline.iter().map(|&rgbe| rgbe_to_vec3(rgbe)));
+    }
+    if bottom_up {
+        let rows: Vec<_> = pixels
+            .chunks(width as usize)
+            .rev()
+            .flatten()
+            .copied()
+            .collect();
+        pixels = rows;
+    }
+    Ok(HdrImage::from_rows(width, height, pixels))
+}
+
+/// Reads a color (`PF`) or grayscale (`Pf`) portable float map.
+pub fn read_pfm<R: Read>(input: &mut R) -> Result<HdrImage, Error> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    let mut pos = 0;
+    let mut token = || -> Result<String, Error> {
+        while data.get(pos).is_some_and(u8::is_ascii_whitespace) {
+            pos += 1;
+        }
+        let start = pos;
+        while data.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(invalid_data("Truncated PFM header"));
+        }
+        Ok(String::from_utf8_lossy(&data[start..pos]).into_owned())
+    };
+    let channels = match token()?.as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid_data("Unknown PFM magic number")),
+    };
+    let width = parse_number(&token()?)?;
+    let height = parse_number(&token()?)?;
+    let scale: f32 = token()?
+        .parse()
+        .map_err(|_| invalid_data("Invalid PFM scale"))?;
+    let body_start = pos + 1;
+    let n_bytes = width
+        .checked_mul(height)
+        .and_then(|pixels| (pixels as usize).checked_mul(channels * 4))
+        .ok_or_else(|| invalid_data("PFM image too large"))?;
+    let body = data
+        .get(body_start..body_start.saturating_add(n_bytes))
+        .ok_or_else(|| invalid_data("Truncated PFM data"))?;
+    let values: Vec<f64> = body
+        .chunks(4)
+        .map(|c| {
+            let bytes = [c[0], c[1], c[2], c[3]];
+            let value = if scale < 0.0 {
+                f32::from_le_bytes(bytes)
+            } else {
+                f32::from_be_bytes(bytes)
+            };
+            f64::from(value)
+        })
+        .collect();
+
+    // Rows are stored bottom-up, matching the image layout.
+    let mut image = HdrImage::new(width, height);
+    for (idx, c) in values.chunks(channels).enumerate() {
+        let point = (idx as u32 % width, idx as u32 / width);
+        image[point] = match c {
+            [gray] => Vec3::new(*gray, *gray, *gray),
+            [r, g, b] => Vec3::new(*r, *g, *b),
+            _ => unreachable!(),
+        };
+    }
+    Ok(image)
+}
+
+/// Writes a little-endian color portable float map.
+pub fn write_pfm<W: Write>(image: &HdrImage, output: &mut W) -> Result<(), Error> {
+    write!(output, "PF\n{} {}\n-1.0\n", image.width, image.height)?;
+    let mut body = Vec::with_capacity(image.buffer.len() * 12);
+    for color in image.buffer.iter() {
+        for value in &[color.x(), color.y(), color.z()] {
+            body.extend_from_slice(&(*value as f32).to_le_bytes());
+        }
+    }
+    output.write_all(&body)
+}
+
+fn read_scanline(data: &[u8], pos: &mut usize, scanline: &mut [[u8; 4]]) -> Result<(), Error> {
+    let width = scanline.len();
+    let header = data
+        .get(*pos..*pos + 4)
+        .ok_or_else(|| invalid_data("Truncated Radiance data"))?;
+    let rle = (8..0x8000).contains(&width)
+        && header[0] == 2
+        && header[1] == 2
+        && usize::from(header[2]) << 8 | usize::from(header[3]) == width;
+    if !rle {
+        for pixel in scanline.iter_mut() {
+            let bytes = data
+                .get(*pos..*pos + 4)
+                .ok_or_else(|| invalid_data("Truncated Radiance data"))?;
+            pixel.copy_from_slice(bytes);
+            *pos += 4;
+        }
+        return Ok(());
+    }
+
+    *pos += 4;
+    let mut byte = || -> Result<u8, Error> {
+        let value = *data
+            .get(*pos)
+            .ok_or_else(|| invalid_data("Truncated Radiance data"))?;
+        *pos += 1;
+        Ok(value)
+    };
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = usize::from(byte()?);
+            let (count, run) = if count > 128 {
+                (count - 128, true)
+            } else {
+                (count, false)
+            };
+            if count == 0 || x + count > width {
+                return Err(invalid_data("Invalid Radiance run length"));
+            }
+            let value = if run { byte()? } else { 0 };
+            for pixel in &mut scanline[x..x + count] {
+                pixel[channel] = if run { value } else { byte()? };
+            }
+            x += count;
+        }
+    }
+    Ok(())
+}
+
+fn rgbe_to_vec3([r, g, b, e]: [u8; 4]) -> Vec3 {
+    if e == 0 {
+        return Vec3::default();
+    }
+    let f = 2f64.powi(i32::from(e) - 136);
+    Vec3::new(
+        (f64::from(r) + 0.5) * f,
+        (f64::from(g) + 0.5) * f,
+        (f64::from(b) + 0.5) * f,
+    )
+}
+
+fn next_line<'a>(data: &'a [u8], pos: &mut usize) -> Result<&'a str, Error> {
+    let start = *pos;
+    let end = data[start..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map(|len| start + len)
+        .ok_or_else(|| invalid_data("Truncated Radiance header"))?;
+    *pos = end + 1;
+    std::str::from_utf8(&data[start..end])
+        .map(str::trim_end)
+        .map_err(|_| invalid_data("Invalid Radiance header"))
+}
+
+fn parse_number(token: &str) -> Result<u32, Error> {
+    token
+        .parse()
+        .map_err(|_| invalid_data("Invalid image dimension"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_read_hdr_flat() {
+        let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 1\n".to_vec();
+        data.extend_from_slice(&[128, 64, 0, 129, 0, 0, 0, 0]);
+        let image = read_hdr(&mut data.as_slice()).unwrap();
+        assert_eq!((image.width(), image.height()), (1, 2));
+        // Top row first in the file, stored at the highest y.
+        assert_eq!(
+            image[(0, 1)],
+            Vec3::new(1.0 + 0.5 / 128.0, 0.5 + 0.5 / 128.0, 0.5 / 128.0)
+        );
+        assert_eq!(image[(0, 0)], Vec3::default());
+    }
+
+    #[test]
+    fn test_read_hdr_rle() {
+        let mut data = b"#?RGBE\n\n-Y 1 +X 8\n".to_vec();
+        data.extend_from_slice(&[2, 2, 0, 8]);
+        // Red: a run of eight; green: eight literals; blue: two runs; exponent: one run.
+        data.extend_from_slice(&[136, 255]);
+        data.extend_from_slice(&[8, 0, 1, 2, 3, 4, 5, 6, 7]);
+        data.extend_from_slice(&[132, 10, 132, 20]);
+        data.extend_from_slice(&[136, 136]);
+        let image = read_hdr(&mut data.as_slice()).unwrap();
+        assert_eq!(image[(3, 0)], Vec3::new(255.5, 3.5, 10.5));
+        assert_eq!(image[(7, 0)], Vec3::new(255.5, 7.5, 20.5));
+    }
+
+    #[test]
+    fn test_read_pfm() {
+        let mut data = b"PF\n2 1\n-1.0\n".to_vec();
+        for value in &[1.0f32, 2.0, 3.0, 0.25, 0.5, 0.75] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let image = read_pfm(&mut data.as_slice()).unwrap();
+        assert_eq!(image[(0, 0)], Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(image[(1, 0)], Vec3::new(0.25, 0.5, 0.75));
+
+        let mut gray = b"Pf 1 1 1.0\n".to_vec();
+        gray.extend_from_slice(&2.5f32.to_be_bytes());
+        let image = read_pfm(&mut gray.as_slice()).unwrap();
+        assert_eq!(image[(0, 0)], Vec3::new(2.5, 2.5, 2.5));
+    }
+
+    #[test]
+    fn test_write_pfm_round_trips() {
+        let mut image = HdrImage::new(2, 3);
+        image[(1, 0)] = Vec3::new(0.5, 1.5, 2.5);
+        image[(0, 2)] = Vec3::new(-1.0, 0.0, 1e3);
+        let mut data = Vec::new();
+        write_pfm(&image, &mut data).unwrap();
+        let read = read_pfm(&mut data.as_slice()).unwrap();
+        assert_eq!((read.width(), read.height()), (2, 3));
+        assert_eq!(read[(1, 0)], image[(1, 0)]);
+        assert_eq!(read[(0, 2)], image[(0, 2)]);
+    }
+
+    #[test]
+    fn test_reject_oversized_header() {
+        let hdr = b"#?RADIANCE\n\n-Y 65536 +X 65536\n".to_vec();
+        let err = read_hdr(&mut hdr.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let pfm = b"PF\n65536 65536\n-1.0\n".to_vec();
+        let err = read_pfm(&mut pfm.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
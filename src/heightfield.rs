@@ -0,0 +1,338 @@
+use crate::error::invalid_data;
+use crate::hit::HitRecord;
+use crate::material::Scatter;
+use crate::triangle::intersect_triangle;
+use crate::{Aabb, Hit, Image, Ray, Vec3};
+use std::io::Error;
+use std::sync::Arc;
+
+/// Terrain given by a regular grid of height samples.
+///
+/// Sample `(i, j)` lies at `(i / (nx - 1), height, j / (nz - 1))` scaled by `scale`,
+/// so the terrain covers `[0, scale.x] x [0, scale.z]` in object space.
+/// Rays are traversed through a min/max mipmap of the grid cells.
+pub struct Heightfield {
+    nx: usize,
+    nz: usize,
+    heights: Vec<f64>,
+    normals: Vec<Vec3>,
+    scale: Vec3,
+    levels: Vec<MinMaxLevel>,
+    material: Arc<dyn Scatter>,
+}
+
+struct MinMaxLevel {
+    nx: usize,
+    nz: usize,
+    bounds: Vec<(f64, f64)>,
+}
+
+impl MinMaxLevel {
+    fn get(&self, i: usize, j: usize) -> (f64, f64) {
+        self.bounds[j * self.nx + i]
+    }
+
+    fn reduce(&self) -> Self {
+        let nx = self.nx.div_ceil(2);
+        let nz = self.nz.div_ceil(2);
+        let mut bounds = Vec::with_capacity(nx * nz);
+        for j in 0..nz {
+            for i in 0..nx {
+                let mut bound = (f64::MAX, f64::MIN);
+                for (ci, cj) in children(i, j, self.nx, self.nz) {
+                    let (min, max) = self.get(ci, cj);
+                    bound = (bound.0.min(min), bound.1.max(max));
+                }
+                bounds.push(bound);
+            }
+        }
+        Self { nx, nz, bounds }
+    }
+}
+
+fn children(i: usize, j: usize, nx: usize, nz: usize) -> impl Iterator<Item = (usize, usize)> {
+    let xs = 2 * i..(2 * i + 2).min(nx);
+    let zs = 2 * j..(2 * j + 2).min(nz);
+    zs.flat_map(move |cj| xs.clone().map(move |ci| (ci, cj)))
+}
+
+impl Heightfield {
+    pub fn new(
+        nx: usize,
+        nz: usize,
+        heights: Vec<f64>,
+        scale: Vec3,
+        material: Arc<dyn Scatter>,
+    ) -> Self {
+        assert!(nx >= 2 && nz >= 2, "Heightfield needs at least 2x2 samples");
+        assert_eq!(heights.len(), nx * nz);
+        let mut field = Self {
+            nx,
+            nz,
+            heights,
+            normals: Vec::new(),
+            scale,
+            levels: Vec::new(),
+            material,
+        };
+        field.normals = (0..nx * nz)
+            .map(|idx| field.vertex_normal(idx % nx, idx / nx))
+            .collect();
+        field.levels = field.build_levels();
+        field
+    }
+
+    /// Heights are taken from the image luminance in `[0, 1]`; the bottom image row is `z = 0`.
+    /// Fails for images smaller than 2x2 pixels.
+    pub fn from_image(
+        image: &Image,
+        scale: Vec3,
+        material: Arc<dyn Scatter>,
+    ) -> Result<Self, Error> {
+        let (nx, nz) = (image.width() as usize, image.height() as usize);
+        if nx < 2 || nz < 2 {
+            return Err(invalid_data("Heightfield image needs at least 2x2 pixels"));
+        }
+        let heights = (0..nx * nz)
+            .map(|idx| {
+                let color = image[((idx % nx) as u32, (idx / nx) as u32)];
+                let luminance = 0.2126 * f64::from(color.red())
+                    + 0.7152 * f64::from(color.green())
+                    + 0.0722 * f64::from(color.blue());
+                luminance / 255.0
+            })
+            .collect();
+        Ok(Self::new(nx, nz, heights, scale, material))
+    }
+
+    fn height(&self, i: usize, j: usize) -> f64 {
+        self.heights[j * self.nx + i]
+    }
+
+    fn cell_size(&self) -> (f64, f64) {
+        (
+            self.scale.x() / (self.nx - 1) as f64,
+            self.scale.z() / (self.nz - 1) as f64,
+        )
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Vec3 {
+        let (dx, dz) = self.cell_size();
+        Vec3::new(
+            i as f64 * dx,
+            self.height(i, j) * self.scale.y(),
+            j as f64 * dz,
+        )
+    }
+
+    fn vertex_normal(&self, i: usize, j: usize) -> Vec3 {
+        let (dx, dz) = self.cell_size();
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.nx - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.nz - 1));
+        let slope_x =
+            (self.height(i1, j) - self.height(i0, j)) * self.scale.y() / ((i1 - i0) as f64 * dx);
+        let slope_z =
+            (self.height(i, j1) - self.height(i, j0)) * self.scale.y() / ((j1 - j0) as f64 * dz);
+        Vec3::new(-slope_x, 1.0, -slope_z).normalize()
+    }
+
+    fn build_levels(&self) -> Vec<MinMaxLevel> {
+        let (nx, nz) = (self.nx - 1, self.nz - 1);
+        let mut bounds = Vec::with_capacity(nx * nz);
+        for j in 0..nz {
+            for i in 0..nx {
+                let corners = [
+                    self.height(i, j),
+                    self.height(i + 1, j),
+                    self.height(i, j + 1),
+                    self.height(i + 1, j + 1),
+                ];
+                let min = corners.iter().cloned().fold(f64::MAX, f64::min);
+                let max = corners.iter().cloned().fold(f64::MIN, f64::max);
+                bounds.push((min, max));
+            }
+        }
+        let mut levels = vec![MinMaxLevel { nx, nz, bounds }];
+        while levels.last().is_some_and(|l| l.nx > 1 || l.nz > 1) {
+            let next = levels.last().unwrap().reduce();
+            levels.push(next);
+        }
+        levels
+    }
+
+    fn node_box(&self, level: usize, i: usize, j: usize) -> Aabb {
+        // Flat regions would produce boxes without thickness, which rays could slip past.
+        const PADDING: f64 = 1e-9;
+        let (dx, dz) = self.cell_size();
+        let cells = 1 << level;
+        let (min, max) = self.levels[level].get(i, j);
+        let x1 = ((i + 1) * cells).min(self.nx - 1);
+        let z1 = ((j + 1) * cells).min(self.nz - 1);
+        // Negative scales mirror the corners.
+        let a = Vec3::new(
+            (i * cells) as f64 * dx,
+            min * self.scale.y(),
+            (j * cells) as f64 * dz,
+        );
+        let b = Vec3::new(x1 as f64 * dx, max * self.scale.y(), z1 as f64 * dz);
+        let padding = Vec3::new(0.0, PADDING, 0.0);
+        Aabb::new(a.min(b) - padding, a.max(b) + padding)
+    }
+
+    fn hit_cell(&self, ray: &Ray, i: usize, j: usize, t_min: f64, t_max: f64) -> Option<CellHit> {
+        let corners = [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)];
+        let mut best: Option<CellHit> = None;
+        for &[a, b, c] in &[[0, 1, 2], [0, 2, 3]] {
+            let (ia, ib, ic) = (corners[a], corners[b], corners[c]);
+            let t_max = best.as_ref().map_or(t_max, |hit| hit.t);
+            let v0 = self.vertex(ia.0, ia.1);
+            let v1 = self.vertex(ib.0, ib.1);
+            let v2 = self.vertex(ic.0, ic.1);
+            if let Some((t, b1, b2)) = intersect_triangle(ray, v0, v1, v2, t_min, t_max) {
+                let b0 = 1.0 - b1 - b2;
+                let normal = b0 * self.normals[ia.1 * self.nx + ia.0]
+                    + b1 * self.normals[ib.1 * self.nx + ib.0]
+                    + b2 * self.normals[ic.1 * self.nx + ic.0];
+                let gx = b0 * ia.0 as f64 + b1 * ib.0 as f64 + b2 * ic.0 as f64;
+                let gz = b0 * ia.1 as f64 + b1 * ib.1 as f64 + b2 * ic.1 as f64;
+                best = Some(CellHit {
+                    t,
+                    normal: normal.normalize(),
+                    uv: (gx / (self.nx - 1) as f64, gz / (self.nz - 1) as f64),
+                });
+            }
+        }
+        best
+    }
+}
+
+/// Intersection found in a cell, before it is turned into a `HitRecord`.
+struct CellHit {
+    t: f64,
+    normal: Vec3,
+    uv: (f64, f64),
+}
+
+impl Hit for Heightfield {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let top = self.levels.len() - 1;
+        let mut closest = t_max;
+        let mut best = None;
+        let mut stack = vec![(top, 0, 0, t_min)];
+        while let Some((level, i, j, t_enter)) = stack.pop() {
+            if t_enter >= closest {
+                continue;
+            }
+            if level == 0 {
+                if let Some(hit) = self.hit_cell(ray, i, j, t_min, closest) {
+                    closest = hit.t;
+                    best = Some(hit);
+                }
+                continue;
+            }
+            let below = &self.levels[level - 1];
+            let mut entered: Vec<_> = children(i, j, below.nx, below.nz)
+                .filter_map(|(ci, cj)| {
+                    let (t0, _) = self.node_box(level - 1, ci, cj).clip(ray, t_min, closest)?;
+                    Some((level - 1, ci, cj, t0))
+                })
+                .collect();
+            entered.sort_by(|a, b| b.3.partial_cmp(&a.3).unwrap());
+            stack.extend(entered);
+        }
+        let hit = best?;
+        Some(HitRecord::with_uv(
+            hit.t,
+            ray.point_at_parameter(hit.t),
+            hit.normal,
+            hit.uv,
+            &*self.material,
+        ))
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(self.node_box(self.levels.len() - 1, 0, 0))
+    }
+
+    fn collect_materials<'a>(&'a self, materials: &mut Vec<&'a dyn Scatter>) {
+        materials.push(&*self.material);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+
+    fn make_field(heights: Vec<f64>, n: usize) -> Heightfield {
+        Heightfield::new(
+            n,
+            n,
+            heights,
+            Vec3::new(1.0, 1.0, 1.0),
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn test_flat_hit() {
+        let field = make_field(vec![0.5; 25], 5);
+        let ray = Ray::new(Vec3::new(0.3, 2.0, 0.7), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let hit = field.hit(&ray, 0.001, f64::MAX).unwrap();
+        assert!((hit.t() - 1.5).abs() < 1e-12);
+        assert!((hit.normal() - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+        let (u, v) = hit.uv();
+        assert!((u - 0.3).abs() < 1e-12 && (v - 0.7).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_hit_matches_brute_force() {
+        let n = 9;
+        let heights: Vec<f64> = (0..n * n)
+            .map(|idx| (((idx % n) as f64 * 0.7).sin() + ((idx / n) as f64 * 1.3).cos()) * 0.25)
+            .collect();
+        let field = make_field(heights, n);
+        for k in 0..50 {
+            let s = f64::from(k) / 50.0;
+            let ray = Ray::new(
+                Vec3::new(-0.5, 1.0, s),
+                Vec3::new(1.0, -0.6 + 0.5 * s, 0.1),
+                0.0,
+            );
+            let expected = (0..n - 1)
+                .flat_map(|j| (0..n - 1).map(move |i| (i, j)))
+                .filter_map(|(i, j)| field.hit_cell(&ray, i, j, 0.001, f64::MAX))
+                .map(|hit| hit.t)
+                .fold(None, |acc: Option<f64>, t| {
+                    Some(acc.map_or(t, |a| a.min(t)))
+                });
+            let actual = field.hit(&ray, 0.001, f64::MAX).map(|hit| hit.t());
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_negative_height_scale() {
+        let field = Heightfield::new(
+            3,
+            3,
+            (0..9).map(|idx| f64::from(idx) * 0.1).collect(),
+            Vec3::new(1.0, -2.0, 1.0),
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let bounds = field.bounding_box(0.0, 0.0).unwrap();
+        assert!(bounds.min().y() < -1.6 && bounds.max().y() >= 0.0);
+        // Straight up at the center sample, of height 0.4.
+        let ray = Ray::new(Vec3::new(0.5, -3.0, 0.5), Vec3::new(0.0, 1.0, 0.0), 0.0);
+        let hit = field.hit(&ray, 0.001, f64::MAX).unwrap();
+        assert!((hit.t() - 2.2).abs() < 1e-9, "{}", hit.t());
+    }
+
+    #[test]
+    fn test_from_image_needs_two_pixels() {
+        let material = Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+        let scale = Vec3::new(1.0, 1.0, 1.0);
+        assert!(Heightfield::from_image(&Image::new(1, 4), scale, material.clone()).is_err());
+        assert!(Heightfield::from_image(&Image::new(2, 2), scale, material).is_ok());
+    }
+}
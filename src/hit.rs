@@ -1,23 +1,57 @@
+use crate::light::Light;
 use crate::material::Scatter;
-use crate::{Ray, Vec3};
+use crate::medium::Medium;
+use crate::{Aabb, Bvh, Ray, Vec3};
+use std::sync::Arc;
 
+#[derive(Clone, Copy)]
 pub struct HitRecord<'a> {
     t: f64,
     point: Vec3,
     normal: Vec3,
+    uv: (f64, f64),
+    tangent: Vec3,
+    color: Option<Vec3>,
+    light: Option<usize>,
+    object: Option<usize>,
+    medium: Option<&'a dyn Medium>,
+    wavelength: Option<f64>,
     material: &'a dyn Scatter,
 }
 
 impl<'a> HitRecord<'a> {
     pub fn new(t: f64, point: Vec3, normal: Vec3, material: &'a dyn Scatter) -> Self {
+        Self::with_uv(t, point, normal, (0.0, 0.0), material)
+    }
+
+    pub fn with_uv(
+        t: f64,
+        point: Vec3,
+        normal: Vec3,
+        uv: (f64, f64),
+        material: &'a dyn Scatter,
+    ) -> Self {
         Self {
             t,
             point,
             normal,
+            uv,
+            tangent: Vec3::default(),
+            color: None,
+            light: None,
+            object: None,
+            medium: None,
+            wavelength: None,
             material,
         }
     }
 
+    /// Attaches the surface tangent along increasing `u`.
+    pub fn with_tangent(mut self, tangent: Vec3) -> Self {
+        self.tangent = tangent;
+        self
+    }
+
     pub fn t(&self) -> f64 {
         self.t
     }
@@ -30,13 +64,97 @@ impl<'a> HitRecord<'a> {
         self.normal
     }
 
-    pub fn material(&self) -> &dyn Scatter {
+    /// Surface parametrization at the hit point, both coordinates in `[0, 1]`.
+    pub fn uv(&self) -> (f64, f64) {
+        self.uv
+    }
+
+    /// Attaches a color interpolated from per-vertex data.
+    pub fn with_color(mut self, color: Vec3) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    /// Marks the hit object as the light with the given index in the scene.
+    pub fn with_light(mut self, light: usize) -> Self {
+        self.light = Some(light);
+        self
+    }
+
+    /// Sets the index of the hit object within the list or `Bvh` holding it;
+    /// the outermost container overrides the indices set inside its objects.
+    pub fn with_object(mut self, object: usize) -> Self {
+        self.object = Some(object);
+        self
+    }
+
+    /// Marks the surface as the boundary of `medium`, which lies on the side
+    /// opposite to the normal.
+    pub fn with_medium(mut self, medium: &'a dyn Medium) -> Self {
+        self.medium = Some(medium);
+        self
+    }
+
+    /// Sets the wavelength in nanometers that dispersive materials scatter
+    /// at, when rendering spectrally.
+    pub fn with_wavelength(mut self, wavelength: f64) -> Self {
+        self.wavelength = Some(wavelength);
+        self
+    }
+
+    pub(crate) fn with_material(mut self, material: &'a dyn Scatter) -> Self {
+        self.material = material;
+        self
+    }
+
+    /// Direction of increasing `u`, or zero if the surface does not provide one.
+    pub fn tangent(&self) -> Vec3 {
+        self.tangent
+    }
+
+    /// Per-vertex color at the hit point; materials multiply their albedo by it.
+    pub fn color(&self) -> Option<Vec3> {
+        self.color
+    }
+
+    /// Index of the scene light the hit object is registered as, if any.
+    pub fn light(&self) -> Option<usize> {
+        self.light
+    }
+
+    /// Index of the hit object among the top-level objects of the scene, if
+    /// it is held by a `HitList` or `Bvh`.
+    pub fn object(&self) -> Option<usize> {
+        self.object
+    }
+
+    /// Medium enclosed by the hit surface, if it bounds one.
+    pub fn medium(&self) -> Option<&'a dyn Medium> {
+        self.medium
+    }
+
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
+    pub fn material(&self) -> &'a dyn Scatter {
         self.material
     }
 }
 
 pub trait Hit: Send + Sync {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>>;
+
+    /// Box enclosing the object over the whole `[time0, time1]` interval,
+    /// or `None` for unbounded objects.
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb>;
+
+    /// Appends lights for the emissive parts of the object and remembers
+    /// their indices so that hits on them can be attributed to the light.
+    fn register_lights(&mut self, _lights: &mut Vec<Arc<dyn Light>>) {}
+
+    /// Appends the materials of the object in the order its parts were added.
+    fn collect_materials<'a>(&'a self, _materials: &mut Vec<&'a dyn Scatter>) {}
 }
 
 #[derive(Default)]
@@ -52,18 +170,53 @@ impl HitList {
     pub fn push<T: Hit + 'static>(&mut self, value: T) {
         self.data.push(Box::new(value))
     }
+
+    pub fn push_boxed(&mut self, value: Box<dyn Hit>) {
+        self.data.push(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Moves the objects into a `Bvh` built for rays with times in `[time0, time1]`.
+    pub fn into_bvh(self, time0: f64, time1: f64) -> Bvh {
+        Bvh::new(self.data, time0, time1)
+    }
 }
 
 impl Hit for HitList {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let mut record = None;
         let mut closest_so_far = t_max;
-        for elem in self.data.iter() {
+        for (idx, elem) in self.data.iter().enumerate() {
             if let Some(temp_record) = elem.hit(ray, t_min, closest_so_far) {
                 closest_so_far = temp_record.t;
-                record = Some(temp_record);
+                record = Some(temp_record.with_object(idx));
             }
         }
         record
     }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let mut boxes = self.data.iter().map(|elem| elem.bounding_box(time0, time1));
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, elem| Some(acc.surrounding(elem?)))
+    }
+
+    fn register_lights(&mut self, lights: &mut Vec<Arc<dyn Light>>) {
+        for elem in self.data.iter_mut() {
+            elem.register_lights(lights);
+        }
+    }
+
+    fn collect_materials<'a>(&'a self, materials: &mut Vec<&'a dyn Scatter>) {
+        for elem in self.data.iter() {
+            elem.collect_materials(materials);
+        }
+    }
 }
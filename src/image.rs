@@ -1,7 +1,8 @@
+use crate::error::invalid_data;
 use crate::Color;
 use rayon::iter::IntoParallelRefMutIterator;
 use rayon::slice::IterMut;
-use std::io::{Error, Write};
+use std::io::{Error, Read, Write};
 use std::ops::{Index, IndexMut};
 
 type Point = (u32, u32);
@@ -25,6 +26,16 @@ impl Image {
         }
     }
 
+    fn from_rows(width: u32, height: u32, rows_top_down: Vec<Color>) -> Self {
+        let mut image = Self::new(width, height);
+        for (idx, color) in rows_top_down.into_iter().enumerate() {
+            let x = idx as u32 % width;
+            let y = height - 1 - idx as u32 / width;
+            image[(x, y)] = color;
+        }
+        image
+    }
+
     pub fn width(&self) -> u32 {
         self.width
     }
@@ -33,7 +44,7 @@ impl Image {
         self.height
     }
 
-    pub fn pixels(&mut self) -> IterMut<Color> {
+    pub fn pixels(&mut self) -> IterMut<'_, Color> {
         self.buffer.par_iter_mut()
     }
 
@@ -71,3 +82,136 @@ pub fn write_ppm<W: Write>(image: Image, output: &mut W) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Reads a binary or plain PPM (`P3`/`P6`) or PGM (`P2`/`P5`) image.
+pub fn read_ppm<R: Read>(input: &mut R) -> Result<Image, Error> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    let mut pos = 0;
+    let magic = next_token(&data, &mut pos)?;
+    let width = parse_number(next_token(&data, &mut pos)?)?;
+    let height = parse_number(next_token(&data, &mut pos)?)?;
+    let max_value = parse_number(next_token(&data, &mut pos)?)?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid_data("Unsupported PPM maximum value"));
+    }
+    let (channels, binary) = match magic {
+        b"P2" => (1, false),
+        b"P3" => (3, false),
+        b"P5" => (1, true),
+        b"P6" => (3, true),
+        _ => return Err(invalid_data("Unknown PPM magic number")),
+    };
+    let n_values = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(channels))
+        .ok_or_else(|| invalid_data("PPM image too large"))?;
+    let values: Vec<u32> = if binary {
+        pos += 1;
+        let bytes_per_value = if max_value > 255 { 2 } else { 1 };
+        let body = n_values
+            .checked_mul(bytes_per_value)
+            .and_then(|len| data.get(pos..pos.checked_add(len)?))
+            .ok_or_else(|| invalid_data("Truncated PPM data"))?;
+        body.chunks(bytes_per_value)
+            .map(|chunk| chunk.iter().fold(0, |acc, &b| (acc << 8) | u32::from(b)))
+            .collect()
+    } else {
+        (0..n_values)
+            .map(|_| parse_number(next_token(&data, &mut pos)?))
+            .collect::<Result<_, _>>()?
+    };
+    let to_u8 = |value: u32| (value.min(max_value) * 255 / max_value) as u8;
+    let colors = values
+        .chunks(channels)
+        .map(|c| match c {
+            [gray] => Color::new(to_u8(*gray), to_u8(*gray), to_u8(*gray)),
+            [r, g, b] => Color::new(to_u8(*r), to_u8(*g), to_u8(*b)),
+            _ => unreachable!(),
+        })
+        .collect();
+    Ok(Image::from_rows(width, height, colors))
+}
+
+/// Reads an 8 or 16 bit grayscale or RGB(A) PNG image; alpha is dropped.
+pub fn read_png<R: Read>(input: &mut R) -> Result<Image, Error> {
+    let mut decoder = png::Decoder::new(input);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|err| invalid_data(&err.to_string()))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|err| invalid_data(&err.to_string()))?;
+    let channels = info.color_type.samples();
+    let colors = buffer[..info.buffer_size()]
+        .chunks(channels)
+        .map(|c| match channels {
+            1 | 2 => Color::new(c[0], c[0], c[0]),
+            _ => Color::new(c[0], c[1], c[2]),
+        })
+        .collect();
+    Ok(Image::from_rows(info.width, info.height, colors))
+}
+
+fn parse_number(token: &[u8]) -> Result<u32, Error> {
+    std::str::from_utf8(token)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| invalid_data("Malformed number in PPM"))
+}
+
+fn next_token<'a>(data: &'a [u8], pos: &mut usize) -> Result<&'a [u8], Error> {
+    loop {
+        match data.get(*pos) {
+            Some(b'#') => {
+                while data.get(*pos).is_some_and(|&b| b != b'\n') {
+                    *pos += 1;
+                }
+            }
+            Some(b) if b.is_ascii_whitespace() => *pos += 1,
+            Some(_) => break,
+            None => return Err(invalid_data("Unexpected end of PPM")),
+        }
+    }
+    let start = *pos;
+    while data.get(*pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+        *pos += 1;
+    }
+    Ok(&data[start..*pos])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_ppm_round_trip() {
+        let mut image = Image::new(2, 2);
+        image[(0, 0)] = Color::new(1, 2, 3);
+        image[(1, 1)] = Color::new(250, 128, 0);
+        let mut output = Vec::new();
+        write_ppm(image, &mut output).unwrap();
+        let image = read_ppm(&mut output.as_slice()).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 2));
+        assert_eq!(image[(0, 0)], Color::new(1, 2, 3));
+        assert_eq!(image[(1, 1)], Color::new(250, 128, 0));
+    }
+
+    #[test]
+    fn test_read_binary_pgm() {
+        let mut input: &[u8] = b"P5\n# comment\n2 1\n255\n\x00\xff";
+        let image = read_ppm(&mut input).unwrap();
+        assert_eq!(image[(0, 0)], Color::new(0, 0, 0));
+        assert_eq!(image[(1, 0)], Color::new(255, 255, 255));
+    }
+
+    #[test]
+    fn test_reject_oversized_header() {
+        let mut input: &[u8] = b"P6\n65536 65536\n255\n\x00";
+        let err = read_ppm(&mut input).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::hit::HitRecord;
+use crate::material::Scatter;
+use crate::transform::AnimatedTransform;
+use crate::{Aabb, Hit, Ray};
+use std::sync::Arc;
+
+/// Places a shared object into the world with a (possibly animated) object-to-world transform.
+pub struct Instance {
+    object: Arc<dyn Hit>,
+    transform: AnimatedTransform,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hit>, transform: AnimatedTransform) -> Self {
+        Self { object, transform }
+    }
+}
+
+impl Hit for Instance {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let to_world = self.transform.at(ray.time());
+        let local_ray = to_world.inverse().ray(ray);
+        let hit = self.object.hit(&local_ray, t_min, t_max)?;
+        let record = HitRecord::with_uv(
+            hit.t(),
+            to_world.point(hit.point()),
+            to_world.normal(hit.normal()).normalize(),
+            hit.uv(),
+            hit.material(),
+        )
+        .with_tangent(to_world.vector(hit.tangent()));
+        let record = match hit.color() {
+            Some(color) => record.with_color(color),
+            None => record,
+        };
+        Some(match hit.medium() {
+            Some(medium) => record.with_medium(medium),
+            None => record,
+        })
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let local = self.object.bounding_box(time0, time1)?;
+        Some(self.transform.bounds(&local, time0, time1))
+    }
+
+    fn collect_materials<'a>(&'a self, materials: &mut Vec<&'a dyn Scatter>) {
+        self.object.collect_materials(materials);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::Keyframe;
+    use crate::{Lambertian, Sphere, Vec3};
+
+    #[test]
+    fn test_hit_follows_keyframes() {
+        let sphere = Sphere::new(
+            Vec3::default(),
+            0.5,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let unit = Vec3::new(1.0, 1.0, 1.0);
+        let instance = Instance::new(
+            Arc::new(sphere),
+            AnimatedTransform::new(vec![
+                Keyframe::new(0.0, Vec3::new(0.0, 0.0, -2.0), axis, 0.0, unit),
+                Keyframe::new(1.0, Vec3::new(3.0, 0.0, -2.0), axis, 0.0, unit),
+            ]),
+        );
+        let direction = Vec3::new(0.0, 0.0, -1.0);
+        let early = Ray::new(Vec3::new(3.0, 0.0, 0.0), direction, 0.0);
+        let late = Ray::new(Vec3::new(3.0, 0.0, 0.0), direction, 1.0);
+        assert!(instance.hit(&early, 0.001, f64::MAX).is_none());
+        let hit = instance.hit(&late, 0.001, f64::MAX).unwrap();
+        assert_eq!(hit.point(), Vec3::new(3.0, 0.0, -1.5));
+        assert_eq!(hit.normal(), Vec3::new(0.0, 0.0, 1.0));
+
+        let bounds = instance.bounding_box(0.0, 1.0).unwrap();
+        assert_eq!(bounds.min(), Vec3::new(-0.5, -0.5, -2.5));
+        assert_eq!(bounds.max(), Vec3::new(3.5, 0.5, -1.5));
+    }
+}
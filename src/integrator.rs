@@ -0,0 +1,722 @@
+use crate::light::power_heuristic;
+use crate::material::Lobe;
+use crate::medium::Medium;
+use crate::spectrum::SampledWavelengths;
+use crate::{Aov, HitRecord, Ray, Scene, Vec3};
+use rand::Rng;
+
+/// How direct illumination from the scene lights is estimated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightSampling {
+    /// Lights are found only when a BSDF-sampled ray happens to hit them.
+    Naive,
+    /// Shadow rays towards sampled lights at every non-specular vertex, combined
+    /// with BSDF sampling by multiple importance sampling (power heuristic).
+    Mis,
+}
+
+/// Unidirectional path tracer.
+pub struct PathTracer {
+    max_depth: u32,
+    max_diffuse_depth: u32,
+    max_specular_depth: u32,
+    max_transmission_depth: u32,
+    roulette_depth: u32,
+    light_sampling: LightSampling,
+    spectral: bool,
+}
+
+/// Bounces made along a path so far, in total and of each kind.
+#[derive(Clone, Copy, Default)]
+struct Bounces {
+    total: u32,
+    diffuse: u32,
+    specular: u32,
+    transmission: u32,
+}
+
+/// Scattering vertex a ray was sampled from, needed to weight emission it hits.
+#[derive(Clone, Copy)]
+struct Previous {
+    point: Vec3,
+    normal: Vec3,
+    bsdf_pdf: f64,
+}
+
+impl Previous {
+    /// Density of light sampling from this vertex choosing `light` and then `wi`.
+    fn light_pdf(&self, scene: &Scene, light: usize, wi: Vec3) -> f64 {
+        let pmf = scene.light_sampler().pmf(self.point, self.normal, light);
+        pmf * scene.lights()[light].pdf_li(self.point, wi)
+    }
+}
+
+/// Point where light is scattered, on a surface or inside a medium (zero normal).
+#[derive(Clone, Copy)]
+struct Interaction {
+    point: Vec3,
+    normal: Vec3,
+    time: f64,
+}
+
+/// State carried along a path.
+#[derive(Clone)]
+struct Path<'a> {
+    /// Media the path is inside of, innermost last.
+    media: Vec<&'a dyn Medium>,
+    /// Wavelengths transported when rendering spectrally; radiance and
+    /// throughput then hold one value per wavelength instead of RGB.
+    wavelengths: Option<SampledWavelengths>,
+}
+
+impl Path<'_> {
+    /// Spectral values of an RGB reflectance, radiance or weight, unchanged in RGB mode.
+    fn spectrum(&self, rgb: Vec3) -> Vec3 {
+        match &self.wavelengths {
+            Some(wavelengths) => wavelengths.uplift(rgb),
+            None => rgb,
+        }
+    }
+}
+
+impl PathTracer {
+    pub fn new(max_depth: u32, light_sampling: LightSampling) -> Self {
+        Self {
+            max_depth,
+            max_diffuse_depth: max_depth,
+            max_specular_depth: max_depth,
+            max_transmission_depth: max_depth,
+            roulette_depth: 3,
+            light_sampling,
+            spectral: false,
+        }
+    }
+
+    /// Bounces after which Russian roulette may terminate paths, with a
+    /// probability that grows as their throughput falls. Defaults to 3.
+    pub fn set_roulette_depth(&mut self, roulette_depth: u32) {
+        self.roulette_depth = roulette_depth;
+    }
+
+    /// Diffuse bounces a path may make, within the overall maximum depth.
+    pub fn set_max_diffuse_depth(&mut self, max_depth: u32) {
+        self.max_diffuse_depth = max_depth;
+    }
+
+    /// Specular and glossy reflections a path may make, within the overall
+    /// maximum depth.
+    pub fn set_max_specular_depth(&mut self, max_depth: u32) {
+        self.max_specular_depth = max_depth;
+    }
+
+    /// Transmissions a path may make, within the overall maximum depth.
+    pub fn set_max_transmission_depth(&mut self, max_depth: u32) {
+        self.max_transmission_depth = max_depth;
+    }
+
+    /// Traces sampled wavelengths instead of RGB, so that dispersive
+    /// dielectrics split light into colors. RGB albedos and emission are
+    /// uplifted to smooth spectra and the result is converted back to RGB.
+    pub fn set_spectral(&mut self, spectral: bool) {
+        self.spectral = spectral;
+    }
+
+    pub fn is_spectral(&self) -> bool {
+        self.spectral
+    }
+
+    /// Radiance arriving along `ray`, which starts outside of every medium.
+    pub fn li<R: Rng>(&self, ray: &Ray, scene: &Scene, rng: &mut R) -> Vec3 {
+        self.li_with_aovs(ray, scene, rng, &mut |_, _| {})
+    }
+
+    /// Radiance arriving along `ray`, also passing every output variable but
+    /// the depth to `aov`, at most once each.
+    pub fn li_with_aovs<R: Rng>(
+        &self,
+        ray: &Ray,
+        scene: &Scene,
+        rng: &mut R,
+        aov: &mut dyn FnMut(Aov, Vec3),
+    ) -> Vec3 {
+        let wavelengths = if self.spectral {
+            Some(SampledWavelengths::sample(rng.gen()))
+        } else {
+            None
+        };
+        let path = Path {
+            media: Vec::new(),
+            wavelengths,
+        };
+        let to_rgb = |radiance| match wavelengths {
+            Some(wavelengths) => wavelengths.to_rgb(radiance),
+            None => radiance,
+        };
+        let mut write = |kind, value| match kind {
+            Aov::Diffuse | Aov::Specular | Aov::Emission => aov(kind, to_rgb(value)),
+            _ => aov(kind, value),
+        };
+        to_rgb(self.trace(ray, scene, rng, path, &mut write))
+    }
+
+    /// Radiance along `ray`; the radiance variables are split by the lobe
+    /// sampled at the first hit.
+    fn trace<'s, R: Rng>(
+        &self,
+        ray: &Ray,
+        scene: &'s Scene,
+        rng: &mut R,
+        mut path: Path<'s>,
+        aov: &mut dyn FnMut(Aov, Vec3),
+    ) -> Vec3 {
+        let mut ray = *ray;
+        let mut radiance = Vec3::default();
+        let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+        let mut previous = None;
+        let mut bounces = Bounces::default();
+        // Radiance gathered before the first bounce, and whether it was diffuse.
+        let mut emission = None;
+        let mut diffuse = false;
+        loop {
+            let hit = scene.world().hit(&ray, 0.001, f64::MAX);
+            if let Some(&medium) = path.media.last() {
+                let t_max = hit.as_ref().map_or(f64::INFINITY, |hit| hit.t());
+                let sample = medium.sample(&ray, t_max, rng);
+                throughput *= path.spectrum(sample.weight());
+                if throughput == Vec3::default() {
+                    break;
+                }
+                if let Some(t) = sample.t() {
+                    // Scattering in a medium counts as a diffuse bounce.
+                    if !self.bounce(&mut bounces, Lobe::DIFFUSE) {
+                        break;
+                    }
+                    if bounces.total == 1 {
+                        emission = Some(radiance);
+                        diffuse = true;
+                    }
+                    let at = Interaction {
+                        point: ray.point_at_parameter(t),
+                        normal: Vec3::default(),
+                        time: ray.time(),
+                    };
+                    let wo = -ray.direction().normalize();
+                    let phase = medium.phase();
+                    let sample_lights = self.light_sampling == LightSampling::Mis;
+                    if sample_lights {
+                        let scattering = |wi: Vec3| {
+                            let p = phase.p(wo, wi);
+                            (Vec3::new(p, p, p), p)
+                        };
+                        radiance +=
+                            throughput * self.sample_direct(at, &scattering, scene, &path, rng);
+                    }
+                    // Phase sampling is exact, so the throughput stays unchanged.
+                    let (wi, pdf) = phase.sample(wo, (rng.gen(), rng.gen()));
+                    previous = if sample_lights {
+                        Some(Previous {
+                            point: at.point,
+                            normal: at.normal,
+                            bsdf_pdf: pdf,
+                        })
+                    } else {
+                        None
+                    };
+                    ray = Ray::new(at.point, wi, at.time);
+                    if !self.survives(&mut throughput, bounces.total, rng) {
+                        break;
+                    }
+                    continue;
+                }
+            }
+            let hit = match (hit, &path.wavelengths) {
+                (Some(hit), Some(wavelengths)) => hit.with_wavelength(wavelengths.hero()),
+                (Some(hit), None) => hit,
+                (None, _) => {
+                    radiance += throughput * path.spectrum(self.escaped(&ray, scene, previous));
+                    break;
+                }
+            };
+            let wo = -ray.direction().normalize();
+            let material = hit.material();
+            if material.is_interface() {
+                // Pass through without counting a bounce.
+                update_media(&mut path.media, &hit, wo, -wo);
+                ray = Ray::new(hit.point(), ray.direction(), ray.time());
+                continue;
+            }
+
+            let mut emitted = path.spectrum(material.emitted(&hit, wo));
+            if let (Some(previous), Some(light)) = (previous, hit.light()) {
+                let light_pdf = previous.light_pdf(scene, light, -wo);
+                emitted *= power_heuristic(previous.bsdf_pdf, light_pdf);
+            }
+            radiance += throughput * emitted;
+            if bounces.total == 0 && emission.is_none() {
+                aov(Aov::Position, hit.point());
+                aov(Aov::Normal, hit.normal());
+                let id = hit.object().map_or(0.0, |object| object as f64 + 1.0);
+                aov(Aov::ObjectId, Vec3::new(id, id, id));
+            }
+            if bounces.total >= self.max_depth {
+                break;
+            }
+
+            let sample = match material.sample(&hit, wo, rng.gen(), (rng.gen(), rng.gen())) {
+                Some(sample) if sample.pdf() > 0.0 => sample,
+                _ => break,
+            };
+            if !self.bounce(&mut bounces, sample.lobe()) {
+                break;
+            }
+            if bounces.total == 1 {
+                emission = Some(radiance);
+                diffuse = sample.lobe().contains(Lobe::DIFFUSE);
+                let cos = sample.wi().dot(hit.normal()).abs();
+                aov(Aov::Albedo, sample.value() * (cos / sample.pdf()));
+            }
+            let delta = sample.lobe().is_delta();
+            let sample_lights = self.light_sampling == LightSampling::Mis && !delta;
+            let at = Interaction {
+                point: hit.point(),
+                normal: hit.normal(),
+                time: ray.time(),
+            };
+            if sample_lights {
+                let scattering = |wi: Vec3| {
+                    let f = material.eval(&hit, wo, wi) * wi.dot(hit.normal()).abs();
+                    (f, material.pdf(&hit, wo, wi))
+                };
+                radiance += throughput * self.sample_direct(at, &scattering, scene, &path, rng);
+            }
+            throughput *= path.spectrum(sample.value())
+                * (sample.wi().dot(hit.normal()).abs() / sample.pdf());
+            if material.is_dispersive() {
+                if let Some(wavelengths) = &mut path.wavelengths {
+                    throughput *= wavelengths.terminate_secondary();
+                }
+            }
+            previous = if sample_lights {
+                Some(Previous {
+                    point: at.point,
+                    normal: at.normal,
+                    bsdf_pdf: sample.pdf(),
+                })
+            } else {
+                None
+            };
+            update_media(&mut path.media, &hit, wo, sample.wi());
+            ray = Ray::new(hit.point(), sample.wi(), ray.time());
+            if !self.survives(&mut throughput, bounces.total, rng) {
+                break;
+            }
+        }
+        match emission {
+            Some(emission) => {
+                aov(Aov::Emission, emission);
+                let lobe = if diffuse { Aov::Diffuse } else { Aov::Specular };
+                aov(lobe, radiance - emission);
+            }
+            None => aov(Aov::Emission, radiance),
+        }
+        radiance
+    }
+
+    /// Counts a bounce through `lobe`, unless that would exceed the maximum
+    /// depth of its kind. Lobes with a diffuse part count as diffuse, other
+    /// transmission as transmission and the rest as specular.
+    fn bounce(&self, bounces: &mut Bounces, lobe: Lobe) -> bool {
+        let (count, max) = if lobe.contains(Lobe::DIFFUSE) {
+            (&mut bounces.diffuse, self.max_diffuse_depth)
+        } else if lobe.contains(Lobe::TRANSMISSION) {
+            (&mut bounces.transmission, self.max_transmission_depth)
+        } else {
+            (&mut bounces.specular, self.max_specular_depth)
+        };
+        if bounces.total >= self.max_depth || *count >= max {
+            return false;
+        }
+        *count += 1;
+        bounces.total += 1;
+        true
+    }
+
+    /// Russian roulette past the minimum depth: paths carrying little
+    /// throughput are terminated, and the survivors weighted up to compensate.
+    fn survives<R: Rng>(&self, throughput: &mut Vec3, depth: u32, rng: &mut R) -> bool {
+        if depth < self.roulette_depth {
+            return true;
+        }
+        let survival = throughput
+            .x()
+            .max(throughput.y())
+            .max(throughput.z())
+            .min(1.0);
+        // Paths that always survive leave the random sequence untouched.
+        if survival >= 1.0 {
+            return true;
+        }
+        if survival <= 0.0 || rng.gen::<f64>() >= survival {
+            return false;
+        }
+        *throughput /= survival;
+        true
+    }
+
+    /// Radiance from the infinite lights seen by a ray leaving the scene.
+    fn escaped(&self, ray: &Ray, scene: &Scene, previous: Option<Previous>) -> Vec3 {
+        let direction = ray.direction().normalize();
+        let lights = scene.lights();
+        let mut radiance = Vec3::default();
+        for &idx in scene.infinite_lights() {
+            let le = lights[idx].le(direction);
+            radiance += match &previous {
+                Some(previous) => {
+                    let light_pdf = previous.light_pdf(scene, idx, direction);
+                    le * power_heuristic(previous.bsdf_pdf, light_pdf)
+                }
+                None => le,
+            };
+        }
+        radiance
+    }
+
+    /// Light-sampling half of the MIS estimate of direct illumination at `at`;
+    /// `scattering` gives the cosine-weighted BSDF or phase function value and
+    /// its sampling density for a direction.
+    fn sample_direct<R: Rng>(
+        &self,
+        at: Interaction,
+        scattering: &dyn Fn(Vec3) -> (Vec3, f64),
+        scene: &Scene,
+        path: &Path,
+        rng: &mut R,
+    ) -> Vec3 {
+        let (light, pmf) = match scene.light_sampler().sample(at.point, at.normal, rng.gen()) {
+            Some(choice) => choice,
+            None => return Vec3::default(),
+        };
+        let light = &scene.lights()[light];
+        let sample = match light.sample_li(at.point, (rng.gen(), rng.gen())) {
+            Some(sample) if sample.pdf() > 0.0 => sample,
+            _ => return Vec3::default(),
+        };
+        let wi = sample.wi();
+        let (f, scattering_pdf) = scattering(wi);
+        if f == Vec3::default() || sample.radiance() == Vec3::default() {
+            return Vec3::default();
+        }
+        let shadow_ray = Ray::new(at.point, wi, at.time);
+        let transmittance =
+            self.transmittance(&shadow_ray, sample.distance(), scene, &path.media, rng);
+        if transmittance == Vec3::default() {
+            return Vec3::default();
+        }
+        let light_pdf = sample.pdf() * pmf;
+        let weight = if sample.is_delta() {
+            1.0
+        } else {
+            power_heuristic(light_pdf, scattering_pdf)
+        };
+        let radiance = path.spectrum(f) * path.spectrum(sample.radiance());
+        radiance * path.spectrum(transmittance) * (weight / light_pdf)
+    }
+
+    /// Transmittance along a unit-direction shadow ray up to `distance`, passing
+    /// through medium boundaries; zero if a surface blocks it.
+    fn transmittance<R: Rng>(
+        &self,
+        ray: &Ray,
+        distance: f64,
+        scene: &Scene,
+        media: &[&dyn Medium],
+        rng: &mut R,
+    ) -> Vec3 {
+        let mut media = media.to_vec();
+        let mut ray = Ray::new(ray.origin(), ray.direction(), ray.time());
+        let mut remaining = distance;
+        let mut transmittance = Vec3::new(1.0, 1.0, 1.0);
+        loop {
+            let t_max = if remaining.is_finite() {
+                remaining * (1.0 - 1e-4)
+            } else {
+                f64::MAX
+            };
+            let hit = scene.world().hit(&ray, 0.001, t_max);
+            if let Some(medium) = media.last() {
+                let segment = hit.as_ref().map_or(t_max, |hit| hit.t());
+                transmittance *= medium.transmittance(&ray, segment, rng);
+            }
+            let hit = match hit {
+                Some(hit) => hit,
+                None => return transmittance,
+            };
+            if !hit.material().is_interface() || transmittance == Vec3::default() {
+                return Vec3::default();
+            }
+            update_media(&mut media, &hit, -ray.direction(), ray.direction());
+            remaining -= hit.t();
+            ray = Ray::new(hit.point(), ray.direction(), ray.time());
+        }
+    }
+}
+
+/// Enters or leaves the medium bounded by `hit` when going from `wo` to `wi`
+/// crosses the surface.
+fn update_media<'a>(media: &mut Vec<&'a dyn Medium>, hit: &HitRecord<'a>, wo: Vec3, wi: Vec3) {
+    let medium = match hit.medium() {
+        Some(medium) => medium,
+        None => return,
+    };
+    let (cos_o, cos_i) = (wo.dot(hit.normal()), wi.dot(hit.normal()));
+    if cos_o > 0.0 && cos_i < 0.0 {
+        media.push(medium);
+    } else if cos_o < 0.0 && cos_i > 0.0 {
+        let same = |other: &&dyn Medium| {
+            std::ptr::eq(
+                *other as *const dyn Medium as *const (),
+                medium as *const dyn Medium as *const (),
+            )
+        };
+        if let Some(idx) = media.iter().rposition(same) {
+            media.remove(idx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ConstantEnvironment, ConstantMedium, Dielectric, DiffuseLight, HitList, HomogeneousMedium,
+        Ior, Lambertian, Metal, Sphere,
+    };
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_mis_matches_naive() {
+        let mut world = HitList::new();
+        world.push(Sphere::new(
+            Vec3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        ));
+        world.push(Sphere::new(
+            Vec3::new(0.0, 1.5, 0.0),
+            0.5,
+            Arc::new(DiffuseLight::new(Vec3::new(4.0, 4.0, 4.0))),
+        ));
+        let scene = Scene::new(world);
+        // The emissive sphere and the sky.
+        assert_eq!(scene.lights().len(), 2);
+
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 3.0), Vec3::new(0.0, -1.0, -3.0), 0.0);
+        let estimate = |light_sampling, n: u32| {
+            let tracer = PathTracer::new(5, light_sampling);
+            let mut rng = StdRng::seed_from_u64(7);
+            let mut acc = Vec3::default();
+            for _ in 0..n {
+                acc += tracer.li(&ray, &scene, &mut rng);
+            }
+            acc / f64::from(n)
+        };
+        let naive = estimate(LightSampling::Naive, 100_000);
+        let mis = estimate(LightSampling::Mis, 20_000);
+        assert!(
+            (naive - mis).length() < 0.03 * mis.length(),
+            "{:?} {:?}",
+            naive,
+            mis
+        );
+    }
+
+    #[test]
+    fn test_roulette_is_unbiased() {
+        // A light inside a closed white room, where paths bounce many times.
+        let mut world = HitList::new();
+        world.push(Sphere::new(
+            Vec3::default(),
+            5.0,
+            Arc::new(Lambertian::new(Vec3::new(0.8, 0.8, 0.8))),
+        ));
+        world.push(Sphere::new(
+            Vec3::new(0.0, 3.0, 0.0),
+            1.0,
+            Arc::new(DiffuseLight::new(Vec3::new(1.0, 1.0, 1.0))),
+        ));
+        let scene = Scene::new(world);
+        let ray = Ray::new(Vec3::default(), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let estimate = |roulette_depth| {
+            let mut tracer = PathTracer::new(200, LightSampling::Mis);
+            tracer.set_roulette_depth(roulette_depth);
+            let mut rng = StdRng::seed_from_u64(5);
+            let n = 20_000;
+            let mut acc = Vec3::default();
+            for _ in 0..n {
+                acc += tracer.li(&ray, &scene, &mut rng);
+            }
+            acc / f64::from(n)
+        };
+        let (roulette, full) = (estimate(0), estimate(u32::MAX));
+        assert!(
+            (roulette - full).length() < 0.03 * full.length(),
+            "{:?} {:?}",
+            roulette,
+            full
+        );
+    }
+
+    #[test]
+    fn test_depth_per_lobe() {
+        let mut world = HitList::new();
+        world.push(Sphere::new(
+            Vec3::new(-1.0, 0.0, 0.0),
+            0.5,
+            Arc::new(Metal::new(Vec3::new(0.8, 0.8, 0.8), 0.0)),
+        ));
+        world.push(Sphere::new(
+            Vec3::new(1.0, 0.0, 0.0),
+            0.5,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        ));
+        let scene = Scene::with_environment(
+            world,
+            Arc::new(ConstantEnvironment::new(Vec3::new(1.0, 1.0, 1.0))),
+        );
+        let mirror = Ray::new(Vec3::new(-1.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let diffuse = Ray::new(Vec3::new(1.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut rng = StdRng::seed_from_u64(9);
+        let mut tracer = PathTracer::new(10, LightSampling::Mis);
+        tracer.set_max_diffuse_depth(0);
+        assert_eq!(tracer.li(&diffuse, &scene, &mut rng), Vec3::default());
+        let reflected = tracer.li(&mirror, &scene, &mut rng);
+        assert!((reflected - Vec3::new(0.8, 0.8, 0.8)).length() < 1e-9);
+
+        let mut tracer = PathTracer::new(10, LightSampling::Naive);
+        tracer.set_max_specular_depth(0);
+        assert_eq!(tracer.li(&mirror, &scene, &mut rng), Vec3::default());
+        let n = 1000;
+        let mut acc = Vec3::default();
+        for _ in 0..n {
+            acc += tracer.li(&diffuse, &scene, &mut rng);
+        }
+        assert!((acc / f64::from(n) - Vec3::new(0.5, 0.5, 0.5)).length() < 1e-9);
+    }
+
+    fn average_li(scene: &Scene, ray: &Ray, n: u32) -> Vec3 {
+        let tracer = PathTracer::new(100, LightSampling::Mis);
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut acc = Vec3::default();
+        for _ in 0..n {
+            acc += tracer.li(ray, scene, &mut rng);
+        }
+        acc / f64::from(n)
+    }
+
+    #[test]
+    fn test_absorbing_medium_attenuates() {
+        let sigma_a = Vec3::new(0.25, 0.5, 1.0);
+        let medium = HomogeneousMedium::new(sigma_a, Vec3::default(), 0.0);
+        let boundary = Sphere::new(
+            Vec3::default(),
+            1.0,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let scene = Scene::with_environment(
+            ConstantMedium::new(boundary, Arc::new(medium)),
+            Arc::new(ConstantEnvironment::new(Vec3::new(1.0, 1.0, 1.0))),
+        );
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let radiance = average_li(&scene, &ray, 20_000);
+        let expected = Vec3::new((-0.5f64).exp(), (-1.0f64).exp(), (-2.0f64).exp());
+        assert!(
+            (radiance - expected).length() < 0.02,
+            "{:?} {:?}",
+            radiance,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_scattering_medium_in_glass_conserves_energy() {
+        // Nothing absorbs, so a uniformly lit white furnace stays uniform.
+        let medium = HomogeneousMedium::isotropic(2.0, Vec3::new(1.0, 1.0, 1.0));
+        let glass = Sphere::new(Vec3::default(), 1.0, Arc::new(Dielectric::new(1.5)));
+        let scene = Scene::with_environment(
+            ConstantMedium::with_surface(glass, Arc::new(medium)),
+            Arc::new(ConstantEnvironment::new(Vec3::new(1.0, 1.0, 1.0))),
+        );
+        let ray = Ray::new(Vec3::new(0.2, 0.1, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let radiance = average_li(&scene, &ray, 20_000);
+        assert!(
+            (radiance - Vec3::new(1.0, 1.0, 1.0)).length() < 0.03,
+            "{:?}",
+            radiance
+        );
+    }
+
+    #[test]
+    fn test_spectral_matches_rgb() {
+        let mut world = HitList::new();
+        world.push(Sphere::new(
+            Vec3::default(),
+            1.0,
+            Arc::new(Lambertian::new(Vec3::new(0.7, 0.4, 0.2))),
+        ));
+        let scene = Scene::with_environment(
+            world,
+            Arc::new(ConstantEnvironment::new(Vec3::new(0.5, 0.6, 0.9))),
+        );
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut tracer = PathTracer::new(1, LightSampling::Mis);
+        let mut rng = StdRng::seed_from_u64(11);
+        let n = 20_000;
+        let mut rgb = Vec3::default();
+        let mut spectral = Vec3::default();
+        for _ in 0..n {
+            rgb += tracer.li(&ray, &scene, &mut rng);
+        }
+        tracer.set_spectral(true);
+        for _ in 0..n {
+            spectral += tracer.li(&ray, &scene, &mut rng);
+        }
+        rgb /= f64::from(n);
+        spectral /= f64::from(n);
+        assert!(
+            (rgb - spectral).length() < 0.03 * rgb.length(),
+            "{:?} {:?}",
+            rgb,
+            spectral
+        );
+    }
+
+    #[test]
+    fn test_dispersive_glass_conserves_energy() {
+        let glass = Sphere::new(
+            Vec3::default(),
+            1.0,
+            Arc::new(Dielectric::with_dispersion(Ior::diamond())),
+        );
+        let scene = Scene::with_environment(
+            glass,
+            Arc::new(ConstantEnvironment::new(Vec3::new(1.0, 1.0, 1.0))),
+        );
+        let ray = Ray::new(Vec3::new(0.3, 0.2, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut tracer = PathTracer::new(100, LightSampling::Mis);
+        tracer.set_spectral(true);
+        let mut rng = StdRng::seed_from_u64(12);
+        let n = 20_000;
+        let mut radiance = Vec3::default();
+        for _ in 0..n {
+            radiance += tracer.li(&ray, &scene, &mut rng);
+        }
+        radiance /= f64::from(n);
+        assert!(
+            (radiance - Vec3::new(1.0, 1.0, 1.0)).length() < 0.05,
+            "{:?}",
+            radiance
+        );
+    }
+}
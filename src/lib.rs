@@ -1,17 +1,105 @@
+mod aabb;
+mod bdpt;
+mod bvh;
 mod camera;
 mod color;
+mod curve;
+mod debug_mode;
+mod distribution;
+mod environment;
+mod error;
+mod framebuffer;
+mod grid_medium;
+mod hdr_image;
+mod heightfield;
 mod hit;
 mod image;
+mod instance;
+mod integrator;
+mod light;
+mod light_sampler;
 mod material;
+mod medium;
+mod mesh;
+mod microfacet;
+mod mlt;
+mod moving_sphere;
+mod noise;
+mod phong;
+mod photon_map;
+mod photon_mapper;
+mod ply;
 mod ray;
+mod rectangle;
+mod render;
+mod scene;
+mod scene_file;
+mod sdf;
+mod sdf_object;
+mod sky;
+mod spectrum;
 mod sphere;
+mod stl;
+mod texture;
+mod transform;
+mod triangle;
 mod vec3;
+mod whitted;
 
+pub use crate::aabb::Aabb;
+pub use crate::bdpt::BidirectionalPathTracer;
+pub use crate::bvh::Bvh;
 pub use crate::camera::Camera;
 pub use crate::color::{Color, RED};
-pub use crate::hit::{Hit, HitList};
-pub use crate::image::{write_ppm, Image};
-pub use crate::material::{Lambertian, Metal, Scatter};
+pub use crate::curve::{Curve, CurveSet};
+pub use crate::debug_mode::DebugMode;
+pub use crate::environment::{
+    ConstantEnvironment, Environment, EnvironmentMap, GradientEnvironment,
+};
+pub use crate::framebuffer::{write_exr, Aov, Framebuffer};
+pub use crate::grid_medium::{read_voxel_grid, GridMedium, VoxelGrid};
+pub use crate::hdr_image::{read_hdr, read_pfm, write_pfm, HdrImage};
+pub use crate::heightfield::Heightfield;
+pub use crate::hit::{Hit, HitList, HitRecord};
+pub use crate::image::{read_png, read_ppm, write_ppm, Image};
+pub use crate::instance::Instance;
+pub use crate::integrator::{LightSampling, PathTracer};
+pub use crate::light::{
+    power_heuristic, DirectionalLight, Light, LightSample, PointLight, SpotLight,
+};
+pub use crate::light_sampler::{LightBounds, LightSampler, LightSelection};
+pub use crate::material::{
+    BsdfSample, Dielectric, DiffuseLight, KajiyaKay, Lambertian, Lobe, Metal, Scatter, ScatteredRay,
+};
+pub use crate::medium::{
+    ConstantMedium, HenyeyGreenstein, HomogeneousMedium, Medium, MediumSample,
+};
+pub use crate::mesh::Mesh;
+pub use crate::microfacet::Microfacet;
+pub use crate::mlt::MetropolisLightTransport;
+pub use crate::moving_sphere::MovingSphere;
+pub use crate::noise::{Granite, Marble, Perlin, Wood};
+pub use crate::phong::Phong;
+pub use crate::photon_map::{Photon, PhotonMap};
+pub use crate::photon_mapper::{PhotonMapper, ProgressivePhotonMapper};
+pub use crate::ply::read_ply;
 pub use crate::ray::Ray;
+pub use crate::rectangle::Rectangle;
+pub use crate::render::{render, render_layers, Integrator, RenderSettings};
+pub use crate::scene::Scene;
+pub use crate::scene_file::read_scene;
+pub use crate::sdf::{
+    smooth_min, Mandelbulb, Repeat, RoundedBox, Sdf, SdfDifference, SdfIntersection, SdfSphere,
+    SdfTorus, SdfTranslate, SdfUnion, SmoothUnion, Twist,
+};
+pub use crate::sdf_object::SdfObject;
+pub use crate::sky::{sun_direction, PreethamSky, SunLight};
+pub use crate::spectrum::{
+    cie_xyz, rgb_to_spectrum, Ior, SampledWavelengths, LAMBDA_MAX, LAMBDA_MIN, REFERENCE_WAVELENGTH,
+};
 pub use crate::sphere::Sphere;
+pub use crate::stl::read_stl;
+pub use crate::texture::{Checker, ImageTexture, SolidColor, Texture, WrapMode};
+pub use crate::transform::{AnimatedTransform, Keyframe, Transform};
 pub use crate::vec3::Vec3;
+pub use crate::whitted::WhittedTracer;
@@ -0,0 +1,449 @@
+use crate::light_sampler::LightBounds;
+use crate::material::{cosine_hemisphere, uniform_sphere};
+use crate::{Aabb, Vec3};
+use std::f64::consts::PI;
+
+/// Incident radiance sampled from a light towards a shading point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightSample {
+    wi: Vec3,
+    radiance: Vec3,
+    distance: f64,
+    pdf: f64,
+    delta: bool,
+    normal: Vec3,
+}
+
+impl LightSample {
+    /// `pdf` is with respect to solid angle at the shading point, or one for delta lights.
+    pub fn new(wi: Vec3, radiance: Vec3, distance: f64, pdf: f64, delta: bool) -> Self {
+        Self {
+            wi,
+            radiance,
+            distance,
+            pdf,
+            delta,
+            normal: Vec3::default(),
+        }
+    }
+
+    /// Attaches the surface normal of the light at the sampled point.
+    pub fn with_normal(mut self, normal: Vec3) -> Self {
+        self.normal = normal;
+        self
+    }
+
+    /// Unit direction from the shading point towards the light.
+    pub fn wi(&self) -> Vec3 {
+        self.wi
+    }
+
+    pub fn radiance(&self) -> Vec3 {
+        self.radiance
+    }
+
+    /// Distance to the sampled point, infinite for lights at infinity.
+    pub fn distance(&self) -> f64 {
+        self.distance
+    }
+
+    pub fn pdf(&self) -> f64 {
+        self.pdf
+    }
+
+    /// Whether the light can only be reached by explicit sampling, e.g. a point light.
+    pub fn is_delta(&self) -> bool {
+        self.delta
+    }
+
+    /// Surface normal at the sampled point, zero for lights without area.
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+}
+
+/// Ray of light leaving a light, for tracing paths from the lights.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EmissionSample {
+    origin: Vec3,
+    direction: Vec3,
+    normal: Vec3,
+    radiance: Vec3,
+    pdf_position: f64,
+    pdf_direction: f64,
+    delta: bool,
+}
+
+impl EmissionSample {
+    /// `pdf_position` is per unit area, or one for lights at a single point
+    /// (`delta`); `pdf_direction` is per unit solid angle.
+    pub fn new(
+        origin: Vec3,
+        direction: Vec3,
+        normal: Vec3,
+        radiance: Vec3,
+        (pdf_position, pdf_direction): (f64, f64),
+        delta: bool,
+    ) -> Self {
+        Self {
+            origin,
+            direction,
+            normal,
+            radiance,
+            pdf_position,
+            pdf_direction,
+            delta,
+        }
+    }
+
+    pub fn origin(&self) -> Vec3 {
+        self.origin
+    }
+
+    /// Unit direction the light leaves in.
+    pub fn direction(&self) -> Vec3 {
+        self.direction
+    }
+
+    /// Surface normal at the origin, zero for lights without area.
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    /// Radiance, or intensity for point lights, along the ray.
+    pub fn radiance(&self) -> Vec3 {
+        self.radiance
+    }
+
+    pub fn pdf_position(&self) -> f64 {
+        self.pdf_position
+    }
+
+    pub fn pdf_direction(&self) -> f64 {
+        self.pdf_direction
+    }
+
+    pub fn is_delta(&self) -> bool {
+        self.delta
+    }
+}
+
+pub trait Light: Send + Sync {
+    /// Samples a direction from `point` towards the light; shadowing is not checked.
+    fn sample_li(&self, point: Vec3, u: (f64, f64)) -> Option<LightSample>;
+
+    /// Solid angle density with which `sample_li` returns the unit direction `wi` from `point`.
+    fn pdf_li(&self, point: Vec3, wi: Vec3) -> f64;
+
+    /// Whether the light is infinitely far away and seen by rays leaving the scene.
+    fn is_infinite(&self) -> bool {
+        false
+    }
+
+    /// Radiance along a ray leaving the scene in unit `direction`, for infinite lights.
+    fn le(&self, _direction: Vec3) -> Vec3 {
+        Vec3::default()
+    }
+
+    /// Spatial, directional and power bounds used to pick among many lights;
+    /// lights without them, e.g. at infinity, are picked uniformly.
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+
+    /// Samples a ray leaving the light from `u_position` and `u_direction`;
+    /// lights at infinity do not support it.
+    fn sample_le(
+        &self,
+        _u_position: (f64, f64),
+        _u_direction: (f64, f64),
+    ) -> Option<EmissionSample> {
+        None
+    }
+
+    /// Area and solid angle densities with which `sample_le` returns a ray
+    /// from `point`, with surface `normal`, along unit `direction`.
+    fn pdf_le(&self, _point: Vec3, _normal: Vec3, _direction: Vec3) -> (f64, f64) {
+        (0.0, 0.0)
+    }
+}
+
+/// Emission sample leaving `point` on an area light with uniform position
+/// density `pdf_position`, cosine distributed around `normal`.
+pub(crate) fn cosine_emission(
+    point: Vec3,
+    normal: Vec3,
+    pdf_position: f64,
+    u: (f64, f64),
+    radiance: impl FnOnce(Vec3) -> Vec3,
+) -> Option<EmissionSample> {
+    let local = cosine_hemisphere(u);
+    let (s, t) = normal.orthonormal_basis();
+    let direction = s * local.x() + t * local.y() + normal * local.z();
+    let pdf_direction = local.z() / PI;
+    if pdf_direction <= 0.0 {
+        return None;
+    }
+    Some(EmissionSample::new(
+        point,
+        direction,
+        normal,
+        radiance(direction),
+        (pdf_position, pdf_direction),
+        false,
+    ))
+}
+
+/// Isotropic point light; `intensity` is the radiant intensity.
+pub struct PointLight {
+    position: Vec3,
+    intensity: Vec3,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, intensity: Vec3) -> Self {
+        Self {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, point: Vec3, _u: (f64, f64)) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance2 = to_light.squared_length();
+        let distance = distance2.sqrt();
+        Some(LightSample::new(
+            to_light / distance,
+            self.intensity / distance2,
+            distance,
+            1.0,
+            true,
+        ))
+    }
+
+    fn pdf_li(&self, _point: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    fn sample_le(
+        &self,
+        _u_position: (f64, f64),
+        u_direction: (f64, f64),
+    ) -> Option<EmissionSample> {
+        Some(EmissionSample::new(
+            self.position,
+            uniform_sphere(u_direction),
+            Vec3::default(),
+            self.intensity,
+            (1.0, 1.0 / (4.0 * PI)),
+            true,
+        ))
+    }
+
+    fn pdf_le(&self, _point: Vec3, _normal: Vec3, _direction: Vec3) -> (f64, f64) {
+        (0.0, 1.0 / (4.0 * PI))
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds::omnidirectional(
+            Aabb::new(self.position, self.position),
+            4.0 * PI * self.intensity.luminance(),
+        ))
+    }
+}
+
+/// Point light restricted to a cone around `direction`.
+///
+/// Intensity is full inside `falloff_start` degrees from the axis and fades
+/// smoothly to zero at `cone_angle` degrees.
+pub struct SpotLight {
+    position: Vec3,
+    direction: Vec3,
+    intensity: Vec3,
+    cos_cone: f64,
+    cos_falloff_start: f64,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Vec3,
+        direction: Vec3,
+        intensity: Vec3,
+        cone_angle: f64,
+        falloff_start: f64,
+    ) -> Self {
+        Self {
+            position,
+            direction: direction.normalize(),
+            intensity,
+            cos_cone: cone_angle.to_radians().cos(),
+            cos_falloff_start: falloff_start.min(cone_angle).to_radians().cos(),
+        }
+    }
+
+    fn falloff(&self, cos: f64) -> f64 {
+        if cos >= self.cos_falloff_start {
+            return 1.0;
+        }
+        if cos <= self.cos_cone {
+            return 0.0;
+        }
+        let t = (cos - self.cos_cone) / (self.cos_falloff_start - self.cos_cone);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    fn cone_pdf(&self) -> f64 {
+        1.0 / (2.0 * PI * (1.0 - self.cos_cone))
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, point: Vec3, _u: (f64, f64)) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance2 = to_light.squared_length();
+        let distance = distance2.sqrt();
+        let wi = to_light / distance;
+        let falloff = self.falloff(-wi.dot(self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+        Some(LightSample::new(
+            wi,
+            self.intensity * (falloff / distance2),
+            distance,
+            1.0,
+            true,
+        ))
+    }
+
+    fn pdf_li(&self, _point: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    fn sample_le(&self, _u_position: (f64, f64), (u1, u2): (f64, f64)) -> Option<EmissionSample> {
+        let cos_theta = 1.0 - u1 * (1.0 - self.cos_cone);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        let (s, t) = self.direction.orthonormal_basis();
+        let direction =
+            s * (sin_theta * phi.cos()) + t * (sin_theta * phi.sin()) + self.direction * cos_theta;
+        Some(EmissionSample::new(
+            self.position,
+            direction,
+            Vec3::default(),
+            self.intensity * self.falloff(cos_theta),
+            (1.0, self.cone_pdf()),
+            true,
+        ))
+    }
+
+    fn pdf_le(&self, _point: Vec3, _normal: Vec3, direction: Vec3) -> (f64, f64) {
+        if direction.dot(self.direction) >= self.cos_cone {
+            (0.0, self.cone_pdf())
+        } else {
+            (0.0, 0.0)
+        }
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let solid_angle = 2.0 * PI * (1.0 - 0.5 * (self.cos_falloff_start + self.cos_cone));
+        let cos_theta_e = (self.cos_cone.acos() - self.cos_falloff_start.acos()).cos();
+        Some(LightBounds::new(
+            Aabb::new(self.position, self.position),
+            self.direction,
+            solid_angle * self.intensity.luminance(),
+            self.cos_falloff_start,
+            cos_theta_e,
+            false,
+        ))
+    }
+}
+
+/// Light at infinity shining along `direction`, like the sun; `irradiance`
+/// is measured on a surface perpendicular to the direction.
+pub struct DirectionalLight {
+    direction: Vec3,
+    irradiance: Vec3,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Vec3) -> Self {
+        Self {
+            direction: direction.normalize(),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _point: Vec3, _u: (f64, f64)) -> Option<LightSample> {
+        Some(LightSample::new(
+            -self.direction,
+            self.irradiance,
+            f64::INFINITY,
+            1.0,
+            true,
+        ))
+    }
+
+    fn pdf_li(&self, _point: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+}
+
+/// Power heuristic (β = 2) weight for combining two sampling strategies.
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b == 0.0 {
+        0.0
+    } else {
+        a / (a + b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_point_light_inverse_square() {
+        let light = PointLight::new(Vec3::new(0.0, 2.0, 0.0), Vec3::new(8.0, 8.0, 8.0));
+        let sample = light.sample_li(Vec3::default(), (0.5, 0.5)).unwrap();
+        assert_eq!(sample.wi(), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(sample.radiance(), Vec3::new(2.0, 2.0, 2.0));
+        assert_eq!(sample.distance(), 2.0);
+        assert!(sample.is_delta());
+    }
+
+    #[test]
+    fn test_spot_light_cone() {
+        let light = SpotLight::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            30.0,
+            20.0,
+        );
+        let center = light.sample_li(Vec3::default(), (0.5, 0.5)).unwrap();
+        assert_eq!(center.radiance(), Vec3::new(1.0, 1.0, 1.0));
+        // 45 degrees off the axis is outside of the cone.
+        assert!(light
+            .sample_li(Vec3::new(1.0, 0.0, 0.0), (0.5, 0.5))
+            .is_none());
+        // 25 degrees off the axis is in the falloff region.
+        let x = 25f64.to_radians().tan();
+        let edge = light.sample_li(Vec3::new(x, 0.0, 0.0), (0.5, 0.5)).unwrap();
+        let attenuated = edge.radiance().x() * (1.0 + x * x);
+        assert!(attenuated > 0.0 && attenuated < 1.0);
+    }
+
+    #[test]
+    fn test_power_heuristic() {
+        assert_eq!(power_heuristic(1.0, 1.0), 0.5);
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+        assert_eq!(power_heuristic(3.0, 0.0), 1.0);
+    }
+}
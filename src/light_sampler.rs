@@ -0,0 +1,569 @@
+use crate::distribution::Distribution1D;
+use crate::light::Light;
+use crate::{Aabb, Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Conservative bounds on where a light is and in which directions it emits,
+/// used to estimate its contribution at a shading point.
+///
+/// Emission is bounded by a cone of normals around `axis` with half-angle
+/// `acos(cos_theta_o)`, each emitting into a further `acos(cos_theta_e)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightBounds {
+    bounds: Aabb,
+    axis: Vec3,
+    power: f64,
+    cos_theta_o: f64,
+    cos_theta_e: f64,
+    two_sided: bool,
+}
+
+impl LightBounds {
+    pub fn new(
+        bounds: Aabb,
+        axis: Vec3,
+        power: f64,
+        cos_theta_o: f64,
+        cos_theta_e: f64,
+        two_sided: bool,
+    ) -> Self {
+        Self {
+            bounds,
+            axis,
+            power,
+            cos_theta_o,
+            cos_theta_e,
+            two_sided,
+        }
+    }
+
+    /// Bounds of a light emitting equally in all directions.
+    pub fn omnidirectional(bounds: Aabb, power: f64) -> Self {
+        Self::new(bounds, Vec3::new(0.0, 0.0, 1.0), power, -1.0, 0.0, false)
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    /// Total emitted power, in luminance.
+    pub fn power(&self) -> f64 {
+        self.power
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        let (axis, cos_theta_o) = union_cones(
+            (self.axis, self.cos_theta_o),
+            (other.axis, other.cos_theta_o),
+        );
+        Self {
+            bounds: self.bounds.surrounding(other.bounds),
+            axis,
+            power: self.power + other.power,
+            cos_theta_o,
+            cos_theta_e: self.cos_theta_e.min(other.cos_theta_e),
+            two_sided: self.two_sided || other.two_sided,
+        }
+    }
+
+    /// Estimated contribution to a point with surface `normal`, or a zero normal
+    /// for points in a medium (Conty Estevez and Kulla, "Importance Sampling of
+    /// Many Lights with Adaptive Tree Splitting", 2018).
+    fn importance(&self, point: Vec3, normal: Vec3) -> f64 {
+        let center = 0.5 * (self.bounds.min() + self.bounds.max());
+        let diagonal = (self.bounds.max() - self.bounds.min()).length();
+        let to_point = point - center;
+        let distance2 = to_point.squared_length().max(0.5 * diagonal);
+        if distance2 <= 0.0 {
+            return self.power;
+        }
+
+        let length = to_point.length();
+        let mut cos_w = if length > 0.0 {
+            to_point.dot(self.axis) / length
+        } else {
+            1.0
+        };
+        if self.two_sided {
+            cos_w = cos_w.abs();
+        }
+        let sin_w = safe_sqrt(1.0 - cos_w * cos_w);
+
+        // Angle subtended by the bounding sphere of the box.
+        let radius = 0.5 * diagonal;
+        let cos_b = if length * length <= radius * radius {
+            -1.0
+        } else {
+            safe_sqrt(1.0 - radius * radius / (length * length))
+        };
+        let sin_b = safe_sqrt(1.0 - cos_b * cos_b);
+
+        let sin_o = safe_sqrt(1.0 - self.cos_theta_o * self.cos_theta_o);
+        let cos_x = cos_sub_clamped(sin_w, cos_w, sin_o, self.cos_theta_o);
+        let sin_x = sin_sub_clamped(sin_w, cos_w, sin_o, self.cos_theta_o);
+        let cos_p = cos_sub_clamped(sin_x, cos_x, sin_b, cos_b);
+        if cos_p <= self.cos_theta_e {
+            return 0.0;
+        }
+        let mut importance = self.power * cos_p / distance2;
+
+        if normal != Vec3::default() && length > 0.0 {
+            let cos_i = (to_point / length).dot(normal).abs();
+            let sin_i = safe_sqrt(1.0 - cos_i * cos_i);
+            importance *= cos_sub_clamped(sin_i, cos_i, sin_b, cos_b);
+        }
+        importance.max(0.0)
+    }
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+/// Cosine of the difference of two angles, or one if it would be negative.
+fn cos_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b {
+        1.0
+    } else {
+        cos_a * cos_b + sin_a * sin_b
+    }
+}
+
+fn sin_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b {
+        0.0
+    } else {
+        sin_a * cos_b - cos_a * sin_b
+    }
+}
+
+/// Smallest cone containing two cones given by unit axis and cosine of half-angle.
+fn union_cones(a: (Vec3, f64), b: (Vec3, f64)) -> (Vec3, f64) {
+    let theta_a = a.1.clamp(-1.0, 1.0).acos();
+    let theta_b = b.1.clamp(-1.0, 1.0).acos();
+    let theta_d = a.0.dot(b.0).clamp(-1.0, 1.0).acos();
+    if (theta_d + theta_b).min(PI) <= theta_a {
+        return a;
+    }
+    if (theta_d + theta_a).min(PI) <= theta_b {
+        return b;
+    }
+    let theta_o = 0.5 * (theta_a + theta_d + theta_b);
+    let rotation_axis = a.0.cross(b.0);
+    if theta_o >= PI || rotation_axis.squared_length() == 0.0 {
+        return (a.0, -1.0);
+    }
+    // Rotate a's axis towards b's by theta_o - theta_a (Rodrigues' formula).
+    let k = rotation_axis.normalize();
+    let theta_r = theta_o - theta_a;
+    let axis = a.0 * theta_r.cos() + k.cross(a.0) * theta_r.sin();
+    (axis, theta_o.cos())
+}
+
+/// Picks one of the scene lights for a shading point.
+pub trait LightSampler: Send + Sync {
+    /// Returns the index of the chosen light and the probability of choosing it.
+    fn sample(&self, point: Vec3, normal: Vec3, u: f64) -> Option<(usize, f64)>;
+
+    /// Probability that `sample` chooses `light` at this point.
+    fn pmf(&self, point: Vec3, normal: Vec3, light: usize) -> f64;
+}
+
+/// How a scene picks the light to sample at each shading point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightSelection {
+    Uniform,
+    /// Proportional to each light's emitted power.
+    Power,
+    /// Proportional to each light's estimated contribution, found through a light BVH.
+    Bvh,
+}
+
+impl LightSelection {
+    pub(crate) fn build(self, lights: &[Arc<dyn Light>]) -> Box<dyn LightSampler> {
+        match self {
+            LightSelection::Uniform => Box::new(UniformLightSampler {
+                count: lights.len(),
+            }),
+            LightSelection::Power => Box::new(PowerLightSampler::new(lights)),
+            LightSelection::Bvh => Box::new(BvhLightSampler::new(lights)),
+        }
+    }
+}
+
+struct UniformLightSampler {
+    count: usize,
+}
+
+impl LightSampler for UniformLightSampler {
+    fn sample(&self, _point: Vec3, _normal: Vec3, u: f64) -> Option<(usize, f64)> {
+        if self.count == 0 {
+            return None;
+        }
+        let light = ((u * self.count as f64) as usize).min(self.count - 1);
+        Some((light, 1.0 / self.count as f64))
+    }
+
+    fn pmf(&self, _point: Vec3, _normal: Vec3, _light: usize) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            1.0 / self.count as f64
+        }
+    }
+}
+
+/// Lights without bounds (the environment, the sun, ...) are chosen uniformly
+/// with the same probability as the group of all bounded lights.
+struct Split {
+    unbounded: Vec<usize>,
+    has_bounded: bool,
+}
+
+impl Split {
+    fn p_unbounded(&self) -> f64 {
+        let n = self.unbounded.len() as f64;
+        if n == 0.0 {
+            0.0
+        } else {
+            n / (n + if self.has_bounded { 1.0 } else { 0.0 })
+        }
+    }
+
+    /// Either an unbounded light, or the remapped sample and probability of
+    /// going on to choose among bounded lights.
+    fn sample(&self, u: f64) -> Result<(usize, f64), (f64, f64)> {
+        let p = self.p_unbounded();
+        if u < p {
+            let n = self.unbounded.len();
+            let idx = ((u / p * n as f64) as usize).min(n - 1);
+            Ok((self.unbounded[idx], p / n as f64))
+        } else {
+            Err(((u - p) / (1.0 - p), 1.0 - p))
+        }
+    }
+
+    fn pmf_unbounded(&self) -> f64 {
+        self.p_unbounded() / self.unbounded.len() as f64
+    }
+}
+
+struct PowerLightSampler {
+    split: Split,
+    bounded: Vec<usize>,
+    slot: Vec<Option<usize>>,
+    distribution: Option<Distribution1D>,
+}
+
+impl PowerLightSampler {
+    fn new(lights: &[Arc<dyn Light>]) -> Self {
+        let mut unbounded = Vec::new();
+        let mut bounded = Vec::new();
+        let mut powers = Vec::new();
+        let mut slot = vec![None; lights.len()];
+        for (idx, light) in lights.iter().enumerate() {
+            match light.bounds() {
+                Some(bounds) => {
+                    slot[idx] = Some(bounded.len());
+                    bounded.push(idx);
+                    powers.push(bounds.power().max(0.0));
+                }
+                None => unbounded.push(idx),
+            }
+        }
+        Self {
+            split: Split {
+                unbounded,
+                has_bounded: !bounded.is_empty(),
+            },
+            distribution: if powers.is_empty() {
+                None
+            } else {
+                Some(Distribution1D::new(powers))
+            },
+            bounded,
+            slot,
+        }
+    }
+}
+
+impl LightSampler for PowerLightSampler {
+    fn sample(&self, _point: Vec3, _normal: Vec3, u: f64) -> Option<(usize, f64)> {
+        match self.split.sample(u) {
+            Ok(choice) => Some(choice),
+            Err((u, p)) => {
+                let distribution = self.distribution.as_ref()?;
+                let (_, _, offset) = distribution.sample_continuous(u);
+                Some((self.bounded[offset], p * distribution.segment_pdf(offset)))
+            }
+        }
+    }
+
+    fn pmf(&self, _point: Vec3, _normal: Vec3, light: usize) -> f64 {
+        match (self.slot[light], &self.distribution) {
+            (Some(offset), Some(distribution)) => {
+                (1.0 - self.split.p_unbounded()) * distribution.segment_pdf(offset)
+            }
+            _ => self.split.pmf_unbounded(),
+        }
+    }
+}
+
+enum NodeKind {
+    Leaf(usize),
+    /// Index of the second child; the first child follows the node.
+    Interior(usize),
+}
+
+struct LightBvhNode {
+    bounds: LightBounds,
+    kind: NodeKind,
+}
+
+/// Bounding volume hierarchy over lights with bounds, traversed stochastically
+/// by the importance of each child at the shading point.
+struct BvhLightSampler {
+    split: Split,
+    nodes: Vec<LightBvhNode>,
+    /// Child choices from the root to each light, one bit per level.
+    trails: Vec<Option<u64>>,
+}
+
+impl BvhLightSampler {
+    fn new(lights: &[Arc<dyn Light>]) -> Self {
+        let mut unbounded = Vec::new();
+        let mut bounded = Vec::new();
+        for (idx, light) in lights.iter().enumerate() {
+            match light.bounds() {
+                Some(bounds) if bounds.power() > 0.0 => bounded.push((idx, bounds)),
+                Some(_) => {}
+                None => unbounded.push(idx),
+            }
+        }
+        let mut sampler = Self {
+            split: Split {
+                unbounded,
+                has_bounded: !bounded.is_empty(),
+            },
+            nodes: Vec::new(),
+            trails: vec![None; lights.len()],
+        };
+        if !bounded.is_empty() {
+            sampler.build(&mut bounded, 0, 0);
+        }
+        sampler
+    }
+
+    fn build(
+        &mut self,
+        lights: &mut [(usize, LightBounds)],
+        trail: u64,
+        depth: u32,
+    ) -> LightBounds {
+        if let [(light, bounds)] = *lights {
+            self.trails[light] = Some(trail);
+            self.nodes.push(LightBvhNode {
+                bounds,
+                kind: NodeKind::Leaf(light),
+            });
+            return bounds;
+        }
+        assert!(depth < 64, "light BVH is too deep");
+
+        let centroid = |bounds: &LightBounds| 0.5 * (bounds.bounds().min() + bounds.bounds().max());
+        let (min, max) = lights.iter().fold(
+            (centroid(&lights[0].1), centroid(&lights[0].1)),
+            |(min, max), (_, bounds)| (min.min(centroid(bounds)), max.max(centroid(bounds))),
+        );
+        let extent = max - min;
+        let axis = if extent.x() >= extent.y() && extent.x() >= extent.z() {
+            0
+        } else if extent.y() >= extent.z() {
+            1
+        } else {
+            2
+        };
+        let key = |bounds: &LightBounds| {
+            let c = centroid(bounds);
+            [c.x(), c.y(), c.z()][axis]
+        };
+        lights.sort_by(|a, b| key(&a.1).total_cmp(&key(&b.1)));
+
+        let node = self.nodes.len();
+        self.nodes.push(LightBvhNode {
+            bounds: lights[0].1,
+            kind: NodeKind::Interior(0),
+        });
+        let (left, right) = lights.split_at_mut(lights.len() / 2);
+        let left_bounds = self.build(left, trail, depth + 1);
+        let second = self.nodes.len();
+        let right_bounds = self.build(right, trail | (1 << depth), depth + 1);
+        let bounds = left_bounds.union(&right_bounds);
+        self.nodes[node] = LightBvhNode {
+            bounds,
+            kind: NodeKind::Interior(second),
+        };
+        bounds
+    }
+}
+
+impl LightSampler for BvhLightSampler {
+    fn sample(&self, point: Vec3, normal: Vec3, u: f64) -> Option<(usize, f64)> {
+        let (mut u, mut pmf) = match self.split.sample(u) {
+            Ok(choice) => return Some(choice),
+            Err(remapped) => remapped,
+        };
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let mut node = 0;
+        loop {
+            match self.nodes[node].kind {
+                NodeKind::Leaf(light) => {
+                    return if self.nodes[node].bounds.importance(point, normal) > 0.0 {
+                        Some((light, pmf))
+                    } else {
+                        None
+                    };
+                }
+                NodeKind::Interior(second) => {
+                    let first_importance = self.nodes[node + 1].bounds.importance(point, normal);
+                    let second_importance = self.nodes[second].bounds.importance(point, normal);
+                    let total = first_importance + second_importance;
+                    if total <= 0.0 {
+                        return None;
+                    }
+                    let p_first = first_importance / total;
+                    if u < p_first {
+                        u = (u / p_first).min(1.0 - f64::EPSILON);
+                        pmf *= p_first;
+                        node += 1;
+                    } else {
+                        u = ((u - p_first) / (1.0 - p_first)).min(1.0 - f64::EPSILON);
+                        pmf *= 1.0 - p_first;
+                        node = second;
+                    }
+                }
+            }
+        }
+    }
+
+    fn pmf(&self, point: Vec3, normal: Vec3, light: usize) -> f64 {
+        let trail = match self.trails[light] {
+            Some(trail) => trail,
+            None if self.split.unbounded.contains(&light) => return self.split.pmf_unbounded(),
+            None => return 0.0,
+        };
+        let mut pmf = 1.0 - self.split.p_unbounded();
+        let mut node = 0;
+        let mut depth = 0;
+        while let NodeKind::Interior(second) = self.nodes[node].kind {
+            let first_importance = self.nodes[node + 1].bounds.importance(point, normal);
+            let second_importance = self.nodes[second].bounds.importance(point, normal);
+            let total = first_importance + second_importance;
+            if total <= 0.0 {
+                return 0.0;
+            }
+            if trail & (1 << depth) == 0 {
+                pmf *= first_importance / total;
+                node += 1;
+            } else {
+                pmf *= second_importance / total;
+                node = second;
+            }
+            depth += 1;
+        }
+        pmf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DiffuseLight, PointLight, Rectangle, Sphere};
+
+    fn lights() -> Vec<Arc<dyn Light>> {
+        let mut lights: Vec<Arc<dyn Light>> = Vec::new();
+        for i in 0..20 {
+            let x = f64::from(i) * 2.0;
+            lights.push(Arc::new(PointLight::new(
+                Vec3::new(x, 3.0, 0.0),
+                Vec3::new(1.0, 1.0, 1.0) * f64::from(i + 1),
+            )));
+            // Facing down, so invisible from above.
+            lights.push(Arc::new(Rectangle::new(
+                Vec3::new(x, 1.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                Arc::new(DiffuseLight::new(Vec3::new(5.0, 5.0, 5.0))),
+            )));
+        }
+        lights.push(Arc::new(Sphere::new(
+            Vec3::new(0.0, -5.0, 0.0),
+            1.0,
+            Arc::new(DiffuseLight::new(Vec3::new(2.0, 2.0, 2.0))),
+        )));
+        lights.push(Arc::new(crate::DirectionalLight::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+        )));
+        lights
+    }
+
+    fn check_pmf_sums_to_one(selection: LightSelection, point: Vec3, normal: Vec3) {
+        let lights = lights();
+        let sampler = selection.build(&lights);
+        let total: f64 = (0..lights.len())
+            .map(|light| sampler.pmf(point, normal, light))
+            .sum();
+        assert!((total - 1.0).abs() < 1e-9, "{:?} {}", selection, total);
+        let n = 1000;
+        for i in 0..n {
+            let u = (f64::from(i) + 0.5) / f64::from(n);
+            if let Some((light, pmf)) = sampler.sample(point, normal, u) {
+                assert!((sampler.pmf(point, normal, light) - pmf).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_samplers_are_consistent() {
+        let point = Vec3::new(7.0, 2.0, 0.5);
+        for &selection in &[LightSelection::Uniform, LightSelection::Power] {
+            check_pmf_sums_to_one(selection, point, Vec3::new(0.0, 1.0, 0.0));
+        }
+        // The BVH never picks lights that cannot contribute, so its pmf sums to
+        // one only where every light can; it must still match its samples.
+        check_pmf_sums_to_one(
+            LightSelection::Bvh,
+            Vec3::new(7.0, 0.0, 0.5),
+            Vec3::default(),
+        );
+    }
+
+    #[test]
+    fn test_bvh_prefers_nearby_lights() {
+        let lights = lights();
+        let sampler = LightSelection::Bvh.build(&lights);
+        let point = Vec3::new(30.0, 0.0, 0.5);
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        // Rectangle 15 is right above the point, rectangle 0 is 30 units away.
+        assert!(sampler.pmf(point, normal, 31) > 10.0 * sampler.pmf(point, normal, 1));
+        // Rectangles face down and are never chosen from above.
+        let above = Vec3::new(30.0, 2.0, 0.5);
+        assert_eq!(sampler.pmf(above, normal, 31), 0.0);
+        assert!(sampler.pmf(above, normal, 30) > 0.0);
+    }
+
+    #[test]
+    fn test_union_cones() {
+        let x = (Vec3::new(1.0, 0.0, 0.0), 1.0);
+        let y = (Vec3::new(0.0, 1.0, 0.0), 1.0);
+        let (axis, cos) = union_cones(x, y);
+        let diagonal = Vec3::new(1.0, 1.0, 0.0).normalize();
+        assert!((axis - diagonal).length() < 1e-9);
+        assert!((cos - (PI / 4.0).cos()).abs() < 1e-9);
+        assert_eq!(union_cones(x, (Vec3::new(1.0, 0.0, 0.0), 0.0)).1, 0.0);
+    }
+}
@@ -1,12 +1,11 @@
-#![feature(try_blocks)]
-
-use rayon::prelude::*;
-use std::io::Write;
+use std::io::{BufWriter, Write};
 use std::{env, io};
 
-use rand::Rng;
 use raytracer::{
-    write_ppm, Camera, Color, Hit, HitList, Image, Lambertian, Metal, Ray, Sphere, Vec3, RED,
+    render_layers, write_exr, write_pfm, write_ppm, BidirectionalPathTracer, Camera, Color,
+    DebugMode, Framebuffer, HitList, Image, Integrator, Lambertian, LightSampling, Metal,
+    MetropolisLightTransport, PathTracer, PhotonMapper, PointLight, ProgressivePhotonMapper,
+    RenderSettings, Scene, Sphere, Vec3, WhittedTracer, RED,
 };
 use std::fs::File;
 use std::sync::Arc;
@@ -30,29 +29,180 @@ fn handle_error(err: &Error) -> i32 {
     1
 }
 
-fn get_output_file_name() -> Result<Option<String>, Error> {
-    let args: Vec<_> = env::args().collect();
-    let filename = match args.len() {
-        1 => None,
-        2 => Some(args[1].to_string()),
-        _ => Err(Error::ParseError("Too many arguments".to_string()))?,
-    };
-    Ok(filename)
+struct Options {
+    output: Option<String>,
+    integrator: String,
+    /// Debug view rendered instead of the integrator's radiance.
+    mode: Option<DebugMode>,
+    /// Multi-layer `.exr` file, or prefix of one `.pfm` file per layer, to
+    /// write the output variables to.
+    aovs: Option<String>,
+    /// Samples per pixel along each axis for the Whitted tracer, which
+    /// otherwise takes one sample through each pixel center.
+    supersample: u32,
+}
+
+fn parse_options() -> Result<Options, Error> {
+    let mut output = None;
+    let mut integrator = "path".to_string();
+    let mut mode = "beauty".to_string();
+    let mut ao_radius = 0.5;
+    let mut aovs = None;
+    let mut supersample = 1;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--integrator" {
+            integrator = match args.next() {
+                Some(name) if INTEGRATORS.contains(&name.as_str()) => name,
+                _ => Err(Error::ParseError(format!(
+                    "Expected --integrator {}",
+                    INTEGRATORS.join("|")
+                )))?,
+            };
+        } else if arg == "--mode" {
+            mode = match args.next() {
+                Some(name) if MODES.contains(&name.as_str()) => name,
+                _ => Err(Error::ParseError(format!(
+                    "Expected --mode {}",
+                    MODES.join("|")
+                )))?,
+            };
+        } else if arg == "--ao-radius" {
+            ao_radius = match args.next().and_then(|radius| radius.parse::<f64>().ok()) {
+                Some(radius) if radius > 0.0 => radius,
+                _ => Err(Error::ParseError(
+                    "Expected --ao-radius <distance>".to_string(),
+                ))?,
+            };
+        } else if arg == "--aovs" {
+            aovs = match args.next() {
+                Some(path) => Some(path),
+                None => Err(Error::ParseError(
+                    "Expected --aovs <file.exr|prefix>".to_string(),
+                ))?,
+            };
+        } else if arg == "--supersample" {
+            supersample = match args.next().and_then(|n| n.parse::<u32>().ok()) {
+                Some(n) if n > 0 => n,
+                _ => Err(Error::ParseError(
+                    "Expected --supersample <samples per axis>".to_string(),
+                ))?,
+            };
+        } else if output.is_none() {
+            output = Some(arg);
+        } else {
+            Err(Error::ParseError("Too many arguments".to_string()))?;
+        }
+    }
+    let mode = debug_mode(&mode, ao_radius);
+    Ok(Options {
+        output,
+        integrator,
+        mode,
+        aovs,
+        supersample,
+    })
+}
+
+const MAX_DEPTH: u32 = 50;
+const INTEGRATORS: [&str; 6] = ["path", "bdpt", "photon", "sppm", "mlt", "whitted"];
+const MODES: [&str; 7] = [
+    "beauty", "ao", "normal", "depth", "albedo", "object", "material",
+];
+/// Depth shown as white in the depth view.
+const DEPTH_RANGE: f64 = 5.0;
+
+fn debug_mode(name: &str, ao_radius: f64) -> Option<DebugMode> {
+    match name {
+        "ao" => Some(DebugMode::AmbientOcclusion(ao_radius)),
+        "normal" => Some(DebugMode::Normal),
+        "depth" => Some(DebugMode::Depth(DEPTH_RANGE)),
+        "albedo" => Some(DebugMode::Albedo),
+        "object" => Some(DebugMode::ObjectId),
+        "material" => Some(DebugMode::MaterialId),
+        _ => None,
+    }
+}
+
+/// Photon mapping integrators trace photons through the scene up front.
+fn make_integrator(name: &str, scene: &Scene) -> Integrator {
+    match name {
+        "bdpt" => Integrator::Bidirectional(BidirectionalPathTracer::new(MAX_DEPTH)),
+        "photon" => Integrator::PhotonMap(PhotonMapper::new(
+            scene,
+            200_000,
+            50,
+            MAX_DEPTH,
+            &mut rand::thread_rng(),
+        )),
+        "sppm" => {
+            Integrator::ProgressivePhotonMap(ProgressivePhotonMapper::new(50_000, 0.1, MAX_DEPTH))
+        }
+        "mlt" => Integrator::Metropolis(MetropolisLightTransport::new(PathTracer::new(
+            MAX_DEPTH,
+            LightSampling::Mis,
+        ))),
+        "whitted" => {
+            let mut tracer = WhittedTracer::new(MAX_DEPTH);
+            tracer.set_ambient(Vec3::new(0.2, 0.2, 0.2));
+            Integrator::Whitted(tracer)
+        }
+        _ => Integrator::Path(PathTracer::new(MAX_DEPTH, LightSampling::Mis)),
+    }
 }
 
-fn draw_sphere<W: Write>(output: &mut W) -> Result<(), Error> {
+/// Writes every layer of `framebuffer` to `path` if it ends in `.exr`, or
+/// else to `<path>.<layer>.pfm` files.
+fn write_aovs(framebuffer: &Framebuffer, path: &str) -> Result<(), Error> {
+    if path.ends_with(".exr") {
+        write_exr(framebuffer, &mut BufWriter::new(File::create(path)?))?;
+    } else {
+        for (name, layer) in framebuffer.layers() {
+            let filename = format!("{}.{}.pfm", path, name);
+            write_pfm(layer, &mut BufWriter::new(File::create(filename)?))?;
+        }
+    }
+    Ok(())
+}
+
+fn draw_sphere<W: Write>(options: &Options, output: &mut W) -> Result<(), Error> {
     let camera = make_camera();
-    let world = make_world();
+    let mut scene = Scene::new(make_world());
+    if options.integrator == "whitted" {
+        // Whitted tracing is lit by point lights only, and the sky is not one.
+        scene.add_light(Arc::new(PointLight::new(
+            Vec3::new(-2.0, 3.0, 1.0),
+            Vec3::new(15.0, 15.0, 15.0),
+        )));
+    }
+    let (integrator, samples) = match options.mode {
+        Some(mode) => (Integrator::Debug(mode), N_SAMPLES),
+        None if options.integrator == "whitted" => (
+            make_integrator(&options.integrator, &scene),
+            options.supersample * options.supersample,
+        ),
+        None => (make_integrator(&options.integrator, &scene), N_SAMPLES),
+    };
+    let settings = RenderSettings::new(IMAGE_WIDTH, IMAGE_HEIGHT, samples, integrator);
+    let framebuffer = render_layers(&scene, &camera, &settings);
+    if let Some(path) = &options.aovs {
+        write_aovs(&framebuffer, path)?;
+    }
+    let radiance = framebuffer
+        .layer(Framebuffer::BEAUTY)
+        .expect("beauty layer");
     let mut image = Image::with_background(IMAGE_WIDTH, IMAGE_HEIGHT, RED);
-    image
-        .pixels()
-        .enumerate()
-        .for_each(|(idx, pixel)| *pixel = calc_pixel(idx, &camera, &world));
+    for y in 0..IMAGE_HEIGHT {
+        for x in 0..IMAGE_WIDTH {
+            image[(x, y)] = vec_to_color(radiance[(x, y)]);
+        }
+    }
     write_ppm(image, output)?;
     return Ok(());
 
     const IMAGE_WIDTH: u32 = 200;
     const IMAGE_HEIGHT: u32 = 100;
+    const N_SAMPLES: u32 = 100;
 
     fn make_camera() -> Camera {
         Camera::new(
@@ -88,64 +238,26 @@ fn draw_sphere<W: Write>(output: &mut W) -> Result<(), Error> {
         world
     }
 
-    fn calc_pixel(idx: usize, camera: &Camera, world: &HitList) -> Color {
-        const N_SAMPLES: u32 = 100;
-        let mut rng = rand::thread_rng();
-        let x = idx as u32 % IMAGE_WIDTH;
-        let y = idx as u32 / IMAGE_WIDTH;
-        let mut acc = Vec3::default();
-        for _ in 0..N_SAMPLES {
-            let u = (f64::from(x) + rng.gen::<f64>()) / f64::from(IMAGE_WIDTH);
-            let v = (f64::from(y) + rng.gen::<f64>()) / f64::from(IMAGE_HEIGHT);
-            let ray = camera.get_ray(u, v);
-            acc += color_vec_at(&ray, world, 0);
-        }
-        return vec_to_color(acc / f64::from(N_SAMPLES));
-
-        fn color_vec_at<T: Hit>(ray: &Ray, world: &T, depth: u32) -> Vec3 {
-            const MAX_DEPTH: u32 = 50;
-            if let Some(hit) = world.hit(ray, 0.001, std::f64::MAX) {
-                if depth >= MAX_DEPTH {
-                    return Vec3::default();
-                }
-
-                if let Some(scattered) = hit.material().scatter(ray, &hit) {
-                    scattered.attenuation() * color_vec_at(&scattered.ray(), world, depth + 1)
-                } else {
-                    Vec3::default()
-                }
-            } else {
-                let unit_direction = ray.direction().normalize();
-                let t = 0.5 * (unit_direction.y() + 1.0);
-                (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
-            }
-        }
+    fn vec_to_color(vec: Vec3) -> Color {
+        const COLOR_SCALE: f64 = 254.99;
+        let vec = COLOR_SCALE * Vec3::new(vec.x().sqrt(), vec.y().sqrt(), vec.z().sqrt());
+        Color::new(vec.x() as u8, vec.y() as u8, vec.z() as u8)
+    }
+}
 
-        fn vec_to_color(vec: Vec3) -> Color {
-            const COLOR_SCALE: f64 = 254.99;
-            let vec = COLOR_SCALE * Vec3::new(vec.x().sqrt(), vec.y().sqrt(), vec.z().sqrt());
-            Color::new(vec.x() as u8, vec.y() as u8, vec.z() as u8)
-        }
+fn run() -> Result<(), Error> {
+    let options = parse_options()?;
+    if let Some(filename) = &options.output {
+        draw_sphere(&options, &mut File::create(filename)?)
+    } else {
+        draw_sphere(&options, &mut io::stdout().lock())
     }
 }
 
 fn main() {
-    let exit_code = {
-        let res = try {
-            if let Some(filename) = get_output_file_name()? {
-                draw_sphere(&mut File::create(filename)?)
-            } else {
-                draw_sphere(&mut io::stdout().lock())
-            }?
-        };
-
-        match res {
-            Ok(()) => 0,
-            Err(err) => {
-                handle_error(&err);
-                1
-            }
-        }
+    let exit_code = match run() {
+        Ok(()) => 0,
+        Err(err) => handle_error(&err),
     };
 
     std::process::exit(exit_code)
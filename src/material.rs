@@ -1,6 +1,12 @@
 use crate::hit::HitRecord;
+use crate::microfacet::fresnel_dielectric;
+use crate::spectrum::{Ior, REFERENCE_WAVELENGTH};
+use crate::texture::{SolidColor, Texture};
 use crate::{Ray, Vec3};
-use rand::distributions::{Distribution, UnitSphereSurface};
+use rand::Rng;
+use std::f64::consts::PI;
+use std::ops::BitOr;
+use std::sync::Arc;
 
 pub struct ScatteredRay {
     ray: Ray,
@@ -21,59 +27,602 @@ impl ScatteredRay {
     }
 }
 
+/// Kind of scattering a BSDF sample came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lobe(u8);
+
+impl Lobe {
+    pub const REFLECTION: Lobe = Lobe(1);
+    pub const TRANSMISSION: Lobe = Lobe(1 << 1);
+    pub const DIFFUSE: Lobe = Lobe(1 << 2);
+    pub const GLOSSY: Lobe = Lobe(1 << 3);
+    /// Delta distribution: `eval` and `pdf` are zero for every direction.
+    pub const SPECULAR: Lobe = Lobe(1 << 4);
+
+    pub fn contains(self, other: Lobe) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_delta(self) -> bool {
+        self.contains(Lobe::SPECULAR)
+    }
+}
+
+impl BitOr for Lobe {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Lobe(self.0 | rhs.0)
+    }
+}
+
+/// Direction sampled from a BSDF together with the BSDF value and the sampling density.
+///
+/// For delta lobes `pdf` is one and `value` already contains the `1 / |cos|` factor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BsdfSample {
+    wi: Vec3,
+    value: Vec3,
+    pdf: f64,
+    lobe: Lobe,
+}
+
+impl BsdfSample {
+    pub fn new(wi: Vec3, value: Vec3, pdf: f64, lobe: Lobe) -> Self {
+        Self {
+            wi,
+            value,
+            pdf,
+            lobe,
+        }
+    }
+
+    pub fn wi(&self) -> Vec3 {
+        self.wi
+    }
+
+    pub fn value(&self) -> Vec3 {
+        self.value
+    }
+
+    pub fn pdf(&self) -> f64 {
+        self.pdf
+    }
+
+    pub fn lobe(&self) -> Lobe {
+        self.lobe
+    }
+}
+
+/// Surface material described by its BSDF.
+///
+/// Directions are unit vectors in world space pointing away from the hit point:
+/// `wo` towards the viewer, `wi` towards the light.
 pub trait Scatter: Send + Sync {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatteredRay>;
+    fn eval(&self, hit: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3;
+
+    /// Samples `wi` using `uc` to pick a lobe and `u` to pick a direction in it.
+    fn sample(&self, hit: &HitRecord, wo: Vec3, uc: f64, u: (f64, f64)) -> Option<BsdfSample>;
+
+    /// Solid angle density with which `sample` returns `wi`.
+    fn pdf(&self, hit: &HitRecord, wo: Vec3, wi: Vec3) -> f64;
+
+    /// Radiance emitted from the hit point towards `wo`.
+    fn emitted(&self, _hit: &HitRecord, _wo: Vec3) -> Vec3 {
+        Vec3::default()
+    }
+
+    /// Whether objects with this material should be registered as lights.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Whether the surface only bounds a participating medium, letting rays
+    /// through unchanged.
+    fn is_interface(&self) -> bool {
+        false
+    }
+
+    /// Whether sampled directions depend on the wavelength of the light.
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    /// Whether every lobe is a delta distribution, so that `eval` is zero
+    /// for all directions.
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatteredRay> {
+        let mut rng = rand::thread_rng();
+        let wo = -ray.direction().normalize();
+        let sample = self.sample(hit, wo, rng.gen(), (rng.gen(), rng.gen()))?;
+        if sample.pdf() <= 0.0 {
+            return None;
+        }
+        let attenuation = sample.value() * (sample.wi().dot(hit.normal()).abs() / sample.pdf());
+        Some(ScatteredRay::new(
+            Ray::new(hit.point(), sample.wi(), ray.time()),
+            attenuation,
+        ))
+    }
+}
+
+/// Orthonormal shading frame with the normal along local `+z`.
+pub(crate) struct Frame {
+    tangent: Vec3,
+    bitangent: Vec3,
+    normal: Vec3,
+}
+
+impl Frame {
+    pub(crate) fn new(normal: Vec3) -> Self {
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        Self {
+            tangent,
+            bitangent,
+            normal,
+        }
+    }
+
+    /// Frame around the hit normal, flipped to the side `wo` is on.
+    pub(crate) fn facing(hit: &HitRecord, wo: Vec3) -> Self {
+        let normal = hit.normal();
+        Self::new(if normal.dot(wo) < 0.0 {
+            -normal
+        } else {
+            normal
+        })
+    }
+
+    pub(crate) fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            v.dot(self.tangent),
+            v.dot(self.bitangent),
+            v.dot(self.normal),
+        )
+    }
+
+    pub(crate) fn to_world(&self, v: Vec3) -> Vec3 {
+        v.x() * self.tangent + v.y() * self.bitangent + v.z() * self.normal
+    }
 }
 
 pub struct Lambertian {
-    albedo: Vec3,
+    albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Vec3) -> Self {
+        Self::with_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn with_texture(albedo: Arc<dyn Texture>) -> Self {
         Lambertian { albedo }
     }
 }
 
 impl Scatter for Lambertian {
-    fn scatter(&self, _ray: &Ray, hit: &HitRecord) -> Option<ScatteredRay> {
-        let target = hit.point() + hit.normal() + random_in_unit_sphere();
-        Some(ScatteredRay::new(
-            Ray::new(hit.point(), target - hit.point()),
-            self.albedo,
+    fn eval(&self, hit: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        if Frame::facing(hit, wo).to_local(wi).z() <= 0.0 {
+            return Vec3::default();
+        }
+        albedo_at(&*self.albedo, hit) / PI
+    }
+
+    fn sample(&self, hit: &HitRecord, wo: Vec3, _uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        let local = cosine_hemisphere(u);
+        if local.z() <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample::new(
+            Frame::facing(hit, wo).to_world(local),
+            albedo_at(&*self.albedo, hit) / PI,
+            local.z() / PI,
+            Lobe::DIFFUSE | Lobe::REFLECTION,
         ))
     }
+
+    fn pdf(&self, hit: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        Frame::facing(hit, wo).to_local(wi).z().max(0.0) / PI
+    }
 }
 
+/// Mirror reflection perturbed by a random offset of length `fuzz`.
+///
+/// With zero `fuzz` the reflection is a delta lobe.
 pub struct Metal {
-    albedo: Vec3,
+    albedo: Arc<dyn Texture>,
     fuzz: f64,
 }
 
 impl Metal {
     pub fn new(albedo: Vec3, fuzz: f64) -> Self {
+        Self::with_texture(Arc::new(SolidColor::new(albedo)), fuzz)
+    }
+
+    pub fn with_texture(albedo: Arc<dyn Texture>, fuzz: f64) -> Self {
         Metal { albedo, fuzz }
     }
+
+    /// Density of `normalize(r + fuzz * s)` for `s` uniform on the unit sphere:
+    /// the projection of the fuzz sphere around the tip of `r` onto directions.
+    fn fuzz_pdf(&self, reflected: Vec3, wi: Vec3) -> f64 {
+        let b = wi.dot(reflected);
+        let discriminant = b * b - 1.0 + self.fuzz * self.fuzz;
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+        let sqrt_d = discriminant.sqrt();
+        [b - sqrt_d, b + sqrt_d]
+            .iter()
+            .filter(|&&t| t > 0.0)
+            .map(|&t| t * t / (4.0 * PI * self.fuzz * sqrt_d))
+            .sum()
+    }
 }
 
 impl Scatter for Metal {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<ScatteredRay> {
-        let reflected = reflect(ray.direction().normalize(), hit.normal());
-        let scattered = Ray::new(hit.point(), reflected + self.fuzz * random_in_unit_sphere());
-        if scattered.direction().dot(hit.normal()) > 0.0 {
-            Some(ScatteredRay::new(scattered, self.albedo))
+    fn eval(&self, hit: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        if self.fuzz <= 0.0 {
+            return Vec3::default();
+        }
+        let frame = Frame::facing(hit, wo);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        if wi.z() <= 0.0 {
+            return Vec3::default();
+        }
+        let reflected = Vec3::new(-wo.x(), -wo.y(), wo.z());
+        albedo_at(&*self.albedo, hit) * (self.fuzz_pdf(reflected, wi) / wi.z())
+    }
+
+    fn sample(&self, hit: &HitRecord, wo: Vec3, _uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        let frame = Frame::facing(hit, wo);
+        let wo = frame.to_local(wo);
+        let reflected = Vec3::new(-wo.x(), -wo.y(), wo.z());
+        let albedo = albedo_at(&*self.albedo, hit);
+        if self.fuzz <= 0.0 {
+            if reflected.z() <= 0.0 {
+                return None;
+            }
+            return Some(BsdfSample::new(
+                frame.to_world(reflected),
+                albedo / reflected.z(),
+                1.0,
+                Lobe::SPECULAR | Lobe::REFLECTION,
+            ));
+        }
+        let wi = (reflected + self.fuzz * uniform_sphere(u)).normalize();
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        let pdf = self.fuzz_pdf(reflected, wi);
+        Some(BsdfSample::new(
+            frame.to_world(wi),
+            albedo * (pdf / wi.z()),
+            pdf,
+            Lobe::GLOSSY | Lobe::REFLECTION,
+        ))
+    }
+
+    fn pdf(&self, hit: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        if self.fuzz <= 0.0 {
+            return 0.0;
+        }
+        let frame = Frame::facing(hit, wo);
+        let (wo, wi) = (frame.to_local(wo), frame.to_local(wi));
+        if wi.z() <= 0.0 {
+            return 0.0;
+        }
+        self.fuzz_pdf(Vec3::new(-wo.x(), -wo.y(), wo.z()), wi)
+    }
+
+    fn is_specular(&self) -> bool {
+        self.fuzz <= 0.0
+    }
+}
+
+/// Kajiya-Kay hair shading: a diffuse term around the fiber plus a specular
+/// highlight on the cone of mirror directions around the tangent.
+///
+/// Both lobes are normalized so that the average reflectance over the sphere
+/// approximately matches the given colors. Directions are sampled uniformly
+/// over the sphere, so light can pass through the fiber.
+pub struct KajiyaKay {
+    diffuse: Vec3,
+    specular: Vec3,
+    exponent: f64,
+}
+
+impl KajiyaKay {
+    pub fn new(diffuse: Vec3, specular: Vec3, exponent: f64) -> Self {
+        KajiyaKay {
+            diffuse,
+            specular,
+            exponent,
+        }
+    }
+}
+
+impl Scatter for KajiyaKay {
+    fn eval(&self, hit: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        let tangent = hit.tangent();
+        let cos_tl = tangent.dot(wi);
+        let cos_tv = tangent.dot(wo);
+        let sin_tl = (1.0 - cos_tl * cos_tl).max(0.0).sqrt();
+        let sin_tv = (1.0 - cos_tv * cos_tv).max(0.0).sqrt();
+        let highlight = (sin_tl * sin_tv - cos_tl * cos_tv).max(0.0);
+        let specular_norm = (2.0 * self.exponent.max(1.0) / PI).sqrt();
+        // The integrator applies |cos| against the normal, which averages to 1/2 over the sphere.
+        (4.0 / PI * sin_tl * self.diffuse
+            + specular_norm * highlight.powf(self.exponent) * self.specular)
+            / (2.0 * PI)
+    }
+
+    fn sample(&self, hit: &HitRecord, wo: Vec3, _uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        if hit.tangent() == Vec3::default() {
+            return None;
+        }
+        let wi = uniform_sphere(u);
+        Some(BsdfSample::new(
+            wi,
+            self.eval(hit, wo, wi),
+            1.0 / (4.0 * PI),
+            Lobe::DIFFUSE | Lobe::GLOSSY | Lobe::REFLECTION | Lobe::TRANSMISSION,
+        ))
+    }
+
+    fn pdf(&self, hit: &HitRecord, _wo: Vec3, _wi: Vec3) -> f64 {
+        if hit.tangent() == Vec3::default() {
+            0.0
         } else {
-            None
+            1.0 / (4.0 * PI)
         }
     }
 }
 
-fn random_in_unit_sphere() -> Vec3 {
-    let sphere = UnitSphereSurface::new();
-    let [x, y, z] = sphere.sample(&mut rand::thread_rng());
-    Vec3::new(x, y, z)
+/// Area light material emitting from the side the surface normal points to.
+pub struct DiffuseLight {
+    emit: Arc<dyn Texture>,
 }
 
-fn reflect(v: Vec3, n: Vec3) -> Vec3 {
+impl DiffuseLight {
+    pub fn new(radiance: Vec3) -> Self {
+        Self::with_texture(Arc::new(SolidColor::new(radiance)))
+    }
+
+    pub fn with_texture(emit: Arc<dyn Texture>) -> Self {
+        DiffuseLight { emit }
+    }
+}
+
+impl Scatter for DiffuseLight {
+    fn eval(&self, _hit: &HitRecord, _wo: Vec3, _wi: Vec3) -> Vec3 {
+        Vec3::default()
+    }
+
+    fn sample(&self, _hit: &HitRecord, _wo: Vec3, _uc: f64, _u: (f64, f64)) -> Option<BsdfSample> {
+        None
+    }
+
+    fn pdf(&self, _hit: &HitRecord, _wo: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    fn emitted(&self, hit: &HitRecord, wo: Vec3) -> Vec3 {
+        if hit.normal().dot(wo) > 0.0 {
+            self.emit.value(hit.uv(), hit.point())
+        } else {
+            Vec3::default()
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
+/// Smooth glass-like boundary that reflects and refracts by the Fresnel equations.
+pub struct Dielectric {
+    ior: Ior,
+}
+
+impl Dielectric {
+    /// `ior` is the index of refraction inside relative to outside the surface.
+    pub fn new(ior: f64) -> Self {
+        Self::with_dispersion(Ior::Constant(ior))
+    }
+
+    /// Glass whose index varies with wavelength, splitting white light into
+    /// colors when rendering spectrally.
+    pub fn with_dispersion(ior: Ior) -> Self {
+        Self { ior }
+    }
+}
+
+impl Scatter for Dielectric {
+    fn eval(&self, _hit: &HitRecord, _wo: Vec3, _wi: Vec3) -> Vec3 {
+        Vec3::default()
+    }
+
+    fn sample(&self, hit: &HitRecord, wo: Vec3, uc: f64, _u: (f64, f64)) -> Option<BsdfSample> {
+        let normal = hit.normal();
+        let cos_o = wo.dot(normal);
+        let ior = self
+            .ior
+            .at(hit.wavelength().unwrap_or(REFERENCE_WAVELENGTH));
+        let reflectance = fresnel_dielectric(cos_o, ior);
+        let one = Vec3::new(1.0, 1.0, 1.0);
+        if uc < reflectance {
+            let wi = reflect(-wo, normal);
+            return Some(BsdfSample::new(
+                wi,
+                one * (reflectance / cos_o.abs()),
+                reflectance,
+                Lobe::SPECULAR | Lobe::REFLECTION,
+            ));
+        }
+        let wi = refract(wo, normal, ior)?;
+        let transmittance = 1.0 - reflectance;
+        Some(BsdfSample::new(
+            wi,
+            one * (transmittance / wi.dot(normal).abs()),
+            transmittance,
+            Lobe::SPECULAR | Lobe::TRANSMISSION,
+        ))
+    }
+
+    fn pdf(&self, _hit: &HitRecord, _wo: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.ior.is_dispersive()
+    }
+
+    fn is_specular(&self) -> bool {
+        true
+    }
+}
+
+pub(crate) fn albedo_at(texture: &dyn Texture, hit: &HitRecord) -> Vec3 {
+    let albedo = texture.value(hit.uv(), hit.point());
+    hit.color().map_or(albedo, |color| albedo * color)
+}
+
+pub(crate) fn cosine_hemisphere((u1, u2): (f64, f64)) -> Vec3 {
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u1).max(0.0).sqrt())
+}
+
+pub(crate) fn uniform_sphere((u1, u2): (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u1;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+pub(crate) fn reflect(v: Vec3, n: Vec3) -> Vec3 {
     v - 2.0 * v.dot(n) * n
 }
+
+/// Refracts `wo` (pointing away from the surface) through a boundary with
+/// relative index `ior` on the side opposite to `n`; `None` on total internal reflection.
+pub(crate) fn refract(wo: Vec3, n: Vec3, ior: f64) -> Option<Vec3> {
+    let cos_o = wo.dot(n);
+    let (n, cos_o, eta) = if cos_o >= 0.0 {
+        (n, cos_o, 1.0 / ior)
+    } else {
+        (-n, -cos_o, ior)
+    };
+    let sin2_t = eta * eta * (1.0 - cos_o * cos_o);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-eta * wo + (eta * cos_o - cos_t) * n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lobe_flags() {
+        let lobe = Lobe::SPECULAR | Lobe::REFLECTION;
+        assert!(lobe.is_delta());
+        assert!(lobe.contains(Lobe::REFLECTION));
+        assert!(!lobe.contains(Lobe::TRANSMISSION));
+        assert!(!(Lobe::DIFFUSE | Lobe::REFLECTION).is_delta());
+    }
+
+    #[test]
+    fn test_lambertian_is_cosine_weighted() {
+        let material = Lambertian::new(Vec3::new(0.5, 0.5, 0.5));
+        let hit = HitRecord::new(1.0, Vec3::default(), Vec3::new(0.0, 1.0, 0.0), &material);
+        let wo = Vec3::new(0.0, 1.0, 0.0);
+        let sample = material.sample(&hit, wo, 0.5, (0.3, 0.7)).unwrap();
+        let cos = sample.wi().y();
+        assert!((sample.pdf() - cos / PI).abs() < 1e-12);
+        assert!((material.pdf(&hit, wo, sample.wi()) - sample.pdf()).abs() < 1e-12);
+        assert_eq!(material.eval(&hit, wo, sample.wi()), sample.value());
+        assert_eq!(
+            material.eval(&hit, wo, Vec3::new(0.0, -1.0, 0.0)),
+            Vec3::default()
+        );
+    }
+
+    #[test]
+    fn test_metal_fuzz_pdf_is_normalized() {
+        for &fuzz in &[0.3, 1.0, 1.7] {
+            let metal = Metal::new(Vec3::new(1.0, 1.0, 1.0), fuzz);
+            let reflected = Vec3::new(0.0, 0.0, 1.0);
+            // Integrate over the angle from `reflected`; substituting `theta = max * (1 - s^2)`
+            // removes the integrable singularity at the edge of the cone.
+            let max_theta = if fuzz < 1.0 { f64::asin(fuzz) } else { PI };
+            let n = 100_000;
+            let mut integral = 0.0;
+            for i in 0..n {
+                let s = (f64::from(i) + 0.5) / f64::from(n);
+                let theta = max_theta * (1.0 - s * s);
+                let wi = Vec3::new(theta.sin(), 0.0, theta.cos());
+                let jacobian = 2.0 * max_theta * s / f64::from(n);
+                integral += metal.fuzz_pdf(reflected, wi) * 2.0 * PI * theta.sin() * jacobian;
+            }
+            assert!((integral - 1.0).abs() < 1e-3, "{} {}", fuzz, integral);
+        }
+    }
+
+    #[test]
+    fn test_mirror_is_delta() {
+        let metal = Metal::new(Vec3::new(0.9, 0.9, 0.9), 0.0);
+        let hit = HitRecord::new(1.0, Vec3::default(), Vec3::new(0.0, 1.0, 0.0), &metal);
+        let wo = Vec3::new(1.0, 1.0, 0.0).normalize();
+        let sample = metal.sample(&hit, wo, 0.5, (0.5, 0.5)).unwrap();
+        assert!(sample.lobe().is_delta());
+        assert!((sample.wi() - Vec3::new(-1.0, 1.0, 0.0).normalize()).length() < 1e-12);
+        assert_eq!(metal.pdf(&hit, wo, sample.wi()), 0.0);
+    }
+
+    #[test]
+    fn test_metal_reflects_on_back_face() {
+        // Seen from below a normal pointing up, as on the inside of a closed mesh.
+        let wo = Vec3::new(1.0, -1.0, 0.0).normalize();
+        let mirror = Metal::new(Vec3::new(0.9, 0.9, 0.9), 0.0);
+        let hit = HitRecord::new(1.0, Vec3::default(), Vec3::new(0.0, 1.0, 0.0), &mirror);
+        let sample = mirror.sample(&hit, wo, 0.5, (0.5, 0.5)).unwrap();
+        assert!((sample.wi() - Vec3::new(-1.0, -1.0, 0.0).normalize()).length() < 1e-12);
+
+        let fuzzy = Metal::new(Vec3::new(0.9, 0.9, 0.9), 0.3);
+        let hit = HitRecord::new(1.0, Vec3::default(), Vec3::new(0.0, 1.0, 0.0), &fuzzy);
+        let sample = fuzzy.sample(&hit, wo, 0.5, (0.3, 0.7)).unwrap();
+        assert!(sample.wi().y() < 0.0);
+        assert!((fuzzy.pdf(&hit, wo, sample.wi()) - sample.pdf()).abs() < 1e-12);
+        assert_eq!(fuzzy.eval(&hit, wo, sample.wi()), sample.value());
+    }
+
+    #[test]
+    fn test_dielectric_refraction() {
+        let glass = Dielectric::new(1.5);
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let hit = HitRecord::new(1.0, Vec3::default(), normal, &glass);
+        let wo = Vec3::new(1.0, 1.0, 0.0).normalize();
+        let reflected = glass.sample(&hit, wo, 0.0, (0.5, 0.5)).unwrap();
+        let refracted = glass.sample(&hit, wo, 0.99, (0.5, 0.5)).unwrap();
+        assert!(reflected.lobe().contains(Lobe::REFLECTION));
+        assert!(refracted.lobe().contains(Lobe::TRANSMISSION));
+        assert!((reflected.pdf() + refracted.pdf() - 1.0).abs() < 1e-12);
+        // Snell's law: sin(45 degrees) = 1.5 sin(theta_t).
+        let sin_t = (1.0 - refracted.wi().y() * refracted.wi().y()).sqrt();
+        assert!((sin_t * 1.5 - 0.5f64.sqrt()).abs() < 1e-12);
+        assert!(refracted.wi().x() < 0.0 && refracted.wi().y() < 0.0);
+        // Every sample carries unit weight.
+        let weight = refracted.value() * (refracted.wi().y().abs() / refracted.pdf());
+        assert!((weight - Vec3::new(1.0, 1.0, 1.0)).length() < 1e-12);
+        // Leaving at a grazing angle is total internal reflection.
+        let inside = Vec3::new(0.9, -0.1, 0.0).normalize();
+        assert!(refract(inside, normal, 1.5).is_none());
+    }
+}
@@ -0,0 +1,306 @@
+use crate::hit::HitRecord;
+use crate::light::Light;
+use crate::material::{BsdfSample, Scatter};
+use crate::{Aabb, Hit, Ray, Vec3};
+use rand::{Rng, RngCore};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Henyey-Greenstein phase function; `g` in `(-1, 1)` is the mean cosine of
+/// the scattering angle, zero for isotropic scattering.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HenyeyGreenstein {
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f64) -> Self {
+        Self {
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    pub fn isotropic() -> Self {
+        Self::new(0.0)
+    }
+
+    /// Density of scattering from the direction opposite to `wo` into `wi`;
+    /// like BSDFs, both point away from the scattering point.
+    pub fn p(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let g = self.g;
+        let denom = 1.0 + g * g + 2.0 * g * wo.dot(wi);
+        (1.0 - g * g) / (4.0 * PI * denom * denom.max(1e-12).sqrt())
+    }
+
+    /// Samples `wi` exactly proportional to `p`, returning it with its density.
+    pub fn sample(&self, wo: Vec3, (u1, u2): (f64, f64)) -> (Vec3, f64) {
+        let g = self.g;
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u1
+        } else {
+            let sqr_term = (1.0 - g * g) / (1.0 + g - 2.0 * g * u1);
+            -(1.0 + g * g - sqr_term * sqr_term) / (2.0 * g)
+        }
+        .clamp(-1.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        let (s, t) = wo.orthonormal_basis();
+        let wi = s * (sin_theta * phi.cos()) + t * (sin_theta * phi.sin()) + wo * cos_theta;
+        (wi, self.p(wo, wi))
+    }
+}
+
+/// Outcome of sampling a free-flight distance along a ray in a medium.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MediumSample {
+    t: Option<f64>,
+    weight: Vec3,
+}
+
+impl MediumSample {
+    pub fn new(t: Option<f64>, weight: Vec3) -> Self {
+        Self { t, weight }
+    }
+
+    /// Ray parameter of the scattering event, or `None` if the ray passed through.
+    pub fn t(&self) -> Option<f64> {
+        self.t
+    }
+
+    /// Throughput weight: transmittance over the sampling density, times the
+    /// scattering coefficient when the ray scattered.
+    pub fn weight(&self) -> Vec3 {
+        self.weight
+    }
+}
+
+/// Participating medium filling the inside of some boundary.
+pub trait Medium: Send + Sync {
+    /// Fraction of light that travels along `ray` from `t = 0` to `t_max`
+    /// without being absorbed or scattered.
+    fn transmittance(&self, ray: &Ray, t_max: f64, rng: &mut dyn RngCore) -> Vec3;
+
+    /// Samples where `ray` first scatters before `t_max`, if it does.
+    fn sample(&self, ray: &Ray, t_max: f64, rng: &mut dyn RngCore) -> MediumSample;
+
+    fn phase(&self) -> HenyeyGreenstein;
+}
+
+/// Medium with the same absorption and scattering coefficients everywhere.
+pub struct HomogeneousMedium {
+    sigma_a: Vec3,
+    sigma_s: Vec3,
+    phase: HenyeyGreenstein,
+}
+
+impl HomogeneousMedium {
+    /// Coefficients are per unit length and per color channel.
+    pub fn new(sigma_a: Vec3, sigma_s: Vec3, g: f64) -> Self {
+        Self {
+            sigma_a,
+            sigma_s,
+            phase: HenyeyGreenstein::new(g),
+        }
+    }
+
+    /// Isotropic medium given by its extinction `density` and single-scattering `albedo`.
+    pub fn isotropic(density: f64, albedo: Vec3) -> Self {
+        let one = Vec3::new(1.0, 1.0, 1.0);
+        Self::new(density * (one - albedo), density * albedo, 0.0)
+    }
+
+    fn sigma_t(&self) -> Vec3 {
+        self.sigma_a + self.sigma_s
+    }
+}
+
+fn exp(v: Vec3) -> Vec3 {
+    Vec3::new(v.x().exp(), v.y().exp(), v.z().exp())
+}
+
+pub(crate) fn average(v: Vec3) -> f64 {
+    (v.x() + v.y() + v.z()) / 3.0
+}
+
+impl Medium for HomogeneousMedium {
+    fn transmittance(&self, ray: &Ray, t_max: f64, _rng: &mut dyn RngCore) -> Vec3 {
+        let distance = (t_max * ray.direction().length()).min(f64::MAX);
+        exp(-self.sigma_t() * distance)
+    }
+
+    fn sample(&self, ray: &Ray, t_max: f64, rng: &mut dyn RngCore) -> MediumSample {
+        // Pick a channel uniformly and sample its exponential free flight; the
+        // density is the average over the channels.
+        let sigma_t = self.sigma_t();
+        let channels = [sigma_t.x(), sigma_t.y(), sigma_t.z()];
+        let sigma = channels[rng.gen_range(0, 3)];
+        let speed = ray.direction().length();
+        let t_max_distance = t_max * speed;
+        let distance = if sigma > 0.0 {
+            -(1.0 - rng.gen::<f64>()).ln() / sigma
+        } else {
+            f64::INFINITY
+        };
+        if distance < t_max_distance {
+            let transmittance = exp(-sigma_t * distance);
+            let pdf = average(sigma_t * transmittance);
+            MediumSample::new(Some(distance / speed), self.sigma_s * transmittance / pdf)
+        } else {
+            let transmittance = exp(-sigma_t * t_max_distance);
+            let pdf = average(transmittance);
+            let weight = if pdf > 0.0 {
+                transmittance / pdf
+            } else {
+                Vec3::default()
+            };
+            MediumSample::new(None, weight)
+        }
+    }
+
+    fn phase(&self) -> HenyeyGreenstein {
+        self.phase
+    }
+}
+
+/// Material of boundaries that only delimit a medium.
+pub(crate) struct Boundary;
+
+pub(crate) static BOUNDARY: Boundary = Boundary;
+
+impl Scatter for Boundary {
+    fn eval(&self, _hit: &HitRecord, _wo: Vec3, _wi: Vec3) -> Vec3 {
+        Vec3::default()
+    }
+
+    fn sample(&self, _hit: &HitRecord, _wo: Vec3, _uc: f64, _u: (f64, f64)) -> Option<BsdfSample> {
+        None
+    }
+
+    fn pdf(&self, _hit: &HitRecord, _wo: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    fn is_interface(&self) -> bool {
+        true
+    }
+}
+
+/// Volume of `medium` enclosed by a closed `boundary`, like fog or smoke.
+///
+/// Volumes may be nested; rays track the media they are in by entering and
+/// leaving boundaries, so the boundary normals must point outwards.
+pub struct ConstantMedium {
+    boundary: Box<dyn Hit>,
+    medium: Arc<dyn Medium>,
+    keep_surface: bool,
+}
+
+impl ConstantMedium {
+    /// The boundary is invisible; its material is ignored.
+    pub fn new<T: Hit + 'static>(boundary: T, medium: Arc<dyn Medium>) -> Self {
+        Self {
+            boundary: Box::new(boundary),
+            medium,
+            keep_surface: false,
+        }
+    }
+
+    /// The boundary keeps its material, e.g. a `Dielectric` holding murky water.
+    pub fn with_surface<T: Hit + 'static>(boundary: T, medium: Arc<dyn Medium>) -> Self {
+        Self {
+            boundary: Box::new(boundary),
+            medium,
+            keep_surface: true,
+        }
+    }
+}
+
+impl Hit for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let hit = self
+            .boundary
+            .hit(ray, t_min, t_max)?
+            .with_medium(&*self.medium);
+        Some(if self.keep_surface {
+            hit
+        } else {
+            hit.with_material(&BOUNDARY)
+        })
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.boundary.bounding_box(time0, time1)
+    }
+
+    fn register_lights(&mut self, lights: &mut Vec<Arc<dyn Light>>) {
+        if self.keep_surface {
+            self.boundary.register_lights(lights);
+        }
+    }
+
+    fn collect_materials<'a>(&'a self, materials: &mut Vec<&'a dyn Scatter>) {
+        if self.keep_surface {
+            self.boundary.collect_materials(materials);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::uniform_sphere;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_henyey_greenstein() {
+        let wo = Vec3::new(0.0, 0.0, 1.0);
+        for &g in &[0.0, 0.7, -0.4] {
+            let phase = HenyeyGreenstein::new(g);
+            let n = 200;
+            let mut integral = 0.0;
+            let mut mean_cos = 0.0;
+            for i in 0..n {
+                for j in 0..n {
+                    let u = (
+                        (f64::from(i) + 0.5) / f64::from(n),
+                        (f64::from(j) + 0.5) / f64::from(n),
+                    );
+                    integral += phase.p(wo, uniform_sphere(u)) * 4.0 * PI;
+                    let (wi, pdf) = phase.sample(wo, u);
+                    assert!((pdf - phase.p(wo, wi)).abs() < 1e-9);
+                    mean_cos += wi.dot(-wo);
+                }
+            }
+            integral /= f64::from(n * n);
+            mean_cos /= f64::from(n * n);
+            assert!((integral - 1.0).abs() < 1e-2, "{} {}", g, integral);
+            assert!((mean_cos - g).abs() < 1e-2, "{} {}", g, mean_cos);
+        }
+    }
+
+    #[test]
+    fn test_homogeneous_sampling_is_unbiased() {
+        let medium =
+            HomogeneousMedium::new(Vec3::new(0.1, 0.5, 1.0), Vec3::new(0.4, 0.2, 0.0), 0.0);
+        let ray = Ray::new(Vec3::default(), Vec3::new(0.0, 0.0, 2.0), 0.0);
+        let mut rng = StdRng::seed_from_u64(1);
+        let n = 200_000;
+        let mut passed = Vec3::default();
+        for _ in 0..n {
+            let sample = medium.sample(&ray, 1.0, &mut rng);
+            if sample.t().is_none() {
+                passed += sample.weight();
+            }
+        }
+        passed /= f64::from(n);
+        let expected = medium.transmittance(&ray, 1.0, &mut rng);
+        assert!(
+            (passed - expected).length() < 0.01,
+            "{:?} {:?}",
+            passed,
+            expected
+        );
+        assert!((expected.z() - (-2.0f64).exp()).abs() < 1e-12);
+    }
+}
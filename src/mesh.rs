@@ -0,0 +1,318 @@
+use crate::hit::HitRecord;
+use crate::light::{cosine_emission, EmissionSample, Light, LightSample};
+use crate::light_sampler::LightBounds;
+use crate::material::Scatter;
+use crate::triangle::intersect_triangle;
+use crate::{Aabb, Bvh, Hit, Ray, Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Indexed triangle mesh.
+///
+/// Normals, colors and texture coordinates are optional per-vertex attributes;
+/// pass empty vectors to omit them. Without normals the mesh is flat shaded.
+pub struct Mesh {
+    data: Arc<MeshData>,
+    bvh: Bvh,
+}
+
+struct MeshData {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    colors: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    triangles: Vec<[usize; 3]>,
+    material: Arc<dyn Scatter>,
+}
+
+#[derive(Clone)]
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    index: usize,
+    light: Option<usize>,
+}
+
+impl MeshTriangle {
+    fn vertices(&self) -> (Vec3, Vec3, Vec3) {
+        let mesh = &*self.mesh;
+        let [i0, i1, i2] = mesh.triangles[self.index];
+        (mesh.positions[i0], mesh.positions[i1], mesh.positions[i2])
+    }
+
+    fn area_pdf_to_solid_angle(&self, point: Vec3, surface_point: Vec3) -> f64 {
+        let (v0, v1, v2) = self.vertices();
+        let cross = (v1 - v0).cross(v2 - v0);
+        let area = 0.5 * cross.length();
+        let to_surface = surface_point - point;
+        let distance2 = to_surface.squared_length();
+        let cos = cross.dot(to_surface).abs() / (cross.length() * distance2.sqrt());
+        if cos <= 0.0 || area <= 0.0 {
+            0.0
+        } else {
+            distance2 / (cos * area)
+        }
+    }
+}
+
+impl Mesh {
+    pub fn new(
+        positions: Vec<Vec3>,
+        normals: Vec<Vec3>,
+        colors: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        triangles: Vec<[usize; 3]>,
+        material: Arc<dyn Scatter>,
+    ) -> Self {
+        let n = positions.len();
+        assert!(normals.is_empty() || normals.len() == n);
+        assert!(colors.is_empty() || colors.len() == n);
+        assert!(uvs.is_empty() || uvs.len() == n);
+        assert!(triangles.iter().flatten().all(|&idx| idx < n));
+        let data = Arc::new(MeshData {
+            positions,
+            normals,
+            colors,
+            uvs,
+            triangles,
+            material,
+        });
+        let objects = (0..data.triangles.len())
+            .map(|index| {
+                Box::new(MeshTriangle {
+                    mesh: data.clone(),
+                    index,
+                    light: None,
+                }) as Box<dyn Hit>
+            })
+            .collect();
+        Self {
+            bvh: Bvh::new(objects, 0.0, 0.0),
+            data,
+        }
+    }
+
+    pub fn positions(&self) -> &[Vec3] {
+        &self.data.positions
+    }
+
+    pub fn normals(&self) -> &[Vec3] {
+        &self.data.normals
+    }
+
+    pub fn colors(&self) -> &[Vec3] {
+        &self.data.colors
+    }
+
+    pub fn uvs(&self) -> &[(f64, f64)] {
+        &self.data.uvs
+    }
+
+    pub fn triangles(&self) -> &[[usize; 3]] {
+        &self.data.triangles
+    }
+}
+
+impl Hit for Mesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.bvh.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        self.bvh.bounding_box(time0, time1)
+    }
+
+    fn register_lights(&mut self, lights: &mut Vec<Arc<dyn Light>>) {
+        if self.data.material.is_emissive() {
+            self.bvh.register_lights(lights);
+        }
+    }
+
+    fn collect_materials<'a>(&'a self, materials: &mut Vec<&'a dyn Scatter>) {
+        materials.push(&*self.data.material);
+    }
+}
+
+/// Box around a triangle, padded so that triangles in an axis-aligned plane
+/// still have a volume rays can clip against.
+fn triangle_box(v0: Vec3, v1: Vec3, v2: Vec3) -> Aabb {
+    let pad = Vec3::new(1e-6, 1e-6, 1e-6);
+    Aabb::new(v0.min(v1).min(v2) - pad, v0.max(v1).max(v2) + pad)
+}
+
+impl Hit for MeshTriangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let mesh = &*self.mesh;
+        let [i0, i1, i2] = mesh.triangles[self.index];
+        let (v0, v1, v2) = (mesh.positions[i0], mesh.positions[i1], mesh.positions[i2]);
+        let (t, b1, b2) = intersect_triangle(ray, v0, v1, v2, t_min, t_max)?;
+        let b0 = 1.0 - b1 - b2;
+        let interpolate = |a: Vec3, b: Vec3, c: Vec3| b0 * a + b1 * b + b2 * c;
+
+        let normal = if mesh.normals.is_empty() {
+            (v1 - v0).cross(v2 - v0).normalize()
+        } else {
+            interpolate(mesh.normals[i0], mesh.normals[i1], mesh.normals[i2]).normalize()
+        };
+        let (uv0, uv1, uv2) = if mesh.uvs.is_empty() {
+            ((0.0, 0.0), (1.0, 0.0), (1.0, 1.0))
+        } else {
+            (mesh.uvs[i0], mesh.uvs[i1], mesh.uvs[i2])
+        };
+        let uv = (
+            b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+            b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+        );
+        let mut record =
+            HitRecord::with_uv(t, ray.point_at_parameter(t), normal, uv, &*mesh.material);
+        if !mesh.colors.is_empty() {
+            record = record.with_color(interpolate(
+                mesh.colors[i0],
+                mesh.colors[i1],
+                mesh.colors[i2],
+            ));
+        }
+        record = record.with_tangent((v1 - v0).normalize());
+        Some(match self.light {
+            Some(light) => record.with_light(light),
+            None => record,
+        })
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        let mesh = &*self.mesh;
+        let [i0, i1, i2] = mesh.triangles[self.index];
+        let (v0, v1, v2) = (mesh.positions[i0], mesh.positions[i1], mesh.positions[i2]);
+        Some(triangle_box(v0, v1, v2))
+    }
+
+    fn register_lights(&mut self, lights: &mut Vec<Arc<dyn Light>>) {
+        self.light = Some(lights.len());
+        lights.push(Arc::new(self.clone()));
+    }
+}
+
+/// Emissive triangle sampled uniformly by area; emission faces the geometric normal.
+impl Light for MeshTriangle {
+    fn sample_li(&self, point: Vec3, (u1, u2): (f64, f64)) -> Option<LightSample> {
+        let (v0, v1, v2) = self.vertices();
+        let r = u1.sqrt();
+        let (b1, b2) = (r * (1.0 - u2), r * u2);
+        let surface_point = (1.0 - b1 - b2) * v0 + b1 * v1 + b2 * v2;
+        let to_light = surface_point - point;
+        let distance = to_light.length();
+        let wi = to_light / distance;
+        let pdf = self.area_pdf_to_solid_angle(point, surface_point);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let normal = (v1 - v0).cross(v2 - v0).normalize();
+        let record = HitRecord::with_uv(0.0, surface_point, normal, (b1, b2), &*self.mesh.material);
+        let radiance = self.mesh.material.emitted(&record, -wi);
+        Some(LightSample::new(wi, radiance, distance, pdf, false).with_normal(normal))
+    }
+
+    fn pdf_li(&self, point: Vec3, wi: Vec3) -> f64 {
+        let (v0, v1, v2) = self.vertices();
+        let ray = Ray::new(point, wi, 0.0);
+        match intersect_triangle(&ray, v0, v1, v2, 0.0, f64::MAX) {
+            Some((t, _, _)) => self.area_pdf_to_solid_angle(point, ray.point_at_parameter(t)),
+            None => 0.0,
+        }
+    }
+
+    fn sample_le(&self, (u1, u2): (f64, f64), u_direction: (f64, f64)) -> Option<EmissionSample> {
+        let (v0, v1, v2) = self.vertices();
+        let r = u1.sqrt();
+        let (b1, b2) = (r * (1.0 - u2), r * u2);
+        let point = (1.0 - b1 - b2) * v0 + b1 * v1 + b2 * v2;
+        let cross = (v1 - v0).cross(v2 - v0);
+        let normal = cross.normalize();
+        let material = &*self.mesh.material;
+        let record = HitRecord::with_uv(0.0, point, normal, (b1, b2), material);
+        cosine_emission(
+            point,
+            normal,
+            2.0 / cross.length(),
+            u_direction,
+            |direction| material.emitted(&record, direction),
+        )
+    }
+
+    fn pdf_le(&self, _point: Vec3, normal: Vec3, direction: Vec3) -> (f64, f64) {
+        let (v0, v1, v2) = self.vertices();
+        let area = 0.5 * (v1 - v0).cross(v2 - v0).length();
+        (1.0 / area, normal.dot(direction).max(0.0) / PI)
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let (v0, v1, v2) = self.vertices();
+        let cross = (v1 - v0).cross(v2 - v0);
+        let normal = cross.normalize();
+        let centroid = (v0 + v1 + v2) / 3.0;
+        let material = &*self.mesh.material;
+        let record = HitRecord::with_uv(0.0, centroid, normal, (1.0 / 3.0, 1.0 / 3.0), material);
+        let radiance = material.emitted(&record, normal);
+        Some(LightBounds::new(
+            triangle_box(v0, v1, v2),
+            normal,
+            PI * 0.5 * cross.length() * radiance.luminance(),
+            1.0,
+            0.0,
+            false,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+
+    #[test]
+    fn test_hit_interpolates_attributes() {
+        let mesh = Mesh::new(
+            vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            Vec::new(),
+            vec![
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+            ],
+            Vec::new(),
+            vec![[0, 1, 2]],
+            Arc::new(Lambertian::new(Vec3::new(1.0, 1.0, 1.0))),
+        );
+        let ray = Ray::new(Vec3::new(0.25, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = mesh.hit(&ray, 0.001, f64::MAX).unwrap();
+        assert_eq!(hit.normal(), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(hit.color(), Some(Vec3::new(0.25, 0.25, 0.5)));
+    }
+
+    #[test]
+    fn test_hit_axis_aligned_quad() {
+        // Two triangles in z = 0, so the mesh BVH has more than one leaf.
+        let mesh = Mesh::new(
+            vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vec![[0, 1, 2], [0, 2, 3]],
+            Arc::new(Lambertian::new(Vec3::new(1.0, 1.0, 1.0))),
+        );
+        let straight = Ray::new(Vec3::new(0.25, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = mesh.hit(&straight, 0.001, f64::MAX).unwrap();
+        assert!((hit.t() - 1.0).abs() < 1e-12);
+        let oblique = Ray::new(Vec3::new(0.0, 0.0, 2.0), Vec3::new(0.3, 0.4, -1.0), 0.0);
+        let hit = mesh.hit(&oblique, 0.001, f64::MAX).unwrap();
+        assert!((hit.point() - Vec3::new(0.6, 0.8, 0.0)).length() < 1e-9);
+    }
+}
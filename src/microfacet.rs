@@ -0,0 +1,280 @@
+use crate::hit::HitRecord;
+use crate::material::{albedo_at, cosine_hemisphere, reflect, BsdfSample, Frame, Lobe};
+use crate::texture::{SolidColor, Texture};
+use crate::{Scatter, Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Physically based material following glTF's metallic-roughness model.
+///
+/// Specular reflection uses the GGX (Trowbridge-Reitz) distribution with
+/// height-correlated Smith masking-shadowing and is sampled from the
+/// distribution of visible normals. The dielectric part layers the specular
+/// lobe over a Lambertian base, weighted by the exact dielectric Fresnel term.
+pub struct Microfacet {
+    base_color: Arc<dyn Texture>,
+    metallic: f64,
+    alpha: f64,
+    ior: f64,
+    conductor: Option<(Vec3, Vec3)>,
+}
+
+impl Microfacet {
+    /// glTF metallic-roughness material; metals use Schlick's Fresnel tinted by `base_color`.
+    pub fn new(base_color: Arc<dyn Texture>, metallic: f64, roughness: f64) -> Self {
+        Self {
+            base_color,
+            metallic: metallic.clamp(0.0, 1.0),
+            alpha: roughness_to_alpha(roughness),
+            ior: 1.5,
+            conductor: None,
+        }
+    }
+
+    /// Rough dielectric coating with index of refraction `ior` over a diffuse base.
+    pub fn dielectric(base_color: Arc<dyn Texture>, ior: f64, roughness: f64) -> Self {
+        Self {
+            ior,
+            ..Self::new(base_color, 0.0, roughness)
+        }
+    }
+
+    /// Rough metal with the complex index of refraction `eta + i k` given per RGB channel.
+    pub fn conductor(eta: Vec3, k: Vec3, roughness: f64) -> Self {
+        Self {
+            conductor: Some((eta, k)),
+            ..Self::new(
+                Arc::new(SolidColor::new(Vec3::new(1.0, 1.0, 1.0))),
+                1.0,
+                roughness,
+            )
+        }
+    }
+
+    /// Probability of sampling the specular lobe rather than the diffuse one.
+    fn specular_probability(&self, cos_o: f64) -> f64 {
+        let fresnel = fresnel_dielectric(cos_o, self.ior);
+        self.metallic + (1.0 - self.metallic) * fresnel.max(0.25)
+    }
+
+    /// BSDF value for unit directions in the local shading frame (normal along +z).
+    pub(crate) fn eval_local(&self, base_color: Vec3, wo: Vec3, wi: Vec3) -> Vec3 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Vec3::default();
+        }
+        let h = (wo + wi).normalize();
+        let cos_oh = wo.dot(h);
+        let specular =
+            ggx_d(h.z(), self.alpha) * smith_g2(wo, wi, self.alpha) / (4.0 * wo.z() * wi.z());
+        let metal_fresnel = match self.conductor {
+            Some((eta, k)) => Vec3::new(
+                fresnel_conductor(cos_oh, eta.x(), k.x()),
+                fresnel_conductor(cos_oh, eta.y(), k.y()),
+                fresnel_conductor(cos_oh, eta.z(), k.z()),
+            ),
+            None => {
+                let weight = (1.0 - cos_oh).powi(5);
+                base_color + weight * (Vec3::new(1.0, 1.0, 1.0) - base_color)
+            }
+        };
+        let dielectric_fresnel = fresnel_dielectric(cos_oh, self.ior);
+        let metal = specular * metal_fresnel;
+        let dielectric = specular * dielectric_fresnel * Vec3::new(1.0, 1.0, 1.0)
+            + (1.0 - dielectric_fresnel) / PI * base_color;
+        self.metallic * metal + (1.0 - self.metallic) * dielectric
+    }
+
+    /// Density of `sample_local` choosing `wi`, with respect to solid angle.
+    pub(crate) fn pdf_local(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let h = (wo + wi).normalize();
+        let specular_pdf = smith_g1(wo, self.alpha) * ggx_d(h.z(), self.alpha) / (4.0 * wo.z());
+        let diffuse_pdf = wi.z() / PI;
+        let p = self.specular_probability(wo.z());
+        p * specular_pdf + (1.0 - p) * diffuse_pdf
+    }
+
+    pub(crate) fn sample_local(&self, wo: Vec3, u: (f64, f64), lobe: f64) -> Vec3 {
+        if lobe < self.specular_probability(wo.z()) {
+            let h = sample_vndf(wo, self.alpha, u);
+            reflect(-wo, h)
+        } else {
+            cosine_hemisphere(u)
+        }
+    }
+}
+
+impl Scatter for Microfacet {
+    fn eval(&self, hit: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        let frame = Frame::facing(hit, wo);
+        let base_color = albedo_at(&*self.base_color, hit);
+        self.eval_local(base_color, frame.to_local(wo), frame.to_local(wi))
+    }
+
+    fn sample(&self, hit: &HitRecord, wo: Vec3, uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        let frame = Frame::facing(hit, wo);
+        let wo_local = frame.to_local(wo);
+        let specular = uc < self.specular_probability(wo_local.z());
+        let wi_local = self.sample_local(wo_local, u, uc);
+        let pdf = self.pdf_local(wo_local, wi_local);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let base_color = albedo_at(&*self.base_color, hit);
+        let lobe = if specular {
+            Lobe::GLOSSY
+        } else {
+            Lobe::DIFFUSE
+        };
+        Some(BsdfSample::new(
+            frame.to_world(wi_local),
+            self.eval_local(base_color, wo_local, wi_local),
+            pdf,
+            lobe | Lobe::REFLECTION,
+        ))
+    }
+
+    fn pdf(&self, hit: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let frame = Frame::facing(hit, wo);
+        self.pdf_local(frame.to_local(wo), frame.to_local(wi))
+    }
+}
+
+fn roughness_to_alpha(roughness: f64) -> f64 {
+    let roughness = roughness.clamp(0.0, 1.0);
+    (roughness * roughness).max(1e-4)
+}
+
+fn ggx_d(cos_h: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    let d = cos_h * cos_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * d * d)
+}
+
+fn smith_lambda(w: Vec3, alpha: f64) -> f64 {
+    let cos2 = w.z() * w.z();
+    let tan2 = (1.0 - cos2).max(0.0) / cos2;
+    0.5 * (-1.0 + (1.0 + alpha * alpha * tan2).sqrt())
+}
+
+fn smith_g1(w: Vec3, alpha: f64) -> f64 {
+    1.0 / (1.0 + smith_lambda(w, alpha))
+}
+
+fn smith_g2(wo: Vec3, wi: Vec3, alpha: f64) -> f64 {
+    1.0 / (1.0 + smith_lambda(wo, alpha) + smith_lambda(wi, alpha))
+}
+
+/// Samples a microfacet normal visible from `wo` (Heitz 2018).
+fn sample_vndf(wo: Vec3, alpha: f64, (u1, u2): (f64, f64)) -> Vec3 {
+    let vh = Vec3::new(alpha * wo.x(), alpha * wo.y(), wo.z()).normalize();
+    let len2 = vh.x() * vh.x() + vh.y() * vh.y();
+    let t1 = if len2 > 0.0 {
+        Vec3::new(-vh.y(), vh.x(), 0.0) / len2.sqrt()
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let t2 = vh.cross(t1);
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + vh.z());
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+    let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+    Vec3::new(alpha * nh.x(), alpha * nh.y(), nh.z().max(0.0)).normalize()
+}
+
+/// Unpolarized Fresnel reflectance of a dielectric for light arriving from outside.
+pub(crate) fn fresnel_dielectric(cos_i: f64, ior: f64) -> f64 {
+    let (cos_i, eta) = if cos_i < 0.0 {
+        (-cos_i, 1.0 / ior)
+    } else {
+        (cos_i, ior)
+    };
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
+}
+
+/// Unpolarized Fresnel reflectance of a conductor with complex index of refraction `eta + i k`.
+fn fresnel_conductor(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_i.clamp(0.0, 1.0) * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rp + rs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_fresnel() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert_eq!(fresnel_dielectric(-0.1, 1.5), 1.0);
+        let gold = fresnel_conductor(1.0, 0.143, 3.983);
+        let expected = ((0.143 - 1.0f64).powi(2) + 3.983f64.powi(2))
+            / ((0.143 + 1.0f64).powi(2) + 3.983f64.powi(2));
+        assert!((gold - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_ggx_normalized() {
+        // The projected microfacet area must integrate to one over the hemisphere.
+        let alpha = 0.3;
+        let n = 2000;
+        let mut acc = 0.0;
+        for i in 0..n {
+            let cos = (f64::from(i) + 0.5) / f64::from(n);
+            acc += ggx_d(cos, alpha) * cos * 2.0 * PI / f64::from(n);
+        }
+        assert!((acc - 1.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_sampling_matches_pdf() {
+        // E[f cos / pdf] estimated with importance sampling must match uniform sampling.
+        let material = Microfacet::new(
+            Arc::new(SolidColor::new(Vec3::new(0.8, 0.5, 0.2))),
+            0.5,
+            0.5,
+        );
+        let base = Vec3::new(0.8, 0.5, 0.2);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        let mut rng = StdRng::seed_from_u64(1);
+        let n = 200_000;
+        let (mut importance, mut uniform) = (Vec3::default(), Vec3::default());
+        for _ in 0..n {
+            let wi = material.sample_local(wo, (rng.gen(), rng.gen()), rng.gen());
+            let pdf = material.pdf_local(wo, wi);
+            if pdf > 0.0 {
+                importance += material.eval_local(base, wo, wi) * (wi.z() / pdf);
+            }
+            let (u1, u2): (f64, f64) = (rng.gen(), rng.gen());
+            let r = (1.0 - u1 * u1).sqrt();
+            let wi = Vec3::new(r * (2.0 * PI * u2).cos(), r * (2.0 * PI * u2).sin(), u1);
+            uniform += material.eval_local(base, wo, wi) * (wi.z() * 2.0 * PI);
+        }
+        let diff = (importance - uniform) / f64::from(n);
+        assert!(diff.length() < 0.02, "{:?}", diff);
+    }
+}
@@ -0,0 +1,393 @@
+use crate::distribution::Distribution1D;
+use crate::{Camera, HdrImage, PathTracer, RenderSettings, Scene, Vec3};
+use rand::distributions::StandardNormal;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use rayon::prelude::*;
+
+/// Primary sample space Metropolis light transport (Kelemen et al., "A Simple
+/// and Robust Mutation Strategy for the Metropolis Light Transport
+/// Algorithm", 2002).
+///
+/// Markov chains wander over the random numbers consumed by a path tracer,
+/// spending time in proportion to the luminance of the paths they produce.
+pub struct MetropolisLightTransport {
+    tracer: PathTracer,
+    bootstrap_samples: usize,
+    chains: u64,
+    large_step_probability: f64,
+    sigma: f64,
+}
+
+/// One coordinate of the primary sample vector, with the state to restore
+/// when a mutation is rejected.
+#[derive(Clone, Copy, Default)]
+struct PrimarySample {
+    value: f64,
+    /// Iteration the value was last brought up to date at.
+    modified: u64,
+    backup: f64,
+    backup_modified: u64,
+}
+
+/// Primary sample vector handed to the path tracer as its random number
+/// generator. Coordinates are created and mutated lazily, as they are used.
+struct PrimarySamples {
+    rng: StdRng,
+    samples: Vec<PrimarySample>,
+    index: usize,
+    iteration: u64,
+    large_step: bool,
+    last_large_step: u64,
+    sigma: f64,
+}
+
+impl PrimarySamples {
+    fn new(seed: u64, sigma: f64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            samples: Vec::new(),
+            index: 0,
+            iteration: 0,
+            large_step: true,
+            last_large_step: 0,
+            sigma,
+        }
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Starts proposing a mutation of the whole vector.
+    fn start_iteration(&mut self, large_step: bool) {
+        self.iteration += 1;
+        self.large_step = large_step;
+        self.index = 0;
+    }
+
+    fn accept(&mut self) {
+        if self.large_step {
+            self.last_large_step = self.iteration;
+        }
+    }
+
+    fn reject(&mut self) {
+        for sample in &mut self.samples {
+            if sample.modified == self.iteration {
+                sample.value = sample.backup;
+                sample.modified = sample.backup_modified;
+            }
+        }
+        self.iteration -= 1;
+    }
+
+    /// Next coordinate in `[0, 1)`, mutated up to the current iteration.
+    fn next(&mut self) -> f64 {
+        if self.index == self.samples.len() {
+            self.samples.push(PrimarySample::default());
+        }
+        let sample = &mut self.samples[self.index];
+        self.index += 1;
+        if sample.modified < self.last_large_step {
+            sample.value = self.rng.gen();
+            sample.modified = self.last_large_step;
+        }
+        sample.backup = sample.value;
+        sample.backup_modified = sample.modified;
+        if self.large_step {
+            sample.value = self.rng.gen();
+        } else {
+            // The small steps skipped while the coordinate went unused add
+            // up to a single Gaussian step.
+            let steps = (self.iteration - sample.modified) as f64;
+            let offset: f64 = self.rng.sample(StandardNormal);
+            sample.value += offset * self.sigma * steps.sqrt();
+            sample.value -= sample.value.floor();
+        }
+        sample.modified = self.iteration;
+        sample.value
+    }
+}
+
+impl RngCore for PrimarySamples {
+    fn next_u32(&mut self) -> u32 {
+        (self.next() * 2f64.powi(32)) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        (self.next() * 2f64.powi(64)) as u64
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Scalar contribution the chains are distributed by.
+fn intensity(radiance: Vec3) -> f64 {
+    let y = radiance.luminance();
+    if y.is_finite() && y > 0.0 {
+        y
+    } else {
+        0.0
+    }
+}
+
+fn splat(film: &mut [Vec3], width: u32, height: u32, (u, v): (f64, f64), value: Vec3) {
+    let x = ((u * f64::from(width)) as u32).min(width - 1);
+    let y = ((v * f64::from(height)) as u32).min(height - 1);
+    film[(y * width + x) as usize] += value;
+}
+
+impl MetropolisLightTransport {
+    pub fn new(tracer: PathTracer) -> Self {
+        Self {
+            tracer,
+            bootstrap_samples: 100_000,
+            chains: 1000,
+            large_step_probability: 0.3,
+            sigma: 0.01,
+        }
+    }
+
+    /// Independent paths traced to estimate the image brightness and to pick
+    /// the chain starting points from. Defaults to 100000.
+    pub fn set_bootstrap_samples(&mut self, bootstrap_samples: usize) {
+        self.bootstrap_samples = bootstrap_samples;
+    }
+
+    /// Markov chains the mutations are split between. Defaults to 1000.
+    pub fn set_chains(&mut self, chains: u64) {
+        self.chains = chains;
+    }
+
+    /// Probability of a mutation drawing a fresh sample vector. Defaults to 0.3.
+    pub fn set_large_step_probability(&mut self, large_step_probability: f64) {
+        self.large_step_probability = large_step_probability;
+    }
+
+    /// Standard deviation of the small step perturbations. Defaults to 0.01.
+    pub fn set_sigma(&mut self, sigma: f64) {
+        self.sigma = sigma;
+    }
+
+    /// Makes as many mutations as there are samples in `settings`.
+    ///
+    /// Chains are seeded deterministically, so the same scene always renders
+    /// to the same image.
+    pub fn render(&self, scene: &Scene, camera: &Camera, settings: &RenderSettings) -> HdrImage {
+        let (width, height) = (settings.width(), settings.height());
+        let pixel_count = (width * height) as usize;
+        let mut image = HdrImage::new(width, height);
+
+        let weights: Vec<f64> = (0..self.bootstrap_samples)
+            .into_par_iter()
+            .map(|i| {
+                let mut sampler = PrimarySamples::new(i as u64, self.sigma);
+                sampler.start_iteration(true);
+                intensity(self.evaluate(scene, camera, &mut sampler).1)
+            })
+            .collect();
+        if weights.is_empty() {
+            return image;
+        }
+        let brightness = weights.iter().sum::<f64>() / weights.len() as f64;
+        if brightness <= 0.0 {
+            return image;
+        }
+        let bootstrap = Distribution1D::new(weights);
+
+        let total = u64::from(settings.samples_per_pixel().max(1)) * pixel_count as u64;
+        let chains = self.chains.max(1).min(total);
+        let mutations = total / chains;
+        let film = || vec![Vec3::default(); pixel_count];
+        let film = (0..chains)
+            .into_par_iter()
+            .fold(film, |mut film, chain| {
+                self.run_chain(
+                    scene,
+                    camera,
+                    &bootstrap,
+                    brightness,
+                    chain,
+                    mutations,
+                    &mut |uv, value| splat(&mut film, width, height, uv, value),
+                );
+                film
+            })
+            .reduce(film, |mut film, other| {
+                for (pixel, other) in film.iter_mut().zip(other) {
+                    *pixel += other;
+                }
+                film
+            });
+
+        let mutations_per_pixel = (chains * mutations) as f64 / pixel_count as f64;
+        for (idx, value) in film.into_iter().enumerate() {
+            let (x, y) = (idx as u32 % width, idx as u32 / width);
+            image[(x, y)] = value / mutations_per_pixel;
+        }
+        image
+    }
+
+    /// Image point and radiance of the path traced from `sampler`.
+    fn evaluate(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        sampler: &mut PrimarySamples,
+    ) -> ((f64, f64), Vec3) {
+        let uv = (sampler.gen(), sampler.gen());
+        let ray = camera.get_ray(uv.0, uv.1);
+        (uv, self.tracer.li(&ray, scene, sampler))
+    }
+
+    /// Runs one chain, splatting both the proposed and the current path at
+    /// every mutation with Kelemen's weights, which combine the Metropolis
+    /// estimate with the independent large steps.
+    #[allow(clippy::too_many_arguments)]
+    fn run_chain(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        bootstrap: &Distribution1D,
+        brightness: f64,
+        chain: u64,
+        mutations: u64,
+        splat: &mut dyn FnMut((f64, f64), Vec3),
+    ) {
+        let seed = self.bootstrap_samples as u64 + 2 * chain;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (_, _, start) = bootstrap.sample_continuous(rng.gen());
+        let mut sampler = PrimarySamples::new(start as u64, self.sigma);
+        sampler.start_iteration(true);
+        let (mut current_uv, mut current) = self.evaluate(scene, camera, &mut sampler);
+        sampler.accept();
+        sampler.reseed(seed + 1);
+        let mut current_intensity = intensity(current);
+
+        let p_large = self.large_step_probability;
+        for _ in 0..mutations {
+            let large_step = rng.gen::<f64>() < p_large;
+            sampler.start_iteration(large_step);
+            let (proposed_uv, proposed) = self.evaluate(scene, camera, &mut sampler);
+            let proposed_intensity = intensity(proposed);
+            let accept = if current_intensity > 0.0 {
+                (proposed_intensity / current_intensity).min(1.0)
+            } else {
+                1.0
+            };
+
+            let proposed_weight = accept + if large_step { 1.0 } else { 0.0 };
+            if proposed_weight > 0.0 {
+                let denominator = proposed_intensity / brightness + p_large;
+                splat(proposed_uv, proposed * (proposed_weight / denominator));
+            }
+            if accept < 1.0 {
+                let denominator = current_intensity / brightness + p_large;
+                splat(current_uv, current * ((1.0 - accept) / denominator));
+            }
+
+            if rng.gen::<f64>() < accept {
+                current_uv = proposed_uv;
+                current = proposed;
+                current_intensity = proposed_intensity;
+                sampler.accept();
+            } else {
+                sampler.reject();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        render, ConstantEnvironment, DiffuseLight, HitList, Integrator, Lambertian, LightSampling,
+        Rectangle, Sphere,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn test_rejected_mutation_restores_samples() {
+        let mut sampler = PrimarySamples::new(1, 0.01);
+        sampler.start_iteration(true);
+        let first: Vec<f64> = (0..4).map(|_| sampler.next()).collect();
+        sampler.accept();
+
+        sampler.start_iteration(false);
+        let mutated: Vec<f64> = (0..4).map(|_| sampler.next()).collect();
+        for (a, b) in first.iter().zip(&mutated) {
+            let distance = (a - b).abs().min(1.0 - (a - b).abs());
+            assert!(distance > 0.0 && distance < 0.1, "{} {}", a, b);
+        }
+        sampler.reject();
+        let restored: Vec<f64> = sampler.samples.iter().map(|s| s.value).collect();
+        assert_eq!(restored, first);
+
+        sampler.start_iteration(true);
+        let fresh: Vec<f64> = (0..4).map(|_| sampler.next()).collect();
+        assert_ne!(fresh, first);
+    }
+
+    #[test]
+    fn test_matches_path_tracer() {
+        // A sphere on a floor lit by a small area light, seen from above.
+        let mut world = HitList::new();
+        world.push(Rectangle::new(
+            Vec3::new(-5.0, 0.0, 5.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -10.0),
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        ));
+        world.push(Sphere::new(
+            Vec3::new(0.3, 0.3, 0.0),
+            0.3,
+            Arc::new(Lambertian::new(Vec3::new(0.8, 0.3, 0.3))),
+        ));
+        world.push(Rectangle::new(
+            Vec3::new(-0.5, 4.0, -0.5),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Arc::new(DiffuseLight::new(Vec3::new(20.0, 20.0, 20.0))),
+        ));
+        let scene =
+            Scene::with_environment(world, Arc::new(ConstantEnvironment::new(Vec3::default())));
+        let camera = Camera::new(
+            Vec3::new(-1.0, 1.0, 1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -2.0),
+            Vec3::new(0.0, 2.0, 0.0),
+        );
+        let path = Integrator::Path(PathTracer::new(5, LightSampling::Mis));
+        let mut settings = RenderSettings::new(4, 4, 20000, path);
+        let expected = render(&scene, &camera, &settings);
+
+        let mut mlt = MetropolisLightTransport::new(PathTracer::new(5, LightSampling::Mis));
+        mlt.set_bootstrap_samples(20_000);
+        mlt.set_chains(100);
+        settings.set_integrator(Integrator::Metropolis(mlt));
+        let image = render(&scene, &camera, &settings);
+        for y in 0..4 {
+            for x in 0..4 {
+                let (got, want) = (image[(x, y)], expected[(x, y)]);
+                assert!(
+                    (got - want).length() < 0.1 * want.length(),
+                    "{:?} {:?}",
+                    got,
+                    want
+                );
+            }
+        }
+    }
+}
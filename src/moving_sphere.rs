@@ -0,0 +1,102 @@
+use crate::hit::HitRecord;
+use crate::material::Scatter;
+use crate::sphere::{hit_sphere, sphere_box};
+use crate::{Aabb, Hit, Ray, Vec3};
+use std::sync::Arc;
+
+/// Sphere whose center moves linearly from `center0` at `time0` to `center1` at `time1`.
+pub struct MovingSphere {
+    center0: Vec3,
+    center1: Vec3,
+    time0: f64,
+    time1: f64,
+    radius: f64,
+    material: Arc<dyn Scatter>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Vec3,
+        center1: Vec3,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        material: Arc<dyn Scatter>,
+    ) -> Self {
+        Self {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        }
+    }
+
+    pub fn center(&self, time: f64) -> Vec3 {
+        if self.time1 == self.time0 {
+            return self.center0;
+        }
+        let s = (time - self.time0) / (self.time1 - self.time0);
+        self.center0 + s * (self.center1 - self.center0)
+    }
+}
+
+impl Hit for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let center = self.center(ray.time());
+        hit_sphere(center, self.radius, &*self.material, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<Aabb> {
+        let box0 = sphere_box(self.center(time0), self.radius);
+        let box1 = sphere_box(self.center(time1), self.radius);
+        Some(box0.surrounding(box1))
+    }
+
+    fn collect_materials<'a>(&'a self, materials: &mut Vec<&'a dyn Scatter>) {
+        materials.push(&*self.material);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+
+    fn make_sphere() -> MovingSphere {
+        MovingSphere::new(
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(2.0, 0.0, -1.0),
+            0.0,
+            1.0,
+            0.5,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn test_center() {
+        let sphere = make_sphere();
+        assert_eq!(sphere.center(0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(sphere.center(0.5), Vec3::new(1.0, 0.0, -1.0));
+        assert_eq!(sphere.center(1.0), Vec3::new(2.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_hit_depends_on_time() {
+        let sphere = make_sphere();
+        let direction = Vec3::new(0.0, 0.0, -1.0);
+        let early = Ray::new(Vec3::new(2.0, 0.0, 0.0), direction, 0.0);
+        let late = Ray::new(Vec3::new(2.0, 0.0, 0.0), direction, 1.0);
+        assert!(sphere.hit(&early, 0.001, f64::MAX).is_none());
+        assert!(sphere.hit(&late, 0.001, f64::MAX).is_some());
+    }
+
+    #[test]
+    fn test_bounding_box_covers_motion() {
+        let sphere = make_sphere();
+        let expected = Aabb::new(Vec3::new(-0.5, -0.5, -1.5), Vec3::new(2.5, 0.5, -0.5));
+        assert_eq!(sphere.bounding_box(0.0, 1.0), Some(expected));
+    }
+}
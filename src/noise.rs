@@ -0,0 +1,211 @@
+use crate::texture::Texture;
+use crate::Vec3;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const POINT_COUNT: usize = 256;
+
+/// Gradient (Perlin) noise with values in about `[-1, 1]`.
+///
+/// The lattice gradients and permutations are generated from `seed`,
+/// so the same seed always gives the same noise.
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| loop {
+                let v = Vec3::new(
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                    rng.gen_range(-1.0, 1.0),
+                );
+                let length = v.length();
+                if length > 1e-3 && length <= 1.0 {
+                    break v / length;
+                }
+            })
+            .collect();
+        let mut permutation = || {
+            let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+            perm.shuffle(&mut rng);
+            perm
+        };
+        Self {
+            perm_x: permutation(),
+            perm_y: permutation(),
+            perm_z: permutation(),
+            gradients,
+        }
+    }
+
+    pub fn noise(&self, p: Vec3) -> f64 {
+        let (fx, fy, fz) = (p.x().floor(), p.y().floor(), p.z().floor());
+        let (u, v, w) = (p.x() - fx, p.y() - fy, p.z() - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+        let hermite = |t: f64| t * t * (3.0 - 2.0 * t);
+        let (uu, vv, ww) = (hermite(u), hermite(v), hermite(w));
+        let mask = POINT_COUNT as i64 - 1;
+        let mut acc = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let idx = self.perm_x[((i + di) & mask) as usize]
+                        ^ self.perm_y[((j + dj) & mask) as usize]
+                        ^ self.perm_z[((k + dk) & mask) as usize];
+                    let (a, b, c) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - a, v - b, w - c);
+                    acc += (a * uu + (1.0 - a) * (1.0 - uu))
+                        * (b * vv + (1.0 - b) * (1.0 - vv))
+                        * (c * ww + (1.0 - c) * (1.0 - ww))
+                        * self.gradients[idx].dot(weight);
+                }
+            }
+        }
+        acc
+    }
+
+    /// Fractional Brownian motion: octaves of noise with doubling frequency and halving amplitude.
+    pub fn fbm(&self, p: Vec3, octaves: u32) -> f64 {
+        self.octaves(p, octaves, |n| n)
+    }
+
+    /// Like `fbm`, but sums absolute values, which gives sharp creases.
+    pub fn turbulence(&self, p: Vec3, octaves: u32) -> f64 {
+        self.octaves(p, octaves, f64::abs)
+    }
+
+    fn octaves(&self, mut p: Vec3, octaves: u32, f: impl Fn(f64) -> f64) -> f64 {
+        let mut acc = 0.0;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            acc += weight * f(self.noise(p));
+            weight *= 0.5;
+            p *= 2.0;
+        }
+        acc
+    }
+}
+
+const OCTAVES: u32 = 7;
+
+fn lerp(a: Vec3, b: Vec3, t: f64) -> Vec3 {
+    (1.0 - t) * a + t * b
+}
+
+/// Marble veins: a sine wave along `z` distorted by turbulence.
+pub struct Marble {
+    noise: Perlin,
+    scale: f64,
+    base: Vec3,
+    vein: Vec3,
+}
+
+impl Marble {
+    pub fn new(seed: u64, scale: f64, base: Vec3, vein: Vec3) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            scale,
+            base,
+            vein,
+        }
+    }
+}
+
+impl Texture for Marble {
+    fn value(&self, _uv: (f64, f64), point: Vec3) -> Vec3 {
+        let p = self.scale * point;
+        let phase = p.z() + 10.0 * self.noise.turbulence(p, OCTAVES);
+        lerp(self.vein, self.base, 0.5 * (1.0 + phase.sin()))
+    }
+}
+
+/// Growth rings around the `y` axis, wobbled by low-frequency noise.
+pub struct Wood {
+    noise: Perlin,
+    rings_per_unit: f64,
+    light: Vec3,
+    dark: Vec3,
+}
+
+impl Wood {
+    pub fn new(seed: u64, rings_per_unit: f64, light: Vec3, dark: Vec3) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            rings_per_unit,
+            light,
+            dark,
+        }
+    }
+}
+
+impl Texture for Wood {
+    fn value(&self, _uv: (f64, f64), point: Vec3) -> Vec3 {
+        let radius = (point.x() * point.x() + point.z() * point.z()).sqrt();
+        let wobble = 0.3 * self.noise.fbm(2.0 * point, 3);
+        let rings = (radius + wobble) * self.rings_per_unit;
+        let t = rings - rings.floor();
+        lerp(self.light, self.dark, t * t * (3.0 - 2.0 * t))
+    }
+}
+
+/// Speckled stone: thresholded high-frequency noise over a mottled base.
+pub struct Granite {
+    noise: Perlin,
+    scale: f64,
+    base: Vec3,
+    speckle: Vec3,
+}
+
+impl Granite {
+    pub fn new(seed: u64, scale: f64, base: Vec3, speckle: Vec3) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            scale,
+            base,
+            speckle,
+        }
+    }
+}
+
+impl Texture for Granite {
+    fn value(&self, _uv: (f64, f64), point: Vec3) -> Vec3 {
+        let p = self.scale * point;
+        let grain = (2.0 * self.noise.turbulence(4.0 * p, OCTAVES) - 0.4).clamp(0.0, 1.0);
+        let mottle = 0.85 + 0.15 * self.noise.fbm(p, 3);
+        mottle * lerp(self.base, self.speckle, grain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_noise_is_reproducible() {
+        let a = Perlin::new(42);
+        let b = Perlin::new(42);
+        let c = Perlin::new(43);
+        let p = Vec3::new(1.3, -2.7, 0.4);
+        assert_eq!(a.noise(p), b.noise(p));
+        assert_eq!(a.turbulence(p, 5), b.turbulence(p, 5));
+        assert_ne!(a.noise(p), c.noise(p));
+    }
+
+    #[test]
+    fn test_noise_vanishes_at_lattice_points() {
+        let perlin = Perlin::new(7);
+        assert_eq!(perlin.noise(Vec3::new(3.0, -1.0, 5.0)), 0.0);
+        for i in 0..100 {
+            let x = f64::from(i) * 0.173;
+            assert!(perlin.noise(Vec3::new(x, 0.5 * x, -x)).abs() <= 1.0);
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use crate::hit::HitRecord;
+use crate::material::{albedo_at, cosine_hemisphere, reflect, BsdfSample, Frame, Lobe};
+use crate::texture::{SolidColor, Texture};
+use crate::{Scatter, Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Modified Phong shading: a Lambertian term plus a highlight of the given
+/// exponent around the mirror direction, or around the normal for the
+/// half vector with Blinn-Phong.
+///
+/// The highlight is normalized so that `diffuse + specular` at most one
+/// conserves energy, which lets the path tracer render the material too.
+pub struct Phong {
+    diffuse: Arc<dyn Texture>,
+    specular: Vec3,
+    exponent: f64,
+    blinn: bool,
+}
+
+impl Phong {
+    pub fn new(diffuse: Vec3, specular: Vec3, exponent: f64) -> Self {
+        Self::with_texture(Arc::new(SolidColor::new(diffuse)), specular, exponent)
+    }
+
+    pub fn with_texture(diffuse: Arc<dyn Texture>, specular: Vec3, exponent: f64) -> Self {
+        Self {
+            diffuse,
+            specular,
+            exponent: exponent.max(0.0),
+            blinn: false,
+        }
+    }
+
+    /// Uses the Blinn-Phong highlight, which follows the half vector.
+    pub fn blinn(mut self) -> Self {
+        self.blinn = true;
+        self
+    }
+
+    /// Probability of sampling the highlight rather than the diffuse term.
+    fn specular_probability(&self, diffuse: Vec3) -> f64 {
+        let specular = self.specular.luminance();
+        let total = specular + diffuse.luminance();
+        if total > 0.0 {
+            specular / total
+        } else {
+            0.0
+        }
+    }
+
+    /// Normalized highlight for unit directions in the local shading frame.
+    fn highlight(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let n = self.exponent;
+        if self.blinn {
+            let h = (wo + wi).normalize();
+            (n + 8.0) / (8.0 * PI) * h.z().max(0.0).powf(n)
+        } else {
+            let mirror = Vec3::new(-wo.x(), -wo.y(), wo.z());
+            (n + 2.0) / (2.0 * PI) * mirror.dot(wi).max(0.0).powf(n)
+        }
+    }
+
+    fn highlight_pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        let n = self.exponent;
+        if self.blinn {
+            let h = (wo + wi).normalize();
+            let cos_oh = wo.dot(h);
+            if cos_oh <= 0.0 {
+                return 0.0;
+            }
+            (n + 1.0) / (2.0 * PI) * h.z().max(0.0).powf(n) / (4.0 * cos_oh)
+        } else {
+            let mirror = Vec3::new(-wo.x(), -wo.y(), wo.z());
+            (n + 1.0) / (2.0 * PI) * mirror.dot(wi).max(0.0).powf(n)
+        }
+    }
+
+    /// Direction with density proportional to `cos^n` around the mirror
+    /// direction, or reflected about a half vector with that density around
+    /// the normal.
+    fn sample_highlight(&self, wo: Vec3, (u1, u2): (f64, f64)) -> Vec3 {
+        let cos = u1.powf(1.0 / (self.exponent + 1.0));
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        let lobe = Vec3::new(sin * phi.cos(), sin * phi.sin(), cos);
+        if self.blinn {
+            reflect(-wo, lobe)
+        } else {
+            Frame::new(Vec3::new(-wo.x(), -wo.y(), wo.z())).to_world(lobe)
+        }
+    }
+
+    fn eval_local(&self, diffuse: Vec3, wo: Vec3, wi: Vec3) -> Vec3 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Vec3::default();
+        }
+        diffuse / PI + self.specular * self.highlight(wo, wi)
+    }
+
+    fn pdf_local(&self, diffuse: Vec3, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let p = self.specular_probability(diffuse);
+        p * self.highlight_pdf(wo, wi) + (1.0 - p) * wi.z() / PI
+    }
+}
+
+impl Scatter for Phong {
+    fn eval(&self, hit: &HitRecord, wo: Vec3, wi: Vec3) -> Vec3 {
+        let frame = Frame::facing(hit, wo);
+        let diffuse = albedo_at(&*self.diffuse, hit);
+        self.eval_local(diffuse, frame.to_local(wo), frame.to_local(wi))
+    }
+
+    fn sample(&self, hit: &HitRecord, wo: Vec3, uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        let frame = Frame::facing(hit, wo);
+        let wo_local = frame.to_local(wo);
+        let diffuse = albedo_at(&*self.diffuse, hit);
+        let specular = uc < self.specular_probability(diffuse);
+        let wi_local = if specular {
+            self.sample_highlight(wo_local, u)
+        } else {
+            cosine_hemisphere(u)
+        };
+        let pdf = self.pdf_local(diffuse, wo_local, wi_local);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let lobe = if specular {
+            Lobe::GLOSSY
+        } else {
+            Lobe::DIFFUSE
+        };
+        Some(BsdfSample::new(
+            frame.to_world(wi_local),
+            self.eval_local(diffuse, wo_local, wi_local),
+            pdf,
+            lobe | Lobe::REFLECTION,
+        ))
+    }
+
+    fn pdf(&self, hit: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let frame = Frame::facing(hit, wo);
+        let diffuse = albedo_at(&*self.diffuse, hit);
+        self.pdf_local(diffuse, frame.to_local(wo), frame.to_local(wi))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_sampling_matches_pdf() {
+        // E[f cos / pdf] estimated with importance sampling must match uniform sampling.
+        let diffuse = Vec3::new(0.5, 0.3, 0.1);
+        let specular = Vec3::new(0.4, 0.4, 0.4);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        for material in [
+            Phong::new(diffuse, specular, 20.0),
+            Phong::new(diffuse, specular, 20.0).blinn(),
+        ] {
+            let mut rng = StdRng::seed_from_u64(1);
+            let n = 200_000;
+            let (mut importance, mut uniform) = (Vec3::default(), Vec3::default());
+            for _ in 0..n {
+                let wi = if rng.gen::<f64>() < material.specular_probability(diffuse) {
+                    material.sample_highlight(wo, (rng.gen(), rng.gen()))
+                } else {
+                    cosine_hemisphere((rng.gen(), rng.gen()))
+                };
+                let pdf = material.pdf_local(diffuse, wo, wi);
+                if pdf > 0.0 {
+                    importance += material.eval_local(diffuse, wo, wi) * (wi.z() / pdf);
+                }
+                let (u1, u2): (f64, f64) = (rng.gen(), rng.gen());
+                let r = (1.0 - u1 * u1).sqrt();
+                let wi = Vec3::new(r * (2.0 * PI * u2).cos(), r * (2.0 * PI * u2).sin(), u1);
+                uniform += material.eval_local(diffuse, wo, wi) * (wi.z() * 2.0 * PI);
+            }
+            let diff = (importance - uniform) / f64::from(n);
+            assert!(diff.length() < 0.02, "{:?}", diff);
+            // Reflects less than the diffuse and specular colors put together.
+            let total = importance / f64::from(n);
+            assert!(total.x() < 0.9 && total.x() > 0.5, "{:?}", total);
+        }
+    }
+}
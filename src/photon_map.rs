@@ -0,0 +1,262 @@
+use crate::Vec3;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// Flux carried by a light path arriving at a surface point from `wi`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Photon {
+    position: Vec3,
+    wi: Vec3,
+    power: Vec3,
+}
+
+impl Photon {
+    pub fn new(position: Vec3, wi: Vec3, power: Vec3) -> Self {
+        Self {
+            position,
+            wi,
+            power,
+        }
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    /// Unit direction back towards where the photon came from.
+    pub fn wi(&self) -> Vec3 {
+        self.wi
+    }
+
+    pub fn power(&self) -> Vec3 {
+        self.power
+    }
+}
+
+/// Photons stored as a balanced k-d tree (Jensen, "Realistic Image Synthesis
+/// Using Photon Mapping", 2001).
+///
+/// The tree is implicit: the median of every range of `photons` splits it,
+/// along the axis recorded at the same index.
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<u8>,
+}
+
+fn coordinate(v: Vec3, axis: u8) -> f64 {
+    match axis {
+        0 => v.x(),
+        1 => v.y(),
+        _ => v.z(),
+    }
+}
+
+/// Photon index ordered by squared distance, for the nearest neighbor heap.
+struct Candidate {
+    distance2: f64,
+    index: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance2.total_cmp(&other.distance2)
+    }
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>) -> Self {
+        let mut axes = vec![0; photons.len()];
+        balance(&mut photons, &mut axes);
+        Self { photons, axes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    /// Calls `f` for every photon within `radius` of `point`.
+    pub fn for_each_within<F: FnMut(&Photon)>(&self, point: Vec3, radius: f64, mut f: F) {
+        self.visit(0, self.photons.len(), point, radius * radius, &mut f);
+    }
+
+    fn visit<F: FnMut(&Photon)>(&self, lo: usize, hi: usize, point: Vec3, radius2: f64, f: &mut F) {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        let photon = &self.photons[mid];
+        if (photon.position - point).squared_length() <= radius2 {
+            f(photon);
+        }
+        let axis = self.axes[mid];
+        let delta = coordinate(point, axis) - coordinate(photon.position, axis);
+        let (near, far) = if delta < 0.0 {
+            ((lo, mid), (mid + 1, hi))
+        } else {
+            ((mid + 1, hi), (lo, mid))
+        };
+        self.visit(near.0, near.1, point, radius2, f);
+        if delta * delta <= radius2 {
+            self.visit(far.0, far.1, point, radius2, f);
+        }
+    }
+
+    /// The `k` photons nearest to `point`, closest first, with the squared
+    /// distance to the farthest of them.
+    pub fn nearest(&self, point: Vec3, k: usize) -> (Vec<&Photon>, f64) {
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        if k > 0 {
+            self.search(0, self.photons.len(), point, k, &mut heap);
+        }
+        let radius2 = heap.peek().map_or(0.0, |candidate| candidate.distance2);
+        let photons = heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|candidate| &self.photons[candidate.index])
+            .collect();
+        (photons, radius2)
+    }
+
+    fn search(
+        &self,
+        lo: usize,
+        hi: usize,
+        point: Vec3,
+        k: usize,
+        heap: &mut BinaryHeap<Candidate>,
+    ) {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        let axis = self.axes[mid];
+        let photon = &self.photons[mid];
+        let delta = coordinate(point, axis) - coordinate(photon.position, axis);
+        let (near, far) = if delta < 0.0 {
+            ((lo, mid), (mid + 1, hi))
+        } else {
+            ((mid + 1, hi), (lo, mid))
+        };
+        self.search(near.0, near.1, point, k, heap);
+
+        let distance2 = (photon.position - point).squared_length();
+        if heap.len() < k {
+            heap.push(Candidate {
+                distance2,
+                index: mid,
+            });
+        } else if distance2 < heap.peek().map_or(f64::INFINITY, |c| c.distance2) {
+            heap.pop();
+            heap.push(Candidate {
+                distance2,
+                index: mid,
+            });
+        }
+
+        let worst = if heap.len() < k {
+            f64::INFINITY
+        } else {
+            heap.peek().map_or(f64::INFINITY, |c| c.distance2)
+        };
+        if delta * delta < worst {
+            self.search(far.0, far.1, point, k, heap);
+        }
+    }
+}
+
+/// Arranges `photons` so that the median of every range splits it along the
+/// axis of its largest extent.
+fn balance(photons: &mut [Photon], axes: &mut [u8]) {
+    if photons.len() <= 1 {
+        return;
+    }
+    let (min, max) = photons.iter().fold(
+        (photons[0].position, photons[0].position),
+        |(min, max), photon| (min.min(photon.position), max.max(photon.position)),
+    );
+    let extent = max - min;
+    let axis = if extent.x() >= extent.y() && extent.x() >= extent.z() {
+        0
+    } else if extent.y() >= extent.z() {
+        1
+    } else {
+        2
+    };
+    let mid = photons.len() / 2;
+    photons.select_nth_unstable_by(mid, |a, b| {
+        coordinate(a.position, axis).total_cmp(&coordinate(b.position, axis))
+    });
+    axes[mid] = axis;
+    let (left, right) = photons.split_at_mut(mid);
+    let (left_axes, right_axes) = axes.split_at_mut(mid);
+    balance(left, left_axes);
+    balance(&mut right[1..], &mut right_axes[1..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_map() -> (PhotonMap, Vec<Vec3>) {
+        let mut rng = StdRng::seed_from_u64(1);
+        let positions: Vec<_> = (0..500)
+            .map(|_| Vec3::new(rng.gen(), rng.gen(), rng.gen::<f64>() * 0.2))
+            .collect();
+        let photons = positions
+            .iter()
+            .map(|&p| Photon::new(p, Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 1.0, 1.0)))
+            .collect();
+        (PhotonMap::new(photons), positions)
+    }
+
+    #[test]
+    fn test_within_matches_brute_force() {
+        let (map, positions) = random_map();
+        assert_eq!(map.len(), 500);
+        let point = Vec3::new(0.4, 0.6, 0.1);
+        let mut found = Vec::new();
+        map.for_each_within(point, 0.15, |photon| found.push(photon.position()));
+        let expected = positions
+            .iter()
+            .filter(|&&p| (p - point).length() <= 0.15)
+            .count();
+        assert_eq!(found.len(), expected);
+        assert!(found.iter().all(|&p| (p - point).length() <= 0.15));
+    }
+
+    #[test]
+    fn test_nearest_matches_brute_force() {
+        let (map, mut positions) = random_map();
+        let point = Vec3::new(0.2, 0.3, 0.5);
+        let (nearest, radius2) = map.nearest(point, 10);
+        positions.sort_by(|a, b| {
+            (*a - point)
+                .squared_length()
+                .total_cmp(&(*b - point).squared_length())
+        });
+        let found: Vec<_> = nearest.iter().map(|photon| photon.position()).collect();
+        assert_eq!(found, positions[..10].to_vec());
+        assert_eq!(radius2, (positions[9] - point).squared_length());
+        assert_eq!(map.nearest(point, 0).0.len(), 0);
+    }
+}
@@ -0,0 +1,500 @@
+use crate::photon_map::{Photon, PhotonMap};
+use crate::render::RenderSettings;
+use crate::{Camera, HdrImage, HitRecord, Ray, Scene, Vec3};
+use rand::Rng;
+use rayon::prelude::*;
+use std::f64::consts::PI;
+
+/// Photon mapper with separate caustic and global maps (Jensen, "Realistic
+/// Image Synthesis Using Photon Mapping", 2001).
+///
+/// Camera rays follow specular bounces to the first surface that is not
+/// purely specular. There direct light is sampled explicitly, and light
+/// arriving after further bounces is estimated from the density of the
+/// nearest photons. Photons leave finite lights only, so lights at infinity
+/// contribute direct light alone.
+pub struct PhotonMapper {
+    /// Photons that only bounced off specular surfaces before landing.
+    caustics: PhotonMap,
+    /// Photons that bounced off at least one other surface before landing.
+    global: PhotonMap,
+    nearest: usize,
+    max_depth: u32,
+}
+
+impl PhotonMapper {
+    /// Traces `photon_count` photons from the lights of `scene`, following
+    /// them for up to `max_depth` bounces. Each estimate uses the `nearest`
+    /// photons around the shaded point.
+    pub fn new<R: Rng>(
+        scene: &Scene,
+        photon_count: usize,
+        nearest: usize,
+        max_depth: u32,
+        rng: &mut R,
+    ) -> Self {
+        Self::with_shutter(scene, (0.0, 0.0), photon_count, nearest, max_depth, rng)
+    }
+
+    /// Like `new`, for a camera whose shutter is open over `shutter`; every
+    /// photon leaves its light at a random time in it.
+    pub fn with_shutter<R: Rng>(
+        scene: &Scene,
+        shutter: (f64, f64),
+        photon_count: usize,
+        nearest: usize,
+        max_depth: u32,
+        rng: &mut R,
+    ) -> Self {
+        let mut caustics = Vec::new();
+        let mut global = Vec::new();
+        trace_photons(
+            scene,
+            shutter,
+            photon_count,
+            max_depth,
+            rng,
+            |photon, caustic| {
+                if caustic {
+                    caustics.push(photon);
+                } else {
+                    global.push(photon);
+                }
+            },
+        );
+        Self {
+            caustics: PhotonMap::new(caustics),
+            global: PhotonMap::new(global),
+            nearest,
+            max_depth,
+        }
+    }
+
+    pub fn caustics(&self) -> &PhotonMap {
+        &self.caustics
+    }
+
+    pub fn global(&self) -> &PhotonMap {
+        &self.global
+    }
+
+    /// Radiance arriving along `ray`.
+    pub fn li<R: Rng>(&self, ray: &Ray, scene: &Scene, rng: &mut R) -> Vec3 {
+        let point = match visible_point(ray, scene, self.max_depth, rng) {
+            Ok(point) => point,
+            Err(radiance) => return radiance,
+        };
+        let hit = &point.hit;
+        let indirect = self.estimate(&self.caustics, hit, point.wo)
+            + self.estimate(&self.global, hit, point.wo);
+        point.radiance + point.beta * indirect
+    }
+
+    /// Radiance towards `wo` from the density of the nearest photons of `map`.
+    fn estimate(&self, map: &PhotonMap, hit: &HitRecord, wo: Vec3) -> Vec3 {
+        let (photons, radius2) = map.nearest(hit.point(), self.nearest);
+        if radius2 <= 0.0 {
+            return Vec3::default();
+        }
+        let material = hit.material();
+        let flux = photons.iter().fold(Vec3::default(), |acc, photon| {
+            acc + material.eval(hit, wo, photon.wi()) * photon.power()
+        });
+        flux / (PI * radius2)
+    }
+}
+
+/// Stochastic progressive photon mapping (Hachisuka and Jensen, 2009).
+///
+/// Every pass traces one camera ray per pixel to its first surface that is
+/// not purely specular, then a fresh set of photons. Each pixel gathers the
+/// photons within its own radius, which shrinks as photons accumulate, so
+/// the image converges to the correct result as passes are added.
+pub struct ProgressivePhotonMapper {
+    photons_per_pass: usize,
+    initial_radius: f64,
+    max_depth: u32,
+    alpha: f64,
+}
+
+/// Statistics gathered at one pixel over the passes.
+#[derive(Clone, Copy)]
+struct PixelStats {
+    /// Sum of the radiance found without photons.
+    direct: Vec3,
+    /// Accumulated photon flux, rescaled whenever the radius shrinks.
+    flux: Vec3,
+    radius2: f64,
+    photon_count: f64,
+}
+
+impl ProgressivePhotonMapper {
+    /// Pixels start gathering photons within `initial_radius`.
+    pub fn new(photons_per_pass: usize, initial_radius: f64, max_depth: u32) -> Self {
+        Self {
+            photons_per_pass,
+            initial_radius,
+            max_depth,
+            alpha: 2.0 / 3.0,
+        }
+    }
+
+    /// Fraction of newly gathered photons kept at each pass, in `(0, 1)`;
+    /// lower values shrink the radii faster. Defaults to 2/3.
+    pub fn set_alpha(&mut self, alpha: f64) {
+        self.alpha = alpha;
+    }
+
+    /// Renders one pass per sample per pixel of `settings`.
+    pub fn render(&self, scene: &Scene, camera: &Camera, settings: &RenderSettings) -> HdrImage {
+        let (width, height) = (settings.width(), settings.height());
+        let passes = settings.samples_per_pixel().max(1);
+        let initial = PixelStats {
+            direct: Vec3::default(),
+            flux: Vec3::default(),
+            radius2: self.initial_radius * self.initial_radius,
+            photon_count: 0.0,
+        };
+        let mut pixels = vec![initial; (width * height) as usize];
+        for _ in 0..passes {
+            let visible: Vec<_> = pixels
+                .par_iter_mut()
+                .enumerate()
+                .map(|(idx, stats)| {
+                    let mut rng = rand::thread_rng();
+                    let (x, y) = (idx as u32 % width, idx as u32 / width);
+                    let u = (f64::from(x) + rng.gen::<f64>()) / f64::from(width);
+                    let v = (f64::from(y) + rng.gen::<f64>()) / f64::from(height);
+                    let ray = camera.get_ray(u, v);
+                    match visible_point(&ray, scene, self.max_depth, &mut rng) {
+                        Ok(point) => {
+                            stats.direct += point.radiance;
+                            Some(point)
+                        }
+                        Err(radiance) => {
+                            stats.direct += radiance;
+                            None
+                        }
+                    }
+                })
+                .collect();
+
+            let mut photons = Vec::with_capacity(self.photons_per_pass);
+            let mut rng = rand::thread_rng();
+            trace_photons(
+                scene,
+                (camera.time0(), camera.time1()),
+                self.photons_per_pass,
+                self.max_depth,
+                &mut rng,
+                |photon, _| photons.push(photon),
+            );
+            let map = PhotonMap::new(photons);
+
+            pixels
+                .par_iter_mut()
+                .zip(visible)
+                .for_each(|(stats, point)| {
+                    if let Some(point) = point {
+                        self.gather(&map, &point, stats);
+                    }
+                });
+        }
+
+        let mut image = HdrImage::new(width, height);
+        let passes = f64::from(passes);
+        for (idx, stats) in pixels.iter().enumerate() {
+            let (x, y) = (idx as u32 % width, idx as u32 / width);
+            image[(x, y)] = stats.direct / passes + stats.flux / (passes * PI * stats.radius2);
+        }
+        image
+    }
+
+    /// Adds the photons of this pass near `point` to the pixel and shrinks its radius.
+    fn gather(&self, map: &PhotonMap, point: &VisiblePoint, stats: &mut PixelStats) {
+        let hit = &point.hit;
+        let material = hit.material();
+        let mut count = 0.0;
+        let mut flux = Vec3::default();
+        map.for_each_within(hit.point(), stats.radius2.sqrt(), |photon| {
+            count += 1.0;
+            flux += material.eval(hit, point.wo, photon.wi()) * photon.power();
+        });
+        if count == 0.0 {
+            return;
+        }
+        let photon_count = stats.photon_count + self.alpha * count;
+        let radius2 = stats.radius2 * photon_count / (stats.photon_count + count);
+        stats.flux = (stats.flux + point.beta * flux) * (radius2 / stats.radius2);
+        stats.radius2 = radius2;
+        stats.photon_count = photon_count;
+    }
+}
+
+/// First surface that is not purely specular along a camera path.
+struct VisiblePoint<'s> {
+    hit: HitRecord<'s>,
+    wo: Vec3,
+    /// Throughput of the specular bounces leading to the point.
+    beta: Vec3,
+    /// Emitted light seen on the way and direct light at the point.
+    radiance: Vec3,
+}
+
+/// Follows `ray` through specular bounces; returns the visible point, or
+/// the radiance found if the path ends before reaching one.
+fn visible_point<'s, R: Rng>(
+    ray: &Ray,
+    scene: &'s Scene,
+    max_depth: u32,
+    rng: &mut R,
+) -> Result<VisiblePoint<'s>, Vec3> {
+    let mut ray = Ray::new(ray.origin(), ray.direction().normalize(), ray.time());
+    let mut beta = Vec3::new(1.0, 1.0, 1.0);
+    let mut radiance = Vec3::default();
+    let mut depth = 0;
+    loop {
+        let hit = match scene.world().hit(&ray, 0.001, f64::MAX) {
+            Some(hit) => hit,
+            None => {
+                let lights = scene.lights();
+                for &idx in scene.infinite_lights() {
+                    radiance += beta * lights[idx].le(ray.direction());
+                }
+                return Err(radiance);
+            }
+        };
+        let material = hit.material();
+        if material.is_interface() {
+            ray = Ray::new(hit.point(), ray.direction(), ray.time());
+            continue;
+        }
+        let wo = -ray.direction();
+        radiance += beta * material.emitted(&hit, wo);
+        if !material.is_specular() {
+            radiance += beta * direct_light(scene, &hit, wo, ray.time(), rng);
+            return Ok(VisiblePoint {
+                hit,
+                wo,
+                beta,
+                radiance,
+            });
+        }
+        if depth >= max_depth {
+            return Err(radiance);
+        }
+        let sample = match material.sample(&hit, wo, rng.gen(), (rng.gen(), rng.gen())) {
+            Some(sample) if sample.pdf() > 0.0 => sample,
+            _ => return Err(radiance),
+        };
+        beta *= sample.value() * (sample.wi().dot(hit.normal()).abs() / sample.pdf());
+        ray = Ray::new(hit.point(), sample.wi(), ray.time());
+        depth += 1;
+    }
+}
+
+/// Light sampling estimate of the light reaching `hit` directly and scattered towards `wo`.
+fn direct_light<R: Rng>(scene: &Scene, hit: &HitRecord, wo: Vec3, time: f64, rng: &mut R) -> Vec3 {
+    let (light, pmf) = match scene
+        .light_sampler()
+        .sample(hit.point(), hit.normal(), rng.gen())
+    {
+        Some(choice) => choice,
+        None => return Vec3::default(),
+    };
+    let sample = match scene.lights()[light].sample_li(hit.point(), (rng.gen(), rng.gen())) {
+        Some(sample) if sample.pdf() > 0.0 => sample,
+        _ => return Vec3::default(),
+    };
+    let wi = sample.wi();
+    let f = hit.material().eval(hit, wo, wi) * wi.dot(hit.normal()).abs();
+    if f == Vec3::default() || !scene.unoccluded(hit.point(), wi, sample.distance(), time) {
+        return Vec3::default();
+    }
+    f * sample.radiance() / (pmf * sample.pdf())
+}
+
+/// Traces `count` photons from the finite lights of `scene` and passes each
+/// one landing on a surface that is not purely specular to `store`, after at
+/// least one bounce, together with whether all its bounces were specular.
+fn trace_photons<R: Rng, F: FnMut(Photon, bool)>(
+    scene: &Scene,
+    (time0, time1): (f64, f64),
+    count: usize,
+    max_depth: u32,
+    rng: &mut R,
+    mut store: F,
+) {
+    for _ in 0..count {
+        let (light, pmf) = match scene.sample_emitter(rng.gen()) {
+            Some(choice) => choice,
+            None => return,
+        };
+        let u_position = (rng.gen(), rng.gen());
+        let emission = match scene.lights()[light].sample_le(u_position, (rng.gen(), rng.gen())) {
+            Some(emission) if emission.pdf_position() > 0.0 && emission.pdf_direction() > 0.0 => {
+                emission
+            }
+            _ => continue,
+        };
+        let direction = emission.direction();
+        let cos = if emission.normal() == Vec3::default() {
+            1.0
+        } else {
+            emission.normal().dot(direction).abs()
+        };
+        let pdf = pmf * emission.pdf_position() * emission.pdf_direction() * count as f64;
+        let mut power = emission.radiance() * (cos / pdf);
+        let time = time0 + rng.gen::<f64>() * (time1 - time0);
+        let mut ray = Ray::new(emission.origin(), direction, time);
+        let mut specular_only = true;
+        let mut depth = 0;
+        while depth <= max_depth && power != Vec3::default() {
+            let hit = match scene.world().hit(&ray, 0.001, f64::MAX) {
+                Some(hit) => hit,
+                None => break,
+            };
+            let material = hit.material();
+            if material.is_interface() {
+                ray = Ray::new(hit.point(), ray.direction(), ray.time());
+                continue;
+            }
+            let wo = -ray.direction();
+            if depth > 0 && !material.is_specular() {
+                store(Photon::new(hit.point(), wo, power), specular_only);
+            }
+            let sample = match material.sample(&hit, wo, rng.gen(), (rng.gen(), rng.gen())) {
+                Some(sample) if sample.pdf() > 0.0 => sample,
+                _ => break,
+            };
+            let throughput = sample.value() * (sample.wi().dot(hit.normal()).abs() / sample.pdf());
+            // Russian roulette keeps the power of surviving photons about constant.
+            let survival = throughput
+                .x()
+                .max(throughput.y())
+                .max(throughput.z())
+                .min(1.0);
+            if rng.gen::<f64>() >= survival {
+                break;
+            }
+            power *= throughput / survival;
+            specular_only &= sample.lobe().is_delta();
+            ray = Ray::new(hit.point(), sample.wi(), ray.time());
+            depth += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        render, ConstantEnvironment, Dielectric, DiffuseLight, HitList, Integrator, Lambertian,
+        LightSampling, PathTracer, Rectangle, Sphere,
+    };
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::sync::Arc;
+
+    /// Diffuse floor under a square light, with a glass sphere between them.
+    fn caustic_scene(glass: bool) -> Scene {
+        let mut world = HitList::new();
+        world.push(Rectangle::new(
+            Vec3::new(-5.0, 0.0, 5.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -10.0),
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        ));
+        world.push(Rectangle::new(
+            Vec3::new(-0.5, 4.0, -0.5),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Arc::new(DiffuseLight::new(Vec3::new(20.0, 20.0, 20.0))),
+        ));
+        if glass {
+            world.push(Sphere::new(
+                Vec3::new(0.0, 2.0, 0.0),
+                0.8,
+                Arc::new(Dielectric::new(1.5)),
+            ));
+        }
+        Scene::with_environment(world, Arc::new(ConstantEnvironment::new(Vec3::default())))
+    }
+
+    /// Radiance of the floor point under the sphere, seen from above at an angle.
+    fn floor_radiance(li: &mut dyn FnMut(&Ray) -> Vec3) -> Vec3 {
+        let ray = Ray::new(Vec3::new(3.0, 3.0, 0.0), Vec3::new(-1.0, -1.0, 0.0), 0.0);
+        let n = 2000;
+        let mut acc = Vec3::default();
+        for _ in 0..n {
+            acc += li(&ray);
+        }
+        acc / f64::from(n)
+    }
+
+    #[test]
+    fn test_photon_maps_split_caustics() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mapper = PhotonMapper::new(&caustic_scene(true), 20_000, 50, 5, &mut rng);
+        assert!(!mapper.caustics().is_empty() && !mapper.global().is_empty());
+        let without_glass = PhotonMapper::new(&caustic_scene(false), 20_000, 50, 5, &mut rng);
+        assert!(without_glass.caustics().is_empty());
+    }
+
+    #[test]
+    fn test_caustic_matches_path_tracer() {
+        // The floor is only lit through the glass, which light sampling
+        // cannot connect through; the path tracer relies on BSDF sampling.
+        let scene = caustic_scene(true);
+        let mut rng = StdRng::seed_from_u64(2);
+        let mapper = PhotonMapper::new(&scene, 200_000, 100, 8, &mut rng);
+        let photons = floor_radiance(&mut |ray| mapper.li(ray, &scene, &mut rng));
+        let tracer = PathTracer::new(8, LightSampling::Mis);
+        let mut n = 0;
+        let mut expected = Vec3::default();
+        while n < 100 {
+            expected += floor_radiance(&mut |ray| tracer.li(ray, &scene, &mut rng));
+            n += 1;
+        }
+        expected /= f64::from(n);
+        assert!(
+            (photons - expected).length() < 0.15 * expected.length(),
+            "{:?} {:?}",
+            photons,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_progressive_matches_path_tracer() {
+        // Looking straight down at the floor below the light.
+        let scene = caustic_scene(false);
+        let camera = Camera::new(
+            Vec3::new(-0.1, 1.0, 0.1),
+            Vec3::new(0.2, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -0.2),
+            Vec3::new(0.0, 2.0, 0.0),
+        );
+        let path = Integrator::Path(PathTracer::new(5, LightSampling::Mis));
+        let expected = render(&scene, &camera, &RenderSettings::new(2, 2, 1000, path));
+        let sppm = Integrator::ProgressivePhotonMap(ProgressivePhotonMapper::new(2_000, 0.3, 5));
+        let image = render(&scene, &camera, &RenderSettings::new(2, 2, 200, sppm));
+        // Few photons per pass leave each pixel noisy; compare the averages.
+        let mean = |image: &HdrImage| {
+            let mut sum = Vec3::default();
+            for y in 0..2 {
+                for x in 0..2 {
+                    sum += image[(x, y)];
+                }
+            }
+            sum / 4.0
+        };
+        let (got, want) = (mean(&image), mean(&expected));
+        assert!(
+            (got - want).length() < 0.15 * want.length(),
+            "{:?} {:?}",
+            got,
+            want
+        );
+    }
+}
@@ -0,0 +1,339 @@
+use crate::error::invalid_data;
+use crate::material::Scatter;
+use crate::{Mesh, Vec3};
+use std::io::{Error, Read};
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Reads an ASCII or binary little-endian PLY mesh.
+///
+/// Vertex positions, normals (`nx`, `ny`, `nz`), colors (`red`, `green`, `blue`)
+/// and texture coordinates (`u`, `v` or `s`, `t`) are loaded; polygonal faces are
+/// triangulated as fans. Other elements and properties are skipped.
+pub fn read_ply<R: Read>(input: &mut R, material: Arc<dyn Scatter>) -> Result<Mesh, Error> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    let (format, elements, body_start) = parse_header(&data)?;
+    let mut body = match format {
+        Format::Ascii => Body::Ascii(
+            std::str::from_utf8(&data[body_start..])
+                .map_err(|_| invalid_data("PLY body is not valid text"))?
+                .split_ascii_whitespace(),
+        ),
+        Format::BinaryLittleEndian => Body::Binary(&data[body_start..]),
+    };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    let mut uvs = Vec::new();
+    let mut triangles = Vec::new();
+    for element in &elements {
+        for _ in 0..element.count {
+            let mut scalars = Vec::with_capacity(element.properties.len());
+            for property in &element.properties {
+                match *property {
+                    Property::Scalar(ref name, ty) => {
+                        scalars.push((name.as_str(), ty, body.read(ty)?));
+                    }
+                    Property::List(ref name, count_ty, item_ty) => {
+                        let count = body.read(count_ty)?;
+                        let items = (0..as_index(count)?)
+                            .map(|_| body.read(item_ty))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let is_indices = name == "vertex_indices" || name == "vertex_index";
+                        if element.name == "face" && is_indices {
+                            let items = items
+                                .into_iter()
+                                .map(as_index)
+                                .collect::<Result<Vec<_>, _>>()?;
+                            for k in 1..items.len().saturating_sub(1) {
+                                triangles.push([items[0], items[k], items[k + 1]]);
+                            }
+                        }
+                    }
+                }
+            }
+            if element.name == "vertex" {
+                let get = |names: &[&str]| {
+                    scalars
+                        .iter()
+                        .find(|(name, _, _)| names.contains(name))
+                        .map(|&(_, ty, value)| (ty, value))
+                };
+                let coord = |name| get(&[name]).map_or(0.0, |(_, value)| value);
+                positions.push(Vec3::new(coord("x"), coord("y"), coord("z")));
+                if let (Some(nx), Some(ny), Some(nz)) = (get(&["nx"]), get(&["ny"]), get(&["nz"])) {
+                    normals.push(Vec3::new(nx.1, ny.1, nz.1));
+                }
+                let channel = |(ty, value): (Scalar, f64)| match ty {
+                    Scalar::U8 => value / 255.0,
+                    Scalar::U16 => value / 65535.0,
+                    _ => value,
+                };
+                if let (Some(r), Some(g), Some(b)) = (
+                    get(&["red", "r", "diffuse_red"]),
+                    get(&["green", "g", "diffuse_green"]),
+                    get(&["blue", "b", "diffuse_blue"]),
+                ) {
+                    colors.push(Vec3::new(channel(r), channel(g), channel(b)));
+                }
+                if let (Some(u), Some(v)) =
+                    (get(&["u", "s", "texture_u"]), get(&["v", "t", "texture_v"]))
+                {
+                    uvs.push((u.1, v.1));
+                }
+            }
+        }
+    }
+    if triangles
+        .iter()
+        .flatten()
+        .any(|&idx| idx >= positions.len())
+    {
+        return Err(invalid_data("PLY face refers to a missing vertex"));
+    }
+    Ok(Mesh::new(
+        positions, normals, colors, uvs, triangles, material,
+    ))
+}
+
+/// List length or vertex index, which must not be negative.
+fn as_index(value: f64) -> Result<usize, Error> {
+    if value >= 0.0 {
+        Ok(value as usize)
+    } else {
+        Err(invalid_data("Negative PLY index"))
+    }
+}
+
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary(&'a [u8]),
+}
+
+impl Body<'_> {
+    fn read(&mut self, ty: Scalar) -> Result<f64, Error> {
+        match self {
+            Body::Ascii(tokens) => tokens
+                .next()
+                .and_then(|token| token.parse().ok())
+                .ok_or_else(|| invalid_data("Malformed PLY value")),
+            Body::Binary(bytes) => {
+                let size = match ty {
+                    Scalar::I8 | Scalar::U8 => 1,
+                    Scalar::I16 | Scalar::U16 => 2,
+                    Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+                    Scalar::F64 => 8,
+                };
+                if bytes.len() < size {
+                    return Err(invalid_data("Truncated PLY data"));
+                }
+                let (head, tail) = bytes.split_at(size);
+                *bytes = tail;
+                let mut buf = [0; 8];
+                buf[..size].copy_from_slice(head);
+                Ok(match ty {
+                    Scalar::I8 => f64::from(head[0] as i8),
+                    Scalar::U8 => f64::from(head[0]),
+                    Scalar::I16 => f64::from(i16::from_le_bytes([buf[0], buf[1]])),
+                    Scalar::U16 => f64::from(u16::from_le_bytes([buf[0], buf[1]])),
+                    Scalar::I32 => f64::from(i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]])),
+                    Scalar::U32 => f64::from(u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]])),
+                    Scalar::F32 => f64::from(f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]])),
+                    Scalar::F64 => f64::from_le_bytes(buf),
+                })
+            }
+        }
+    }
+}
+
+fn parse_header(data: &[u8]) -> Result<(Format, Vec<Element>, usize), Error> {
+    const END: &[u8] = b"end_header";
+    let end = data
+        .windows(END.len())
+        .position(|window| window == END)
+        .ok_or_else(|| invalid_data("PLY header is not terminated"))?;
+    let body_start = data[end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map(|pos| end + pos + 1)
+        .ok_or_else(|| invalid_data("PLY header is not terminated"))?;
+    let header = std::str::from_utf8(&data[..end])
+        .map_err(|_| invalid_data("PLY header is not valid text"))?;
+
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err(invalid_data("Not a PLY file"));
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words: Vec<_> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", "ascii", _] => format = Some(Format::Ascii),
+            ["format", "binary_little_endian", _] => format = Some(Format::BinaryLittleEndian),
+            ["format", ..] => return Err(invalid_data("Unsupported PLY format")),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid_data("Malformed PLY element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_ty, item_ty, name] => {
+                let property =
+                    Property::List(name.to_string(), scalar(count_ty)?, scalar(item_ty)?);
+                push_property(&mut elements, property)?;
+            }
+            ["property", ty, name] => {
+                let property = Property::Scalar(name.to_string(), scalar(ty)?);
+                push_property(&mut elements, property)?;
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(invalid_data("Malformed PLY header")),
+        }
+    }
+    let format = format.ok_or_else(|| invalid_data("PLY format is missing"))?;
+    Ok((format, elements, body_start))
+}
+
+fn push_property(elements: &mut [Element], property: Property) -> Result<(), Error> {
+    elements
+        .last_mut()
+        .ok_or_else(|| invalid_data("PLY property outside of an element"))?
+        .properties
+        .push(property);
+    Ok(())
+}
+
+fn scalar(name: &str) -> Result<Scalar, Error> {
+    Ok(match name {
+        "char" | "int8" => Scalar::I8,
+        "uchar" | "uint8" => Scalar::U8,
+        "short" | "int16" => Scalar::I16,
+        "ushort" | "uint16" => Scalar::U16,
+        "int" | "int32" => Scalar::I32,
+        "uint" | "uint32" => Scalar::U32,
+        "float" | "float32" => Scalar::F32,
+        "double" | "float64" => Scalar::F64,
+        _ => return Err(invalid_data("Unknown PLY property type")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+
+    fn material() -> Arc<dyn Scatter> {
+        Arc::new(Lambertian::new(Vec3::new(1.0, 1.0, 1.0)))
+    }
+
+    #[test]
+    fn test_read_ascii() {
+        let mut input: &[u8] = b"ply
+format ascii 1.0
+comment a quad
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 0 255 0
+1 1 0 0 0 255
+0 1 0 255 255 255
+4 0 1 2 3
+";
+        let mesh = read_ply(&mut input, material()).unwrap();
+        assert_eq!(mesh.positions().len(), 4);
+        assert_eq!(mesh.positions()[2], Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(mesh.colors()[1], Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(mesh.triangles(), &[[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn test_reject_negative_index() {
+        let mut input: &[u8] = b"ply
+format ascii 1.0
+element vertex 3
+property float x
+property float y
+property float z
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0
+1 0 0
+0 1 0
+3 0 -1 2
+";
+        let err = read_ply(&mut input, material()).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_read_binary() {
+        let mut input = b"ply
+format binary_little_endian 1.0
+element vertex 3
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+element face 1
+property list uchar uint vertex_indices
+end_header
+"
+        .to_vec();
+        for v in &[[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] {
+            for x in v.iter().chain(&[0.0, 0.0, 1.0]) {
+                input.extend_from_slice(&x.to_le_bytes());
+            }
+        }
+        input.push(3);
+        for idx in 0u32..3 {
+            input.extend_from_slice(&idx.to_le_bytes());
+        }
+        let mesh = read_ply(&mut input.as_slice(), material()).unwrap();
+        assert_eq!(mesh.positions()[1], Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(mesh.normals()[0], Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(mesh.triangles(), &[[0, 1, 2]]);
+    }
+}
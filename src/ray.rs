@@ -4,11 +4,16 @@ use crate::vec3::Vec3;
 pub struct Ray {
     origin: Vec3,
     direction: Vec3,
+    time: f64,
 }
 
 impl Ray {
-    pub fn new(origin: Vec3, direction: Vec3) -> Self {
-        Self { origin, direction }
+    pub fn new(origin: Vec3, direction: Vec3, time: f64) -> Self {
+        Self {
+            origin,
+            direction,
+            time,
+        }
     }
 
     pub fn origin(&self) -> Vec3 {
@@ -19,6 +24,10 @@ impl Ray {
         self.direction
     }
 
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
     pub fn point_at_parameter(&self, t: f64) -> Vec3 {
         self.origin + self.direction * t
     }
@@ -32,8 +41,12 @@ mod tests {
     fn test_new() {
         let origin = Vec3::new(1.0, 2.0, 3.0);
         let direction = Vec3::new(4.0, 5.0, 6.0);
-        let actual = Ray::new(origin, direction);
-        let expected = Ray { origin, direction };
+        let actual = Ray::new(origin, direction, 0.5);
+        let expected = Ray {
+            origin,
+            direction,
+            time: 0.5,
+        };
         assert_eq!(actual, expected);
     }
 
@@ -41,7 +54,7 @@ mod tests {
     fn test_point_at_parameter() {
         let origin = Vec3::new(1.0, 2.0, 3.0);
         let direction = Vec3::new(4.0, 5.0, 6.0);
-        let ray = Ray::new(origin, direction);
+        let ray = Ray::new(origin, direction, 0.0);
         let vec3 = ray.point_at_parameter(2.0);
         assert_eq!(vec3, Vec3::new(9.0, 12.0, 15.0));
     }
@@ -0,0 +1,337 @@
+use crate::hit::HitRecord;
+use crate::light::{cosine_emission, EmissionSample, Light, LightSample};
+use crate::light_sampler::LightBounds;
+use crate::material::Scatter;
+use crate::{Aabb, Hit, Ray, Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Rectangle spanned by two perpendicular edges from `corner`, facing along
+/// `edge_u × edge_v`.
+#[derive(Clone)]
+pub struct Rectangle {
+    corner: Vec3,
+    edge_u: Vec3,
+    edge_v: Vec3,
+    normal: Vec3,
+    material: Arc<dyn Scatter>,
+    light: Option<usize>,
+}
+
+/// Solid angle subtended by a rectangle, set up for sampling from one point
+/// (Ureña, Fajardo and King, "An Area-Preserving Parametrization for
+/// Spherical Rectangles", 2013).
+struct SphericalRectangle {
+    origin: Vec3,
+    x: Vec3,
+    y: Vec3,
+    z: Vec3,
+    x0: f64,
+    x1: f64,
+    y0: f64,
+    y1: f64,
+    z0: f64,
+    b0: f64,
+    b1: f64,
+    k: f64,
+    solid_angle: f64,
+}
+
+impl Rectangle {
+    pub fn new(corner: Vec3, edge_u: Vec3, edge_v: Vec3, material: Arc<dyn Scatter>) -> Self {
+        assert!(
+            edge_u.dot(edge_v).abs() < 1e-9 * edge_u.length() * edge_v.length(),
+            "rectangle edges must be perpendicular"
+        );
+        Self {
+            corner,
+            edge_u,
+            edge_v,
+            normal: edge_u.cross(edge_v).normalize(),
+            material,
+            light: None,
+        }
+    }
+
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let denom = self.normal.dot(ray.direction());
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = self.normal.dot(self.corner - ray.origin()) / denom;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let offset = ray.point_at_parameter(t) - self.corner;
+        let u = offset.dot(self.edge_u) / self.edge_u.squared_length();
+        let v = offset.dot(self.edge_v) / self.edge_v.squared_length();
+        if (0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v) {
+            Some((t, u, v))
+        } else {
+            None
+        }
+    }
+
+    fn spherical_rectangle(&self, origin: Vec3) -> Option<SphericalRectangle> {
+        let (length_u, length_v) = (self.edge_u.length(), self.edge_v.length());
+        let x = self.edge_u / length_u;
+        let y = self.edge_v / length_v;
+        let mut z = x.cross(y);
+        let d = self.corner - origin;
+        let mut z0 = d.dot(z);
+        if z0.abs() < 1e-9 {
+            return None;
+        }
+        if z0 > 0.0 {
+            z = -z;
+            z0 = -z0;
+        }
+        let (x0, y0) = (d.dot(x), d.dot(y));
+        let (x1, y1) = (x0 + length_u, y0 + length_v);
+
+        let n0 = Vec3::new(0.0, z0, -y0).normalize();
+        let n1 = Vec3::new(-z0, 0.0, x1).normalize();
+        let n2 = Vec3::new(0.0, -z0, y1).normalize();
+        let n3 = Vec3::new(z0, 0.0, -x0).normalize();
+        let angle = |a: Vec3, b: Vec3| (-a.dot(b)).clamp(-1.0, 1.0).acos();
+        let g0 = angle(n0, n1);
+        let g1 = angle(n1, n2);
+        let g2 = angle(n2, n3);
+        let g3 = angle(n3, n0);
+        let k = 2.0 * PI - g2 - g3;
+        let solid_angle = g0 + g1 - k;
+        if solid_angle <= 1e-12 {
+            return None;
+        }
+        Some(SphericalRectangle {
+            origin,
+            x,
+            y,
+            z,
+            x0,
+            x1,
+            y0,
+            y1,
+            z0,
+            b0: n0.z(),
+            b1: n2.z(),
+            k,
+            solid_angle,
+        })
+    }
+}
+
+impl SphericalRectangle {
+    /// Point on the rectangle whose direction is uniformly distributed in solid angle.
+    fn sample(&self, (u1, u2): (f64, f64)) -> Vec3 {
+        let au = u1 * self.solid_angle + self.k;
+        let fu = (au.cos() * self.b0 - self.b1) / au.sin();
+        let cu = (1.0 / (fu * fu + self.b0 * self.b0).sqrt())
+            .copysign(fu)
+            .clamp(-1.0, 1.0);
+        let xu = (-(cu * self.z0) / (1.0 - cu * cu).max(0.0).sqrt()).clamp(self.x0, self.x1);
+        let d = (xu * xu + self.z0 * self.z0).sqrt();
+        let h0 = self.y0 / (d * d + self.y0 * self.y0).sqrt();
+        let h1 = self.y1 / (d * d + self.y1 * self.y1).sqrt();
+        let hv = h0 + u2 * (h1 - h0);
+        let yv = if hv * hv < 1.0 - 1e-12 {
+            (hv * d / (1.0 - hv * hv).sqrt()).clamp(self.y0, self.y1)
+        } else {
+            self.y1
+        };
+        self.origin + xu * self.x + yv * self.y + self.z0 * self.z
+    }
+}
+
+impl Hit for Rectangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t, u, v) = self.intersect(ray, t_min, t_max)?;
+        let record = HitRecord::with_uv(
+            t,
+            ray.point_at_parameter(t),
+            self.normal,
+            (u, v),
+            &*self.material,
+        )
+        .with_tangent(self.edge_u / self.edge_u.length());
+        Some(match self.light {
+            Some(light) => record.with_light(light),
+            None => record,
+        })
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        let corners = [
+            self.corner,
+            self.corner + self.edge_u,
+            self.corner + self.edge_v,
+            self.corner + self.edge_u + self.edge_v,
+        ];
+        let pad = Vec3::new(1e-6, 1e-6, 1e-6);
+        let (min, max) = corners
+            .iter()
+            .fold((corners[0], corners[0]), |(min, max), &corner| {
+                (min.min(corner), max.max(corner))
+            });
+        Some(Aabb::new(min - pad, max + pad))
+    }
+
+    fn register_lights(&mut self, lights: &mut Vec<Arc<dyn Light>>) {
+        if self.material.is_emissive() {
+            self.light = Some(lights.len());
+            lights.push(Arc::new(self.clone()));
+        }
+    }
+
+    fn collect_materials<'a>(&'a self, materials: &mut Vec<&'a dyn Scatter>) {
+        materials.push(&*self.material);
+    }
+}
+
+/// Emissive rectangle sampled uniformly over the solid angle it subtends.
+impl Light for Rectangle {
+    fn sample_li(&self, point: Vec3, u: (f64, f64)) -> Option<LightSample> {
+        let rectangle = self.spherical_rectangle(point)?;
+        let to_light = rectangle.sample(u) - point;
+        let distance = to_light.length();
+        let wi = to_light / distance;
+        let offset = to_light + point - self.corner;
+        let uv = (
+            offset.dot(self.edge_u) / self.edge_u.squared_length(),
+            offset.dot(self.edge_v) / self.edge_v.squared_length(),
+        );
+        let record =
+            HitRecord::with_uv(distance, point + to_light, self.normal, uv, &*self.material);
+        let radiance = self.material.emitted(&record, -wi);
+        Some(
+            LightSample::new(wi, radiance, distance, 1.0 / rectangle.solid_angle, false)
+                .with_normal(self.normal),
+        )
+    }
+
+    fn pdf_li(&self, point: Vec3, wi: Vec3) -> f64 {
+        if self
+            .intersect(&Ray::new(point, wi, 0.0), 0.0, f64::MAX)
+            .is_none()
+        {
+            return 0.0;
+        }
+        self.spherical_rectangle(point)
+            .map_or(0.0, |rectangle| 1.0 / rectangle.solid_angle)
+    }
+
+    fn sample_le(&self, (u1, u2): (f64, f64), u_direction: (f64, f64)) -> Option<EmissionSample> {
+        let point = self.corner + u1 * self.edge_u + u2 * self.edge_v;
+        let record = HitRecord::with_uv(0.0, point, self.normal, (u1, u2), &*self.material);
+        let area = self.edge_u.cross(self.edge_v).length();
+        cosine_emission(point, self.normal, 1.0 / area, u_direction, |direction| {
+            self.material.emitted(&record, direction)
+        })
+    }
+
+    fn pdf_le(&self, _point: Vec3, normal: Vec3, direction: Vec3) -> (f64, f64) {
+        let area = self.edge_u.cross(self.edge_v).length();
+        (1.0 / area, normal.dot(direction).max(0.0) / PI)
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let center = self.corner + 0.5 * (self.edge_u + self.edge_v);
+        let record = HitRecord::with_uv(0.0, center, self.normal, (0.5, 0.5), &*self.material);
+        let radiance = self.material.emitted(&record, self.normal);
+        let area = self.edge_u.cross(self.edge_v).length();
+        Some(LightBounds::new(
+            self.bounding_box(0.0, 0.0)?,
+            self.normal,
+            PI * area * radiance.luminance(),
+            1.0,
+            0.0,
+            false,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DiffuseLight;
+
+    fn square_light() -> Rectangle {
+        // 2x2 square at y = 1 facing down.
+        Rectangle::new(
+            Vec3::new(-1.0, 1.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            Arc::new(DiffuseLight::new(Vec3::new(1.0, 1.0, 1.0))),
+        )
+    }
+
+    #[test]
+    fn test_rectangle_solid_angle() {
+        // Seen from the center of a cube, a face covers a sixth of the sphere.
+        let light = square_light();
+        let pdf = light.pdf_li(Vec3::default(), Vec3::new(0.0, 1.0, 0.0));
+        assert!((pdf - 3.0 / (2.0 * PI)).abs() < 1e-9, "{}", pdf);
+        assert_eq!(light.pdf_li(Vec3::default(), Vec3::new(1.0, 0.1, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn test_rectangle_samples_lie_on_rectangle() {
+        let light = square_light();
+        let point = Vec3::new(0.3, -0.5, 2.0);
+        for i in 0..10 {
+            for j in 0..10 {
+                let u = ((i as f64 + 0.5) / 10.0, (j as f64 + 0.5) / 10.0);
+                let sample = light.sample_li(point, u).unwrap();
+                let ray = Ray::new(point, sample.wi(), 0.0);
+                let hit = light.hit(&ray, 0.0, f64::MAX).unwrap();
+                assert!((hit.t() - sample.distance()).abs() < 1e-6);
+                assert!((light.pdf_li(point, sample.wi()) - sample.pdf()).abs() < 1e-9);
+                assert_eq!(sample.radiance(), Vec3::new(1.0, 1.0, 1.0));
+            }
+        }
+        // The back side does not emit.
+        let above = light
+            .sample_li(Vec3::new(0.0, 3.0, 0.0), (0.5, 0.5))
+            .unwrap();
+        assert_eq!(above.radiance(), Vec3::default());
+    }
+
+    #[test]
+    fn test_rectangle_sampling_is_uniform_in_solid_angle() {
+        // Integrate the cosine to the plane normal, which is the form factor.
+        let light = square_light();
+        let n = 200;
+        let mut estimate = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let u = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let sample = light.sample_li(Vec3::default(), u).unwrap();
+                estimate += sample.wi().y() / sample.pdf();
+            }
+        }
+        estimate /= f64::from(n * n);
+        // Irradiance from a unit-radiance square: 4 * atan(1 / sqrt(2)) / sqrt(2).
+        let expected = 4.0 * (1.0 / 2f64.sqrt()).atan() / 2f64.sqrt();
+        assert!(
+            (estimate - expected).abs() < 1e-3,
+            "{} {}",
+            estimate,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_rectangle_emission_sampling() {
+        let light = square_light();
+        for &u in &[(0.1, 0.2), (0.5, 0.5), (0.9, 0.7)] {
+            let sample = light.sample_le(u, (u.1, u.0)).unwrap();
+            assert!((sample.origin().y() - 1.0).abs() < 1e-12);
+            assert!(sample.direction().y() < 0.0);
+            assert_eq!(sample.radiance(), Vec3::new(1.0, 1.0, 1.0));
+            let (pdf_position, pdf_direction) =
+                light.pdf_le(sample.origin(), sample.normal(), sample.direction());
+            assert!((pdf_position - 0.25).abs() < 1e-12);
+            assert!((pdf_direction - sample.pdf_direction()).abs() < 1e-9);
+        }
+    }
+}
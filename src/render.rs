@@ -0,0 +1,431 @@
+use crate::{
+    Aov, BidirectionalPathTracer, Camera, DebugMode, Framebuffer, HdrImage,
+    MetropolisLightTransport, PathTracer, PhotonMapper, ProgressivePhotonMapper, Scene, Vec3,
+    WhittedTracer,
+};
+use rand::Rng;
+use rayon::prelude::*;
+
+/// Algorithm estimating the radiance reaching each pixel.
+pub enum Integrator {
+    Path(PathTracer),
+    Bidirectional(BidirectionalPathTracer),
+    /// Photon maps traced beforehand for the scene being rendered.
+    PhotonMap(PhotonMapper),
+    /// Renders in passes of its own, one per sample per pixel.
+    ProgressivePhotonMap(ProgressivePhotonMapper),
+    /// Renders with Markov chains of its own, splatting every mutation.
+    Metropolis(MetropolisLightTransport),
+    /// Shows geometry or material properties instead of radiance.
+    Debug(DebugMode),
+    /// Traces a regular grid of rays per pixel of its own.
+    Whitted(WhittedTracer),
+}
+
+/// Integrators that estimate each pixel from independent camera samples.
+enum PerSample<'a> {
+    Path(&'a PathTracer),
+    Bidirectional(&'a BidirectionalPathTracer),
+    PhotonMap(&'a PhotonMapper),
+    Debug(&'a DebugMode),
+}
+
+impl PerSample<'_> {
+    /// Radiance through image coordinates `(u, v)`; contributions to other
+    /// image points go to `splat` and output variables to `aov`.
+    fn sample<R: Rng>(
+        &self,
+        uv: (f64, f64),
+        camera: &Camera,
+        scene: &Scene,
+        rng: &mut R,
+        splat: &mut dyn FnMut((f64, f64), Vec3),
+        aov: &mut dyn FnMut(Aov, Vec3),
+    ) -> Vec3 {
+        match self {
+            PerSample::Path(tracer) => {
+                tracer.li_with_aovs(&camera.get_ray(uv.0, uv.1), scene, rng, aov)
+            }
+            PerSample::Bidirectional(tracer) => tracer.li(uv, camera, scene, rng, splat),
+            PerSample::PhotonMap(mapper) => mapper.li(&camera.get_ray(uv.0, uv.1), scene, rng),
+            PerSample::Debug(mode) => mode.li(&camera.get_ray(uv.0, uv.1), camera, scene, rng),
+        }
+    }
+
+    fn writes_aovs(&self) -> bool {
+        matches!(self, PerSample::Path(_))
+    }
+
+    fn splats(&self) -> bool {
+        matches!(self, PerSample::Bidirectional(_))
+    }
+}
+
+pub struct RenderSettings {
+    width: u32,
+    height: u32,
+    samples_per_pixel: u32,
+    integrator: Integrator,
+}
+
+impl RenderSettings {
+    pub fn new(width: u32, height: u32, samples_per_pixel: u32, integrator: Integrator) -> Self {
+        Self {
+            width,
+            height,
+            samples_per_pixel,
+            integrator,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn samples_per_pixel(&self) -> u32 {
+        self.samples_per_pixel
+    }
+
+    pub fn integrator(&self) -> &Integrator {
+        &self.integrator
+    }
+
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        self.integrator = integrator;
+    }
+}
+
+/// Accumulators of one pixel: the radiance, then each `Aov` in order, with
+/// the number of samples that wrote each and the object ID every sample saw.
+#[derive(Default)]
+struct Pixel {
+    sums: [Vec3; 1 + Aov::ALL.len()],
+    counts: [u32; 1 + Aov::ALL.len()],
+    ids: Vec<f64>,
+}
+
+impl Pixel {
+    fn add(&mut self, layer: usize, value: Vec3) {
+        self.sums[layer] += value;
+        self.counts[layer] += 1;
+    }
+
+    /// Value of `kind` over `spp` samples. Radiance splits average over all
+    /// samples, other variables over the samples that hit something, and the
+    /// object ID is the one seen by most samples, so that it names an object.
+    fn aov(&self, kind: Aov, spp: u32) -> Vec3 {
+        let layer = 1 + kind as usize;
+        match kind {
+            Aov::Diffuse | Aov::Specular | Aov::Emission => self.sums[layer] / f64::from(spp),
+            Aov::ObjectId => {
+                // Runs of equal IDs in ascending order, so ties go to the smaller ID.
+                let mut ids = self.ids.clone();
+                ids.sort_by(f64::total_cmp);
+                let mut best = (0.0, 0);
+                for run in ids.chunk_by(|a, b| a == b) {
+                    if run.len() > best.1 {
+                        best = (run[0], run.len());
+                    }
+                }
+                Vec3::new(best.0, best.0, best.0)
+            }
+            _ if self.counts[layer] > 0 => self.sums[layer] / f64::from(self.counts[layer]),
+            _ => Vec3::default(),
+        }
+    }
+}
+
+/// Renders linear radiance, one row of pixels per task.
+///
+/// Splatted contributions are summed over the whole image and divided by the
+/// number of samples per pixel, like the samples taken at each pixel.
+pub fn render(scene: &Scene, camera: &Camera, settings: &RenderSettings) -> HdrImage {
+    render_layers(scene, camera, settings)
+        .into_layer(Framebuffer::BEAUTY)
+        .expect("beauty layer")
+}
+
+/// Renders the radiance into the beauty layer and, when the integrator writes
+/// them, every `Aov` into a layer of the same name.
+pub fn render_layers(scene: &Scene, camera: &Camera, settings: &RenderSettings) -> Framebuffer {
+    let (width, height) = (settings.width, settings.height);
+    let mut framebuffer = Framebuffer::new(width, height);
+    let integrator = match &settings.integrator {
+        Integrator::Path(tracer) => PerSample::Path(tracer),
+        Integrator::Bidirectional(tracer) => PerSample::Bidirectional(tracer),
+        Integrator::PhotonMap(mapper) => PerSample::PhotonMap(mapper),
+        Integrator::Debug(mode) => PerSample::Debug(mode),
+        Integrator::ProgressivePhotonMap(mapper) => {
+            *framebuffer.add_layer(Framebuffer::BEAUTY) = mapper.render(scene, camera, settings);
+            return framebuffer;
+        }
+        Integrator::Metropolis(mlt) => {
+            *framebuffer.add_layer(Framebuffer::BEAUTY) = mlt.render(scene, camera, settings);
+            return framebuffer;
+        }
+        Integrator::Whitted(tracer) => {
+            *framebuffer.add_layer(Framebuffer::BEAUTY) = tracer.render(scene, camera, settings);
+            return framebuffer;
+        }
+    };
+
+    let spp = settings.samples_per_pixel.max(1);
+    // Only integrators that splat need a film for the whole image per task.
+    let pixel_count = if integrator.splats() {
+        (width * height) as usize
+    } else {
+        0
+    };
+    let film = || (Vec::new(), vec![Vec3::default(); pixel_count]);
+    let (rows, splats) = (0..height)
+        .into_par_iter()
+        .fold(film, |(mut rows, mut splats), y| {
+            let mut rng = rand::thread_rng();
+            let mut splat = |(u, v): (f64, f64), value: Vec3| {
+                let x = ((u * f64::from(width)) as u32).min(width - 1);
+                let y = ((v * f64::from(height)) as u32).min(height - 1);
+                splats[(y * width + x) as usize] += value;
+            };
+            let row: Vec<Pixel> = (0..width)
+                .map(|x| {
+                    let mut acc = Pixel::default();
+                    for _ in 0..spp {
+                        let u = (f64::from(x) + rng.gen::<f64>()) / f64::from(width);
+                        let v = (f64::from(y) + rng.gen::<f64>()) / f64::from(height);
+                        let mut id = 0.0;
+                        let mut aov = |kind: Aov, value: Vec3| match kind {
+                            Aov::ObjectId => id = value.x(),
+                            Aov::Position => {
+                                let depth = (value - camera.origin()).dot(camera.forward());
+                                acc.add(1 + Aov::Depth as usize, Vec3::new(depth, depth, depth));
+                                acc.add(1 + kind as usize, value);
+                            }
+                            _ => acc.add(1 + kind as usize, value),
+                        };
+                        let radiance = integrator.sample(
+                            (u, v),
+                            camera,
+                            scene,
+                            &mut rng,
+                            &mut splat,
+                            &mut aov,
+                        );
+                        acc.add(0, radiance);
+                        if integrator.writes_aovs() {
+                            acc.ids.push(id);
+                        }
+                    }
+                    acc
+                })
+                .collect();
+            rows.push((y, row));
+            (rows, splats)
+        })
+        .reduce(
+            film,
+            |(mut rows, mut splats), (other_rows, other_splats)| {
+                rows.extend(other_rows);
+                for (splat, other) in splats.iter_mut().zip(other_splats) {
+                    *splat += other;
+                }
+                (rows, splats)
+            },
+        );
+
+    let beauty = framebuffer.add_layer(Framebuffer::BEAUTY);
+    for (y, row) in &rows {
+        for (x, pixel) in (0..width).zip(row) {
+            let splat = splats
+                .get((y * width + x) as usize)
+                .copied()
+                .unwrap_or_default();
+            beauty[(x, *y)] = (pixel.sums[0] + splat) / f64::from(spp);
+        }
+    }
+    if integrator.writes_aovs() {
+        for &kind in Aov::ALL.iter() {
+            let layer = framebuffer.add_layer(kind.name());
+            for (y, row) in &rows {
+                for (x, pixel) in (0..width).zip(row) {
+                    layer[(x, *y)] = pixel.aov(kind, spp);
+                }
+            }
+        }
+    }
+    framebuffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConstantEnvironment, HitList, Lambertian, LightSampling, Metal, Sphere};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_integrators_agree_in_furnace() {
+        // A white sphere under a uniform white sky looks uniformly white.
+        let mut world = HitList::new();
+        world.push(Sphere::new(
+            Vec3::new(0.0, 0.0, -2.0),
+            0.5,
+            Arc::new(Lambertian::new(Vec3::new(1.0, 1.0, 1.0))),
+        ));
+        let scene = Scene::with_environment(
+            world,
+            Arc::new(ConstantEnvironment::new(Vec3::new(1.0, 1.0, 1.0))),
+        );
+        let camera = Camera::new(
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::default(),
+        );
+        let mut settings = RenderSettings::new(
+            8,
+            8,
+            64,
+            Integrator::Path(PathTracer::new(20, LightSampling::Mis)),
+        );
+        let integrators = vec![
+            Integrator::Path(PathTracer::new(20, LightSampling::Mis)),
+            Integrator::Bidirectional(BidirectionalPathTracer::new(20)),
+        ];
+        for integrator in integrators {
+            settings.set_integrator(integrator);
+            let image = render(&scene, &camera, &settings);
+            for y in 0..8 {
+                for x in 0..8 {
+                    let pixel = image[(x, y)];
+                    assert!((pixel.x() - 1.0).abs() < 0.1, "{:?}", pixel);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_aov_layers() {
+        // A diffuse sphere ahead, a mirror behind and open sky above.
+        let mut world = HitList::new();
+        world.push(Sphere::new(
+            Vec3::new(0.0, 0.0, -3.0),
+            2.0,
+            Arc::new(Lambertian::new(Vec3::new(0.2, 0.4, 0.6))),
+        ));
+        world.push(Sphere::new(
+            Vec3::new(0.0, 0.0, 3.0),
+            2.0,
+            Arc::new(Metal::new(Vec3::new(0.9, 0.9, 0.9), 0.0)),
+        ));
+        let sky = Vec3::new(0.5, 0.5, 0.5);
+        let scene = Scene::with_environment(world, Arc::new(ConstantEnvironment::new(sky)));
+        let narrow = |llc: Vec3, horizontal: Vec3, vertical: Vec3| {
+            Camera::new(llc, horizontal, vertical, Vec3::default())
+        };
+        let ahead = narrow(
+            Vec3::new(-0.1, -0.1, -1.0),
+            Vec3::new(0.2, 0.0, 0.0),
+            Vec3::new(0.0, 0.2, 0.0),
+        );
+        let behind = narrow(
+            Vec3::new(0.1, -0.1, 1.0),
+            Vec3::new(-0.2, 0.0, 0.0),
+            Vec3::new(0.0, 0.2, 0.0),
+        );
+        let above = narrow(
+            Vec3::new(-0.1, 1.0, -0.1),
+            Vec3::new(0.2, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.2),
+        );
+        let settings = RenderSettings::new(
+            2,
+            2,
+            16,
+            Integrator::Path(PathTracer::new(5, LightSampling::Mis)),
+        );
+        let pixel =
+            |framebuffer: &Framebuffer, name: &str| framebuffer.layer(name).unwrap()[(1, 1)];
+        let layers = |camera: &Camera| {
+            let framebuffer = render_layers(&scene, camera, &settings);
+            let split = [Aov::Diffuse, Aov::Specular, Aov::Emission]
+                .iter()
+                .map(|aov| pixel(&framebuffer, aov.name()))
+                .fold(Vec3::default(), |acc, value| acc + value);
+            let beauty = pixel(&framebuffer, Framebuffer::BEAUTY);
+            assert!((split - beauty).length() < 1e-9, "{:?} {:?}", split, beauty);
+            framebuffer
+        };
+        let close = |a: Vec3, b: Vec3| (a - b).length() < 1e-6;
+
+        let diffuse = layers(&ahead);
+        let at = |aov: Aov| pixel(&diffuse, aov.name());
+        assert!(close(
+            at(Aov::Diffuse),
+            pixel(&diffuse, Framebuffer::BEAUTY)
+        ));
+        assert!(close(at(Aov::Albedo), Vec3::new(0.2, 0.4, 0.6)));
+        assert!(close(at(Aov::ObjectId), Vec3::new(1.0, 1.0, 1.0)));
+        assert!((at(Aov::Depth).x() - 1.0).abs() < 0.02);
+        assert!((at(Aov::Position).z() + 1.0).abs() < 0.02);
+        assert!(at(Aov::Normal).z() > 0.99);
+
+        let mirror = layers(&behind);
+        let at = |aov: Aov| pixel(&mirror, aov.name());
+        assert!(close(
+            at(Aov::Specular),
+            pixel(&mirror, Framebuffer::BEAUTY)
+        ));
+        assert!(close(at(Aov::ObjectId), Vec3::new(2.0, 2.0, 2.0)));
+
+        let background = layers(&above);
+        let at = |aov: Aov| pixel(&background, aov.name());
+        assert!(close(at(Aov::Emission), sky));
+        assert!(close(pixel(&background, Framebuffer::BEAUTY), sky));
+        assert_eq!(at(Aov::ObjectId), Vec3::default());
+
+        // A pixel across the silhouette of the sphere averages the geometry
+        // of the samples that hit it and names a single object.
+        let edge = narrow(
+            Vec3::new(0.8, -0.1, -1.0),
+            Vec3::new(0.2, 0.0, 0.0),
+            Vec3::new(0.0, 0.2, 0.0),
+        );
+        let single = RenderSettings::new(
+            1,
+            1,
+            64,
+            Integrator::Path(PathTracer::new(5, LightSampling::Mis)),
+        );
+        let framebuffer = render_layers(&scene, &edge, &single);
+        let at = |aov: Aov| framebuffer.layer(aov.name()).unwrap()[(0, 0)];
+        assert!(at(Aov::Normal).length() > 0.95, "{:?}", at(Aov::Normal));
+        assert!(at(Aov::Depth).x() > 1.0, "{:?}", at(Aov::Depth));
+        let id = at(Aov::ObjectId);
+        assert!(id == Vec3::default() || id == Vec3::new(1.0, 1.0, 1.0));
+
+        // Other integrators only write the beauty layer.
+        let mut settings = settings;
+        settings.set_integrator(Integrator::Debug(DebugMode::Normal));
+        let framebuffer = render_layers(&scene, &ahead, &settings);
+        let names: Vec<_> = framebuffer.layers().map(|(name, _)| name).collect();
+        assert_eq!(names, vec![Framebuffer::BEAUTY]);
+    }
+
+    #[test]
+    fn test_object_id_majority() {
+        let pixel = Pixel {
+            ids: vec![2.0, 1.0, 3.0, 2.0, 1.0, 0.0],
+            ..Pixel::default()
+        };
+        // Objects 1 and 2 tie, and the smaller ID wins.
+        assert_eq!(pixel.aov(Aov::ObjectId, 6), Vec3::new(1.0, 1.0, 1.0));
+        let pixel = Pixel {
+            ids: vec![4.0, 0.0, 4.0],
+            ..Pixel::default()
+        };
+        assert_eq!(pixel.aov(Aov::ObjectId, 3), Vec3::new(4.0, 4.0, 4.0));
+    }
+}
@@ -0,0 +1,154 @@
+use crate::distribution::Distribution1D;
+use crate::environment::{Environment, EnvironmentLight, GradientEnvironment};
+use crate::light::Light;
+use crate::light_sampler::{LightSampler, LightSelection};
+use crate::material::Scatter;
+use crate::{Hit, Ray, Vec3};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Geometry to render together with the lights found in it.
+pub struct Scene {
+    world: Box<dyn Hit>,
+    lights: Vec<Arc<dyn Light>>,
+    infinite_lights: Vec<usize>,
+    light_selection: LightSelection,
+    light_sampler: Box<dyn LightSampler>,
+    /// Finite lights by emitted power, for starting paths at the lights.
+    emitters: Option<Distribution1D>,
+    environment: Arc<dyn Environment>,
+    /// Index of every material in the world by address, in the order first added.
+    material_ids: HashMap<usize, usize>,
+}
+
+fn material_address(material: &dyn Scatter) -> usize {
+    material as *const dyn Scatter as *const () as usize
+}
+
+impl Scene {
+    /// Registers every emissive object of `world` as a light; the background is
+    /// the default sky gradient.
+    pub fn new<T: Hit + 'static>(world: T) -> Self {
+        Self::with_environment(world, Arc::new(GradientEnvironment::default()))
+    }
+
+    /// Like `new`, with `environment` lighting the scene from infinitely far away.
+    pub fn with_environment<T: Hit + 'static>(
+        mut world: T,
+        environment: Arc<dyn Environment>,
+    ) -> Self {
+        let mut lights = Vec::new();
+        world.register_lights(&mut lights);
+        let mut materials = Vec::new();
+        world.collect_materials(&mut materials);
+        let mut material_ids = HashMap::new();
+        for material in materials {
+            let next = material_ids.len();
+            material_ids
+                .entry(material_address(material))
+                .or_insert(next);
+        }
+        let mut scene = Self {
+            world: Box::new(world),
+            lights,
+            infinite_lights: Vec::new(),
+            light_selection: LightSelection::Bvh,
+            light_sampler: LightSelection::Uniform.build(&[]),
+            emitters: None,
+            environment: environment.clone(),
+            material_ids,
+        };
+        scene.add_light(Arc::new(EnvironmentLight(environment)));
+        scene
+    }
+
+    /// Adds a light that is not part of the geometry, e.g. a point light.
+    pub fn add_light(&mut self, light: Arc<dyn Light>) {
+        if light.is_infinite() {
+            self.infinite_lights.push(self.lights.len());
+        }
+        self.lights.push(light);
+        self.light_sampler = self.light_selection.build(&self.lights);
+        let powers: Vec<_> = self
+            .lights
+            .iter()
+            .map(|light| light.bounds().map_or(0.0, |bounds| bounds.power().max(0.0)))
+            .collect();
+        self.emitters = if powers.iter().any(|&power| power > 0.0) {
+            Some(Distribution1D::new(powers))
+        } else {
+            None
+        };
+    }
+
+    /// Changes how lights are picked for direct lighting; the default is `Bvh`.
+    pub fn set_light_selection(&mut self, selection: LightSelection) {
+        self.light_selection = selection;
+        self.light_sampler = selection.build(&self.lights);
+    }
+
+    pub fn light_sampler(&self) -> &dyn LightSampler {
+        &*self.light_sampler
+    }
+
+    /// Chooses a finite light to emit a path from, proportionally to its power;
+    /// returns its index and the probability of choosing it.
+    pub fn sample_emitter(&self, u: f64) -> Option<(usize, f64)> {
+        let emitters = self.emitters.as_ref()?;
+        let (_, _, light) = emitters.sample_continuous(u);
+        Some((light, emitters.segment_pdf(light)))
+    }
+
+    /// Probability that `sample_emitter` chooses `light`.
+    pub fn emitter_pmf(&self, light: usize) -> f64 {
+        self.emitters
+            .as_ref()
+            .map_or(0.0, |emitters| emitters.segment_pdf(light))
+    }
+
+    /// Whether the segment of length `distance` from `from` along the unit
+    /// `direction` is clear, passing through medium boundaries; `distance`
+    /// may be infinite.
+    pub fn unoccluded(&self, from: Vec3, direction: Vec3, distance: f64, time: f64) -> bool {
+        let mut ray = Ray::new(from, direction, time);
+        let mut remaining = distance;
+        loop {
+            let t_max = if remaining.is_finite() {
+                remaining * (1.0 - 1e-4)
+            } else {
+                f64::MAX
+            };
+            match self.world.hit(&ray, 0.001, t_max) {
+                None => return true,
+                Some(hit) if hit.material().is_interface() => {
+                    remaining -= hit.t();
+                    ray = Ray::new(hit.point(), direction, time);
+                }
+                Some(_) => return false,
+            }
+        }
+    }
+
+    pub fn world(&self) -> &dyn Hit {
+        &*self.world
+    }
+
+    pub fn environment(&self) -> &dyn Environment {
+        &*self.environment
+    }
+
+    pub fn lights(&self) -> &[Arc<dyn Light>] {
+        &self.lights
+    }
+
+    /// Number of `material` among the materials of the world, counted in the
+    /// order the objects using them were added; `None` for other materials.
+    pub fn material_id(&self, material: &dyn Scatter) -> Option<usize> {
+        self.material_ids.get(&material_address(material)).copied()
+    }
+
+    /// Indices into `lights` of the lights seen by rays leaving the scene.
+    pub fn infinite_lights(&self) -> &[usize] {
+        &self.infinite_lights
+    }
+}
@@ -0,0 +1,302 @@
+use crate::environment::{ConstantEnvironment, Environment, EnvironmentMap, GradientEnvironment};
+use crate::light::{DirectionalLight, Light, PointLight, SpotLight};
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal, Scatter};
+use crate::{
+    read_hdr, read_pfm, read_voxel_grid, Aabb, ConstantMedium, GridMedium, HitList,
+    HomogeneousMedium, Ior, Microfacet, PreethamSky, Rectangle, Scene, SolidColor, Sphere,
+    SunLight, Vec3,
+};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read};
+use std::sync::Arc;
+
+/// Reads a line-based scene description.
+///
+/// Each non-empty line holds one statement; `#` starts a comment:
+///
+/// ```text
+/// material <name> lambertian <r g b>
+/// material <name> metal <r g b> <fuzz>
+/// material <name> microfacet <r g b> <metallic> <roughness>
+/// material <name> emissive <r g b>
+/// material <name> dielectric <ior>
+/// material <name> dispersive bk7|diamond|cauchy <a> <b>
+/// sphere <x y z> <radius> <material>
+/// medium_sphere <x y z> <radius> <absorption r g b> <scattering r g b> <g> [material]
+/// voxel_grid <path> <min x y z> <max x y z> <absorption r g b> <scattering r g b> <g>
+/// rectangle <corner x y z> <edge u x y z> <edge v x y z> <material>
+/// point_light <x y z> <r g b>
+/// spot_light <x y z> <dx dy dz> <r g b> <cone angle> <falloff start>
+/// directional_light <dx dy dz> <r g b>
+/// environment gradient <bottom r g b> <top r g b>
+/// environment constant <r g b>
+/// environment map <path to .hdr or .pfm> <rotation> <intensity>
+/// environment sky <sun elevation> <sun azimuth> <turbidity>
+/// ```
+///
+/// Materials must be declared before they are used. Angles are in degrees.
+/// A `medium_sphere` without a material has an invisible boundary; with one,
+/// such as a dielectric, the medium fills the inside of that surface.
+/// Without an `environment` statement the default sky gradient is used; the
+/// physical `sky` also adds the matching sun light.
+pub fn read_scene<R: Read>(input: &mut R) -> Result<Scene, Error> {
+    let mut materials: HashMap<String, Arc<dyn Scatter>> = HashMap::new();
+    let mut world = HitList::new();
+    let mut lights: Vec<Arc<dyn Light>> = Vec::new();
+    let mut environment: Arc<dyn Environment> = Arc::new(GradientEnvironment::default());
+    for (number, line) in BufReader::new(input).lines().enumerate() {
+        let line = line?;
+        let line = line.split('#').next().unwrap_or("");
+        let mut statement = Statement {
+            tokens: line.split_whitespace(),
+            line: number + 1,
+        };
+        let keyword = match statement.tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        match keyword {
+            "material" => {
+                let name = statement.word()?.to_string();
+                let material: Arc<dyn Scatter> = match statement.word()? {
+                    "lambertian" => Arc::new(Lambertian::new(statement.vec3()?)),
+                    "metal" => Arc::new(Metal::new(statement.vec3()?, statement.number()?)),
+                    "microfacet" => Arc::new(Microfacet::new(
+                        Arc::new(SolidColor::new(statement.vec3()?)),
+                        statement.number()?,
+                        statement.number()?,
+                    )),
+                    "emissive" => Arc::new(DiffuseLight::new(statement.vec3()?)),
+                    "dielectric" => Arc::new(Dielectric::new(statement.number()?)),
+                    "dispersive" => {
+                        let ior = match statement.word()? {
+                            "bk7" => Ior::bk7(),
+                            "diamond" => Ior::diamond(),
+                            "cauchy" => Ior::Cauchy {
+                                a: statement.number()?,
+                                b: statement.number()?,
+                            },
+                            other => {
+                                return Err(statement.error(&format!("unknown glass '{}'", other)))
+                            }
+                        };
+                        Arc::new(Dielectric::with_dispersion(ior))
+                    }
+                    other => return Err(statement.error(&format!("unknown material '{}'", other))),
+                };
+                materials.insert(name, material);
+            }
+            "sphere" => {
+                let center = statement.vec3()?;
+                let radius = statement.number()?;
+                let material = statement.material(&materials)?;
+                world.push(Sphere::new(center, radius, material));
+            }
+            "medium_sphere" => {
+                let center = statement.vec3()?;
+                let radius = statement.number()?;
+                let medium = Arc::new(HomogeneousMedium::new(
+                    statement.vec3()?,
+                    statement.vec3()?,
+                    statement.number()?,
+                ));
+                if statement.tokens.clone().next().is_some() {
+                    let material = statement.material(&materials)?;
+                    let boundary = Sphere::new(center, radius, material);
+                    world.push(ConstantMedium::with_surface(boundary, medium));
+                } else {
+                    let boundary =
+                        Sphere::new(center, radius, Arc::new(Lambertian::new(Vec3::default())));
+                    world.push(ConstantMedium::new(boundary, medium));
+                }
+            }
+            "voxel_grid" => {
+                let grid = read_voxel_grid(&mut File::open(statement.word()?)?)?;
+                let bounds = Aabb::new(statement.vec3()?, statement.vec3()?);
+                let medium = GridMedium::new(
+                    grid,
+                    bounds,
+                    statement.vec3()?,
+                    statement.vec3()?,
+                    statement.number()?,
+                );
+                world.push(medium.into_volume());
+            }
+            "rectangle" => {
+                let corner = statement.vec3()?;
+                let edge_u = statement.vec3()?;
+                let edge_v = statement.vec3()?;
+                let material = statement.material(&materials)?;
+                if edge_u.dot(edge_v).abs() > 1e-9 * edge_u.length() * edge_v.length() {
+                    return Err(statement.error("rectangle edges must be perpendicular"));
+                }
+                world.push(Rectangle::new(corner, edge_u, edge_v, material));
+            }
+            "point_light" => {
+                lights.push(Arc::new(PointLight::new(
+                    statement.vec3()?,
+                    statement.vec3()?,
+                )));
+            }
+            "spot_light" => {
+                lights.push(Arc::new(SpotLight::new(
+                    statement.vec3()?,
+                    statement.vec3()?,
+                    statement.vec3()?,
+                    statement.number()?,
+                    statement.number()?,
+                )));
+            }
+            "directional_light" => {
+                lights.push(Arc::new(DirectionalLight::new(
+                    statement.vec3()?,
+                    statement.vec3()?,
+                )));
+            }
+            "environment" => {
+                environment = match statement.word()? {
+                    "gradient" => Arc::new(GradientEnvironment::new(
+                        statement.vec3()?,
+                        statement.vec3()?,
+                    )),
+                    "constant" => Arc::new(ConstantEnvironment::new(statement.vec3()?)),
+                    "map" => {
+                        let path = statement.word()?;
+                        let mut file = File::open(path)?;
+                        let image = if path.ends_with(".pfm") {
+                            read_pfm(&mut file)?
+                        } else {
+                            read_hdr(&mut file)?
+                        };
+                        Arc::new(EnvironmentMap::new(
+                            &image,
+                            statement.number()?,
+                            statement.number()?,
+                        )?)
+                    }
+                    "sky" => {
+                        let elevation = statement.number()?;
+                        let azimuth = statement.number()?;
+                        let turbidity = statement.number()?;
+                        lights.push(Arc::new(SunLight::new(elevation, azimuth, turbidity)));
+                        Arc::new(PreethamSky::new(elevation, azimuth, turbidity))
+                    }
+                    other => {
+                        return Err(statement.error(&format!("unknown environment '{}'", other)))
+                    }
+                };
+            }
+            other => return Err(statement.error(&format!("unknown statement '{}'", other))),
+        }
+        statement.finish()?;
+    }
+
+    let mut scene = Scene::with_environment(world.into_bvh(0.0, 1.0), environment);
+    for light in lights {
+        scene.add_light(light);
+    }
+    Ok(scene)
+}
+
+struct Statement<'a> {
+    tokens: std::str::SplitWhitespace<'a>,
+    line: usize,
+}
+
+impl<'a> Statement<'a> {
+    fn word(&mut self) -> Result<&'a str, Error> {
+        self.tokens
+            .next()
+            .ok_or_else(|| self.error("unexpected end of line"))
+    }
+
+    fn number(&mut self) -> Result<f64, Error> {
+        let word = self.word()?;
+        word.parse()
+            .map_err(|_| self.error(&format!("invalid number '{}'", word)))
+    }
+
+    fn material(
+        &mut self,
+        materials: &HashMap<String, Arc<dyn Scatter>>,
+    ) -> Result<Arc<dyn Scatter>, Error> {
+        let name = self.word()?;
+        materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| self.error(&format!("undefined material '{}'", name)))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, Error> {
+        Ok(Vec3::new(self.number()?, self.number()?, self.number()?))
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        match self.tokens.next() {
+            Some(word) => Err(self.error(&format!("unexpected '{}'", word))),
+            None => Ok(()),
+        }
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("line {}: {}", self.line, msg),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Ray;
+
+    #[test]
+    fn test_read_scene() {
+        let text = "# two lights and a floor\n\
+                    material floor lambertian 0.5 0.5 0.5\n\
+                    sphere 0 -100 0 100 floor\n\
+                    material lamp emissive 4 4 4\n\
+                    rectangle -1 3 -1  2 0 0  0 0 2 lamp\n\
+                    point_light 0 5 0 10 10 10\n\
+                    spot_light 0 5 0  0 -1 0  10 10 10  30 20\n\
+                    directional_light 0 -1 -1 1 1 1\n\
+                    material glass dielectric 1.5\n\
+                    material prism dispersive cauchy 1.5 0.004\n\
+                    medium_sphere 0 1 -5 0.5 0.1 0.1 0.1 1 1 1 0.3 glass\n\
+                    medium_sphere 3 1 -5 0.5 0 0 0 0.5 0.5 0.5 0\n";
+        let scene = read_scene(&mut text.as_bytes()).unwrap();
+        // The rectangle, three analytic lights and the sky.
+        assert_eq!(scene.lights().len(), 5);
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world().hit(&ray, 0.001, f64::MAX).unwrap();
+        assert!(hit.medium().is_some() && !hit.material().is_interface());
+        let ray = Ray::new(Vec3::new(3.0, 1.0, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let hit = scene.world().hit(&ray, 0.001, f64::MAX).unwrap();
+        assert!(hit.medium().is_some() && hit.material().is_interface());
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let hit = scene.world().hit(&ray, 0.001, f64::MAX).unwrap();
+        assert!((hit.t() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_read_scene_errors() {
+        let undefined = "sphere 0 0 0 1 missing\n";
+        let error = read_scene(&mut undefined.as_bytes()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("line 1"));
+        assert!(read_scene(&mut "point_light 0 0 0 1 1\n".as_bytes()).is_err());
+        let skewed = "material m lambertian 1 1 1\nrectangle 0 0 0 1 0 0 1 1 0 m\n";
+        assert!(read_scene(&mut skewed.as_bytes()).is_err());
+        assert!(read_scene(&mut "point_light 0 0 0 1 1 1 1\n".as_bytes()).is_err());
+        assert!(read_scene(&mut "material m dispersive quartz\n".as_bytes()).is_err());
+        assert!(read_scene(&mut "environment map missing.hdr 0 1\n".as_bytes()).is_err());
+        let constant = read_scene(&mut "environment constant 1 2 3\n".as_bytes()).unwrap();
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        assert_eq!(
+            constant.environment().radiance(up),
+            Vec3::new(1.0, 2.0, 3.0)
+        );
+    }
+}
@@ -0,0 +1,431 @@
+use crate::{Aabb, Vec3};
+
+/// Signed distance field: negative inside the shape, positive outside.
+///
+/// `distance` must never overestimate the distance to the surface, otherwise
+/// sphere tracing may step through it.
+pub trait Sdf: Send + Sync {
+    fn distance(&self, point: Vec3) -> f64;
+
+    /// Bounds of the surface, or `None` if it is infinite.
+    fn bounding_box(&self) -> Option<Aabb>;
+}
+
+/// Polynomial smooth minimum; `k` is the width of the blending region.
+pub fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k * 0.25
+}
+
+fn abs(v: Vec3) -> Vec3 {
+    Vec3::new(v.x().abs(), v.y().abs(), v.z().abs())
+}
+
+fn cube(half_extent: f64) -> Aabb {
+    let extent = Vec3::new(half_extent, half_extent, half_extent);
+    Aabb::new(-extent, extent)
+}
+
+fn expand(aabb: Aabb, margin: f64) -> Aabb {
+    let margin = Vec3::new(margin, margin, margin);
+    Aabb::new(aabb.min() - margin, aabb.max() + margin)
+}
+
+pub struct SdfSphere {
+    radius: f64,
+}
+
+impl SdfSphere {
+    pub fn new(radius: f64) -> Self {
+        Self { radius }
+    }
+}
+
+impl Sdf for SdfSphere {
+    fn distance(&self, point: Vec3) -> f64 {
+        point.length() - self.radius
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(cube(self.radius))
+    }
+}
+
+/// Box with `half_extents` whose edges are rounded off by `radius`.
+pub struct RoundedBox {
+    half_extents: Vec3,
+    radius: f64,
+}
+
+impl RoundedBox {
+    pub fn new(half_extents: Vec3, radius: f64) -> Self {
+        Self {
+            half_extents,
+            radius,
+        }
+    }
+}
+
+impl Sdf for RoundedBox {
+    fn distance(&self, point: Vec3) -> f64 {
+        let inner = self.half_extents - Vec3::new(self.radius, self.radius, self.radius);
+        let q = abs(point) - inner;
+        let outside = q.max(Vec3::default()).length();
+        let inside = q.x().max(q.y()).max(q.z()).min(0.0);
+        outside + inside - self.radius
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(-self.half_extents, self.half_extents))
+    }
+}
+
+/// Torus lying in the XZ plane.
+pub struct SdfTorus {
+    major_radius: f64,
+    minor_radius: f64,
+}
+
+impl SdfTorus {
+    pub fn new(major_radius: f64, minor_radius: f64) -> Self {
+        Self {
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+
+impl Sdf for SdfTorus {
+    fn distance(&self, point: Vec3) -> f64 {
+        let ring = (point.x() * point.x() + point.z() * point.z()).sqrt() - self.major_radius;
+        (ring * ring + point.y() * point.y()).sqrt() - self.minor_radius
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        Some(Aabb::new(
+            Vec3::new(-outer, -self.minor_radius, -outer),
+            Vec3::new(outer, self.minor_radius, outer),
+        ))
+    }
+}
+
+/// Mandelbulb fractal of the given `power`, fitting into the unit-ish cube.
+pub struct Mandelbulb {
+    power: f64,
+    iterations: u32,
+}
+
+impl Mandelbulb {
+    /// `iterations` must be at least one.
+    pub fn new(power: f64, iterations: u32) -> Self {
+        assert!(iterations >= 1, "Mandelbulb needs at least one iteration");
+        Self { power, iterations }
+    }
+}
+
+impl Sdf for Mandelbulb {
+    fn distance(&self, point: Vec3) -> f64 {
+        const BAILOUT: f64 = 2.0;
+        let mut z = point;
+        let mut dr = 1.0;
+        let mut r = 0.0;
+        for _ in 0..self.iterations {
+            r = z.length();
+            if r > BAILOUT {
+                break;
+            }
+            if r == 0.0 {
+                // Zero stays zero under the power, though its angles are undefined.
+                dr = 1.0;
+                z = point;
+                continue;
+            }
+            let theta = (z.z() / r).acos() * self.power;
+            let phi = z.y().atan2(z.x()) * self.power;
+            dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+            let zr = r.powf(self.power);
+            z =
+                zr * Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                ) + point;
+        }
+        if r == 0.0 {
+            return 0.0;
+        }
+        0.5 * r.ln() * r / dr
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(cube(1.2))
+    }
+}
+
+pub struct SdfTranslate {
+    sdf: Box<dyn Sdf>,
+    offset: Vec3,
+}
+
+impl SdfTranslate {
+    pub fn new<T: Sdf + 'static>(sdf: T, offset: Vec3) -> Self {
+        Self {
+            sdf: Box::new(sdf),
+            offset,
+        }
+    }
+}
+
+impl Sdf for SdfTranslate {
+    fn distance(&self, point: Vec3) -> f64 {
+        self.sdf.distance(point - self.offset)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let aabb = self.sdf.bounding_box()?;
+        Some(Aabb::new(
+            aabb.min() + self.offset,
+            aabb.max() + self.offset,
+        ))
+    }
+}
+
+pub struct SdfUnion {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+}
+
+impl SdfUnion {
+    pub fn new<A: Sdf + 'static, B: Sdf + 'static>(a: A, b: B) -> Self {
+        Self {
+            a: Box::new(a),
+            b: Box::new(b),
+        }
+    }
+}
+
+impl Sdf for SdfUnion {
+    fn distance(&self, point: Vec3) -> f64 {
+        self.a.distance(point).min(self.b.distance(point))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.a.bounding_box()?.surrounding(self.b.bounding_box()?))
+    }
+}
+
+pub struct SdfIntersection {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+}
+
+impl SdfIntersection {
+    pub fn new<A: Sdf + 'static, B: Sdf + 'static>(a: A, b: B) -> Self {
+        Self {
+            a: Box::new(a),
+            b: Box::new(b),
+        }
+    }
+}
+
+impl Sdf for SdfIntersection {
+    fn distance(&self, point: Vec3) -> f64 {
+        self.a.distance(point).max(self.b.distance(point))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        match (self.a.bounding_box(), self.b.bounding_box()) {
+            (Some(a), Some(b)) => Some(Aabb::new(a.min().max(b.min()), a.max().min(b.max()))),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// Carves `b` out of `a`.
+pub struct SdfDifference {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+}
+
+impl SdfDifference {
+    pub fn new<A: Sdf + 'static, B: Sdf + 'static>(a: A, b: B) -> Self {
+        Self {
+            a: Box::new(a),
+            b: Box::new(b),
+        }
+    }
+}
+
+impl Sdf for SdfDifference {
+    fn distance(&self, point: Vec3) -> f64 {
+        self.a.distance(point).max(-self.b.distance(point))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.a.bounding_box()
+    }
+}
+
+/// Union of two shapes blended together over a region of width `k`.
+pub struct SmoothUnion {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+    k: f64,
+}
+
+impl SmoothUnion {
+    pub fn new<A: Sdf + 'static, B: Sdf + 'static>(a: A, b: B, k: f64) -> Self {
+        Self {
+            a: Box::new(a),
+            b: Box::new(b),
+            k,
+        }
+    }
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, point: Vec3) -> f64 {
+        smooth_min(self.a.distance(point), self.b.distance(point), self.k)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let aabb = self.a.bounding_box()?.surrounding(self.b.bounding_box()?);
+        Some(expand(aabb, 0.25 * self.k))
+    }
+}
+
+/// Infinite repetition of a shape with the given period along each axis;
+/// a zero component disables repetition along that axis.
+///
+/// The repeated shape must fit into a single cell.
+pub struct Repeat {
+    sdf: Box<dyn Sdf>,
+    period: Vec3,
+}
+
+impl Repeat {
+    pub fn new<T: Sdf + 'static>(sdf: T, period: Vec3) -> Self {
+        Self {
+            sdf: Box::new(sdf),
+            period,
+        }
+    }
+}
+
+impl Sdf for Repeat {
+    fn distance(&self, point: Vec3) -> f64 {
+        fn wrap(x: f64, period: f64) -> f64 {
+            if period > 0.0 {
+                x - period * (x / period).round()
+            } else {
+                x
+            }
+        }
+        let local = Vec3::new(
+            wrap(point.x(), self.period.x()),
+            wrap(point.y(), self.period.y()),
+            wrap(point.z(), self.period.z()),
+        );
+        self.sdf.distance(local)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+/// Twists a shape around the Y axis by `rate` radians per unit of height.
+pub struct Twist {
+    sdf: Box<dyn Sdf>,
+    rate: f64,
+}
+
+impl Twist {
+    pub fn new<T: Sdf + 'static>(sdf: T, rate: f64) -> Self {
+        Self {
+            sdf: Box::new(sdf),
+            rate,
+        }
+    }
+}
+
+impl Sdf for Twist {
+    fn distance(&self, point: Vec3) -> f64 {
+        let angle = self.rate * point.y();
+        let (sin, cos) = angle.sin_cos();
+        let local = Vec3::new(
+            cos * point.x() - sin * point.z(),
+            point.y(),
+            sin * point.x() + cos * point.z(),
+        );
+        // Twisting stretches space, so the distance is scaled by the local Lipschitz bound.
+        let radius = (point.x() * point.x() + point.z() * point.z()).sqrt();
+        let lipschitz = (1.0 + (self.rate * radius).powi(2)).sqrt();
+        self.sdf.distance(local) / lipschitz
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let aabb = self.sdf.bounding_box()?;
+        let radius = aabb
+            .corners()
+            .iter()
+            .map(|c| (c.x() * c.x() + c.z() * c.z()).sqrt())
+            .fold(0.0, f64::max);
+        Some(Aabb::new(
+            Vec3::new(-radius, aabb.min().y(), -radius),
+            Vec3::new(radius, aabb.max().y(), radius),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sphere_distance() {
+        let sphere = SdfSphere::new(1.0);
+        assert_eq!(sphere.distance(Vec3::new(3.0, 0.0, 0.0)), 2.0);
+        assert_eq!(sphere.distance(Vec3::default()), -1.0);
+    }
+
+    #[test]
+    fn test_rounded_box_distance() {
+        let rounded_box = RoundedBox::new(Vec3::new(1.0, 2.0, 3.0), 0.5);
+        assert!((rounded_box.distance(Vec3::new(2.0, 0.0, 0.0)) - 1.0).abs() < 1e-12);
+        assert!((rounded_box.distance(Vec3::new(0.0, 0.0, -4.0)) - 1.0).abs() < 1e-12);
+        assert!(rounded_box.distance(Vec3::default()) < 0.0);
+    }
+
+    #[test]
+    fn test_smooth_min() {
+        assert_eq!(smooth_min(1.0, 3.0, 0.5), 1.0);
+        assert_eq!(smooth_min(1.0, 1.0, 0.0), 1.0);
+        assert!(smooth_min(1.0, 1.0, 0.5) < 1.0);
+    }
+
+    #[test]
+    fn test_repeat() {
+        let repeated = Repeat::new(SdfSphere::new(0.5), Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(repeated.distance(Vec3::new(4.0, 0.0, 0.0)), -0.5);
+        assert_eq!(repeated.distance(Vec3::new(4.0, 2.0, 0.0)), 1.5);
+    }
+
+    #[test]
+    fn test_mandelbulb_at_origin() {
+        let bulb = Mandelbulb::new(8.0, 10);
+        assert_eq!(bulb.distance(Vec3::default()), 0.0);
+        assert!(bulb.distance(Vec3::new(0.0, 0.0, 1e-3)) <= 0.0);
+        assert!(bulb.distance(Vec3::new(0.0, 0.0, 3.0)) > 0.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one iteration")]
+    fn test_mandelbulb_needs_iterations() {
+        Mandelbulb::new(8.0, 0);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::hit::HitRecord;
+use crate::material::Scatter;
+use crate::sdf::Sdf;
+use crate::{Aabb, Hit, Ray, Vec3};
+use std::sync::Arc;
+
+const DEFAULT_EPSILON: f64 = 1e-4;
+const DEFAULT_MAX_STEPS: u32 = 256;
+
+/// Surface of a signed distance field, intersected by sphere tracing.
+pub struct SdfObject {
+    sdf: Box<dyn Sdf>,
+    material: Arc<dyn Scatter>,
+    epsilon: f64,
+    max_steps: u32,
+}
+
+impl SdfObject {
+    pub fn new<T: Sdf + 'static>(sdf: T, material: Arc<dyn Scatter>) -> Self {
+        Self::with_precision(sdf, material, DEFAULT_EPSILON, DEFAULT_MAX_STEPS)
+    }
+
+    /// `epsilon` is the distance at which the surface counts as hit,
+    /// `max_steps` bounds the number of marching steps per ray.
+    pub fn with_precision<T: Sdf + 'static>(
+        sdf: T,
+        material: Arc<dyn Scatter>,
+        epsilon: f64,
+        max_steps: u32,
+    ) -> Self {
+        Self {
+            sdf: Box::new(sdf),
+            material,
+            epsilon,
+            max_steps,
+        }
+    }
+
+    fn normal(&self, point: Vec3) -> Vec3 {
+        let h = self.epsilon;
+        let dx = Vec3::new(h, 0.0, 0.0);
+        let dy = Vec3::new(0.0, h, 0.0);
+        let dz = Vec3::new(0.0, 0.0, h);
+        Vec3::new(
+            self.sdf.distance(point + dx) - self.sdf.distance(point - dx),
+            self.sdf.distance(point + dy) - self.sdf.distance(point - dy),
+            self.sdf.distance(point + dz) - self.sdf.distance(point - dz),
+        )
+        .normalize()
+    }
+}
+
+impl Hit for SdfObject {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t_start, t_end) = match self.sdf.bounding_box() {
+            Some(aabb) => aabb.clip(ray, t_min, t_max)?,
+            None => (t_min, t_max),
+        };
+        let speed = ray.direction().length();
+        let mut t = t_start;
+        // Rays leaving the surface start within epsilon of it; they must get
+        // away from it before a hit is accepted.
+        let mut left_surface = t_start > t_min;
+        for _ in 0..self.max_steps {
+            if t > t_end {
+                return None;
+            }
+            let point = ray.point_at_parameter(t);
+            let distance = self.sdf.distance(point).abs();
+            if distance < self.epsilon {
+                if left_surface {
+                    let normal = self.normal(point);
+                    return Some(HitRecord::new(t, point, normal, &*self.material));
+                }
+            } else {
+                left_surface = true;
+            }
+            t += distance.max(self.epsilon) / speed;
+        }
+        None
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        self.sdf.bounding_box()
+    }
+
+    fn collect_materials<'a>(&'a self, materials: &mut Vec<&'a dyn Scatter>) {
+        materials.push(&*self.material);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdf::SdfSphere;
+    use crate::Lambertian;
+
+    #[test]
+    fn test_hit_sphere() {
+        let object = SdfObject::new(
+            SdfSphere::new(1.0),
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0), 0.0);
+        let hit = object.hit(&ray, 0.001, f64::MAX).unwrap();
+        assert!((hit.t() - 2.0).abs() < 1e-3);
+        assert!((hit.normal() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-3);
+
+        let miss = Ray::new(Vec3::new(2.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(object.hit(&miss, 0.001, f64::MAX).is_none());
+    }
+}
@@ -0,0 +1,273 @@
+use crate::environment::Environment;
+use crate::light::{Light, LightSample};
+use crate::material::uniform_sphere;
+use crate::spectrum::xyz_to_rgb;
+use crate::Vec3;
+use std::f64::consts::PI;
+
+/// Radiance per kcd/m² of luminance, so a clear noon zenith is around one half.
+const LUMINANCE_SCALE: f64 = 0.05;
+/// Luminance of the sun disk outside the atmosphere in kcd/m².
+const SUN_LUMINANCE: f64 = 1.6e6;
+/// Angular radius of the sun disk in radians.
+const SUN_RADIUS: f64 = 0.00465;
+
+/// Unit vector towards the sun; `azimuth` is measured around +y from +z towards +x.
+pub fn sun_direction(elevation: f64, azimuth: f64) -> Vec3 {
+    let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+    Vec3::new(
+        elevation.cos() * azimuth.sin(),
+        elevation.sin(),
+        elevation.cos() * azimuth.cos(),
+    )
+}
+
+/// Analytic daylight sky of Preetham, Shirley and Smits (1999), without the sun disk.
+///
+/// Directions below the horizon are black, as if the ground covered them,
+/// and sampling only picks directions above it. Pair it with a `SunLight`
+/// made from the same parameters.
+pub struct PreethamSky {
+    sun: Vec3,
+    theta_sun: f64,
+    zenith: [f64; 3],
+    perez: [[f64; 5]; 3],
+}
+
+impl PreethamSky {
+    /// Angles are in degrees; `turbidity` ranges from about 2 (clear) to 10 (hazy).
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64) -> Self {
+        let t = turbidity;
+        let sun = sun_direction(elevation.max(0.0), azimuth);
+        let theta = sun.y().acos();
+        let (theta2, theta3) = (theta * theta, theta * theta * theta);
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let x = t * t * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta)
+            + t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta + 0.00394)
+            + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta + 0.25886);
+        let y = t * t * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta)
+            + t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta + 0.00516)
+            + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta + 0.26688);
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+        Self {
+            sun,
+            theta_sun: theta,
+            zenith: [luminance, x, y],
+            perez,
+        }
+    }
+}
+
+fn perez([a, b, c, d, e]: [f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+impl Environment for PreethamSky {
+    fn radiance(&self, direction: Vec3) -> Vec3 {
+        if direction.y() < 0.0 {
+            return Vec3::default();
+        }
+        let cos_theta = direction.y().max(0.01);
+        let gamma = direction.dot(self.sun).clamp(-1.0, 1.0).acos();
+        let mut yxy = [0.0; 3];
+        for (i, value) in yxy.iter_mut().enumerate() {
+            *value = self.zenith[i] * perez(self.perez[i], cos_theta, gamma)
+                / perez(self.perez[i], 1.0, self.theta_sun);
+        }
+        let [luminance, x, y] = yxy;
+        let xyz = Vec3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        xyz_to_rgb(xyz).max(Vec3::default()) * LUMINANCE_SCALE
+    }
+
+    fn sample(&self, (u1, u2): (f64, f64)) -> Option<LightSample> {
+        // The upper half of a uniform sphere sample, turned so that it is +y.
+        let w = uniform_sphere((0.5 * u1, u2));
+        let wi = Vec3::new(w.x(), w.z(), w.y());
+        Some(LightSample::new(
+            wi,
+            self.radiance(wi),
+            f64::INFINITY,
+            1.0 / (2.0 * PI),
+            false,
+        ))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        if direction.y() < 0.0 {
+            0.0
+        } else {
+            1.0 / (2.0 * PI)
+        }
+    }
+}
+
+/// Sun disk seen through the atmosphere, matching `PreethamSky`.
+///
+/// The disk is small enough that it is only found by light sampling in
+/// practice, but escaping rays that hit it still see its radiance.
+pub struct SunLight {
+    direction: Vec3,
+    radiance: Vec3,
+    cos_radius: f64,
+}
+
+impl SunLight {
+    /// Angles are in degrees; the sun has no radiance below the horizon.
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64) -> Self {
+        let direction = sun_direction(elevation, azimuth);
+        let radiance = if elevation > 0.0 {
+            sun_transmittance(direction.y().acos(), turbidity) * (SUN_LUMINANCE * LUMINANCE_SCALE)
+        } else {
+            Vec3::default()
+        };
+        Self {
+            direction,
+            radiance,
+            cos_radius: SUN_RADIUS.cos(),
+        }
+    }
+
+    pub fn direction(&self) -> Vec3 {
+        self.direction
+    }
+
+    fn cone_pdf(&self) -> f64 {
+        1.0 / (2.0 * PI * (1.0 - self.cos_radius))
+    }
+}
+
+/// Rayleigh and aerosol extinction at the red, green and blue wavelengths.
+fn sun_transmittance(theta: f64, turbidity: f64) -> Vec3 {
+    let mass = 1.0 / (theta.cos() + 0.15 * (93.885 - theta.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let channel = |lambda: f64| {
+        let rayleigh = 0.008735 * lambda.powf(-4.08);
+        let aerosol = beta * lambda.powf(-1.3);
+        (-mass * (rayleigh + aerosol)).exp()
+    };
+    Vec3::new(channel(0.680), channel(0.550), channel(0.440))
+}
+
+impl Light for SunLight {
+    fn sample_li(&self, _point: Vec3, (u1, u2): (f64, f64)) -> Option<LightSample> {
+        if self.radiance == Vec3::default() {
+            return None;
+        }
+        let cos_theta = 1.0 - u1 * (1.0 - self.cos_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        let (s, t) = self.direction.orthonormal_basis();
+        let wi =
+            s * (sin_theta * phi.cos()) + t * (sin_theta * phi.sin()) + self.direction * cos_theta;
+        Some(LightSample::new(
+            wi,
+            self.radiance,
+            f64::INFINITY,
+            self.cone_pdf(),
+            false,
+        ))
+    }
+
+    fn pdf_li(&self, _point: Vec3, wi: Vec3) -> f64 {
+        if wi.dot(self.direction) >= self.cos_radius {
+            self.cone_pdf()
+        } else {
+            0.0
+        }
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+
+    fn le(&self, direction: Vec3) -> Vec3 {
+        if direction.dot(self.direction) >= self.cos_radius {
+            self.radiance
+        } else {
+            Vec3::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sky_is_blue_and_brightest_near_sun() {
+        let sky = PreethamSky::new(40.0, 90.0, 3.0);
+        let away = sky.radiance(sun_direction(40.0, 270.0));
+        assert!(away.z() > away.x(), "{:?}", away);
+        let zenith = sky.radiance(Vec3::new(0.0, 1.0, 0.0));
+        assert!((0.1..2.0).contains(&zenith.luminance()), "{:?}", zenith);
+        let near_sun = sky.radiance(sun_direction(42.0, 90.0));
+        assert!(near_sun.luminance() > zenith.luminance());
+    }
+
+    #[test]
+    fn test_sky_covers_upper_hemisphere() {
+        let sky = PreethamSky::new(20.0, 0.0, 3.0);
+        let below = Vec3::new(0.6, -0.8, 0.0);
+        assert_eq!(sky.radiance(below), Vec3::default());
+        assert_eq!(sky.pdf(below), 0.0);
+        for &u in &[(0.0, 0.0), (0.3, 0.6), (0.999, 0.9)] {
+            let sample = sky.sample(u).unwrap();
+            assert!(sample.wi().y() >= 0.0);
+            assert!((sample.wi().length() - 1.0).abs() < 1e-9);
+            assert_eq!(sky.pdf(sample.wi()), sample.pdf());
+            assert_eq!(sky.radiance(sample.wi()), sample.radiance());
+        }
+    }
+
+    #[test]
+    fn test_sun_reddens_towards_horizon() {
+        let ratio = |elevation| {
+            let sun = SunLight::new(elevation, 0.0, 3.0);
+            let radiance = sun.le(sun.direction());
+            radiance.x() / radiance.z()
+        };
+        assert!(ratio(5.0) > ratio(60.0));
+        assert!(ratio(60.0) > 1.0);
+        assert_eq!(
+            SunLight::new(-5.0, 0.0, 3.0).sample_li(Vec3::default(), (0.5, 0.5)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sun_samples_stay_in_disk() {
+        let sun = SunLight::new(30.0, 45.0, 4.0);
+        for &u in &[(0.0, 0.0), (0.5, 0.25), (0.999, 0.75)] {
+            let sample = sun.sample_li(Vec3::default(), u).unwrap();
+            assert!((sample.wi().length() - 1.0).abs() < 1e-9);
+            assert_eq!(sun.pdf_li(Vec3::default(), sample.wi()), sample.pdf());
+            assert_eq!(sun.le(sample.wi()), sample.radiance());
+        }
+        assert_eq!(sun.pdf_li(Vec3::default(), Vec3::new(0.0, 1.0, 0.0)), 0.0);
+    }
+}
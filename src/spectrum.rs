@@ -0,0 +1,291 @@
+use crate::Vec3;
+use std::sync::OnceLock;
+
+/// Shortest wavelength sampled by spectral rendering, in nanometers.
+pub const LAMBDA_MIN: f64 = 360.0;
+/// Longest wavelength sampled by spectral rendering, in nanometers.
+pub const LAMBDA_MAX: f64 = 830.0;
+/// Wavelength at which dispersive materials are evaluated when rendering in RGB.
+pub const REFERENCE_WAVELENGTH: f64 = 550.0;
+
+/// Wavelengths carried by one path: a hero wavelength and two more spread
+/// evenly over the visible range (Wilkie et al., "Hero Wavelength Spectral
+/// Sampling", 2014). Spectral values along the path are stored in a `Vec3`,
+/// one component per wavelength with the hero first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SampledWavelengths {
+    lambdas: [f64; 3],
+    secondary_terminated: bool,
+}
+
+impl SampledWavelengths {
+    /// Samples the hero wavelength uniformly from `u` in `[0, 1)`.
+    pub fn sample(u: f64) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut lambdas = [0.0; 3];
+        for (i, lambda) in lambdas.iter_mut().enumerate() {
+            let offset = (u + i as f64 / 3.0).fract();
+            *lambda = LAMBDA_MIN + offset * range;
+        }
+        Self {
+            lambdas,
+            secondary_terminated: false,
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambdas[0]
+    }
+
+    pub fn lambdas(&self) -> [f64; 3] {
+        self.lambdas
+    }
+
+    /// Drops the secondary wavelengths after a wavelength-dependent scattering
+    /// direction was chosen for the hero. Returns the weight of light gathered
+    /// from then on: the first call keeps only the hero, compensating for the
+    /// dropped wavelengths, later calls change nothing.
+    pub fn terminate_secondary(&mut self) -> Vec3 {
+        if self.secondary_terminated {
+            return Vec3::new(1.0, 1.0, 1.0);
+        }
+        self.secondary_terminated = true;
+        Vec3::new(3.0, 0.0, 0.0)
+    }
+
+    /// Spectral reflectance or radiance at these wavelengths for an RGB value.
+    pub fn uplift(&self, rgb: Vec3) -> Vec3 {
+        let [r, g, b] = self.lambdas;
+        Vec3::new(
+            rgb_to_spectrum(rgb, r),
+            rgb_to_spectrum(rgb, g),
+            rgb_to_spectrum(rgb, b),
+        )
+    }
+
+    /// Linear RGB estimate of a radiance spectrum sampled at these wavelengths.
+    pub fn to_rgb(&self, values: Vec3) -> Vec3 {
+        let values = [values.x(), values.y(), values.z()];
+        let mut xyz = Vec3::default();
+        for (&lambda, &value) in self.lambdas.iter().zip(&values) {
+            xyz += cie_xyz(lambda) * value;
+        }
+        // Average of the per-wavelength estimates with density 1 / range.
+        xyz *= (LAMBDA_MAX - LAMBDA_MIN) / (3.0 * cie_y_integral());
+        balanced_rgb(xyz)
+    }
+}
+
+/// CIE 1931 color matching functions, using the multi-lobe fit of Wyman,
+/// Sloan and Shirley, "Simple Analytic Approximations to the CIE XYZ Color
+/// Matching Functions", 2013.
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if lambda < mu {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        let t = (lambda - mu) / sigma;
+        (-0.5 * t * t).exp()
+    };
+    Vec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+/// Linear sRGB from CIE XYZ, without clamping out-of-gamut colors.
+pub(crate) fn xyz_to_rgb(xyz: Vec3) -> Vec3 {
+    let (x, y, z) = (xyz.x(), xyz.y(), xyz.z());
+    Vec3::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+/// Integrates `f` over the sampled range with one nanometer steps.
+fn integrate(f: impl Fn(f64) -> Vec3) -> Vec3 {
+    let steps = (LAMBDA_MAX - LAMBDA_MIN) as u32;
+    (0..steps)
+        .map(|i| f(LAMBDA_MIN + f64::from(i) + 0.5))
+        .fold(Vec3::default(), |acc, v| acc + v)
+}
+
+fn cie_y_integral() -> f64 {
+    static INTEGRAL: OnceLock<f64> = OnceLock::new();
+    *INTEGRAL.get_or_init(|| integrate(cie_xyz).y())
+}
+
+/// RGB of `xyz`, white balanced so that a constant unit spectrum is (1, 1, 1).
+fn balanced_rgb(xyz: Vec3) -> Vec3 {
+    static WHITE: OnceLock<Vec3> = OnceLock::new();
+    let white = *WHITE.get_or_init(|| xyz_to_rgb(integrate(cie_xyz) / cie_y_integral()));
+    let rgb = xyz_to_rgb(xyz);
+    Vec3::new(
+        rgb.x() / white.x(),
+        rgb.y() / white.y(),
+        rgb.z() / white.z(),
+    )
+}
+
+/// Smooth blue, green and red reflectance spectra that sum to one everywhere.
+fn basis(lambda: f64) -> Vec3 {
+    let smoothstep = |lo: f64, hi: f64| {
+        let t = ((lambda - lo) / (hi - lo)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    };
+    let blue = 1.0 - smoothstep(470.0, 510.0);
+    let red = smoothstep(570.0, 610.0);
+    Vec3::new(red, 1.0 - blue - red, blue)
+}
+
+/// Inverse of the matrix whose columns are the balanced RGB of the red, green
+/// and blue basis spectra, so that uplifted colors convert back exactly.
+fn uplift_matrix() -> &'static [Vec3; 3] {
+    static MATRIX: OnceLock<[Vec3; 3]> = OnceLock::new();
+    MATRIX.get_or_init(|| {
+        let column = |select: fn(Vec3) -> f64| {
+            let xyz = integrate(|lambda| cie_xyz(lambda) * select(basis(lambda)));
+            balanced_rgb(xyz / cie_y_integral())
+        };
+        let (c0, c1, c2) = (column(|v| v.x()), column(|v| v.y()), column(|v| v.z()));
+        // Rows of the inverse are the cross products of the columns.
+        let det = c0.dot(c1.cross(c2));
+        [c1.cross(c2) / det, c2.cross(c0) / det, c0.cross(c1) / det]
+    })
+}
+
+/// Value at `lambda` of a smooth spectrum whose color is `rgb`, never negative.
+///
+/// The spectrum is a mix of three broad basis spectra, so gray values give
+/// flat spectra and colors within the gamut of the basis convert back to
+/// themselves.
+pub fn rgb_to_spectrum(rgb: Vec3, lambda: f64) -> f64 {
+    let [r, g, b] = uplift_matrix();
+    let weights = Vec3::new(r.dot(rgb), g.dot(rgb), b.dot(rgb));
+    weights.dot(basis(lambda)).max(0.0)
+}
+
+/// Index of refraction as a function of wavelength.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ior {
+    Constant(f64),
+    /// Cauchy's equation `a + b / λ²` with λ in micrometers.
+    Cauchy {
+        a: f64,
+        b: f64,
+    },
+    /// Sellmeier equation `n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)` with λ in micrometers.
+    Sellmeier {
+        b: [f64; 3],
+        c: [f64; 3],
+    },
+}
+
+impl Ior {
+    /// Schott N-BK7 crown glass.
+    pub fn bk7() -> Self {
+        Ior::Sellmeier {
+            b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+            c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+        }
+    }
+
+    pub fn diamond() -> Self {
+        Ior::Sellmeier {
+            b: [4.3356, 0.3306, 0.0],
+            c: [0.106 * 0.106, 0.175 * 0.175, 0.0],
+        }
+    }
+
+    /// Index at `lambda` in nanometers.
+    pub fn at(&self, lambda: f64) -> f64 {
+        let micrometers = lambda / 1000.0;
+        let l2 = micrometers * micrometers;
+        match *self {
+            Ior::Constant(ior) => ior,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => {
+                let sum: f64 = b.iter().zip(&c).map(|(b, c)| b * l2 / (l2 - c)).sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cie_y_peaks_at_555() {
+        let peak = (400..700)
+            .max_by(|&a, &b| {
+                cie_xyz(f64::from(a))
+                    .y()
+                    .total_cmp(&cie_xyz(f64::from(b)).y())
+            })
+            .unwrap();
+        assert!((550..=560).contains(&peak), "{}", peak);
+        assert!(
+            (cie_y_integral() - 106.9).abs() < 1.0,
+            "{}",
+            cie_y_integral()
+        );
+    }
+
+    #[test]
+    fn test_uplift_round_trip() {
+        let colors = [
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(0.5, 0.5, 0.5),
+            Vec3::new(0.8, 0.3, 0.3),
+            Vec3::new(0.2, 0.6, 0.3),
+            Vec3::new(0.1, 0.2, 0.7),
+        ];
+        for &color in &colors {
+            let steps = 2000;
+            let mut rgb = Vec3::default();
+            for i in 0..steps {
+                let wavelengths =
+                    SampledWavelengths::sample((f64::from(i) + 0.5) / f64::from(steps));
+                rgb += wavelengths.to_rgb(wavelengths.uplift(color));
+            }
+            rgb /= f64::from(steps);
+            assert!((rgb - color).length() < 0.01, "{:?} {:?}", rgb, color);
+        }
+        // Gray is flat and reflectances stay physical.
+        for &lambda in &[380.0, 500.0, 700.0] {
+            assert!((rgb_to_spectrum(Vec3::new(0.5, 0.5, 0.5), lambda) - 0.5).abs() < 1e-9);
+            assert!(rgb_to_spectrum(Vec3::new(1.0, 0.0, 0.0), lambda) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn test_terminate_secondary() {
+        let mut wavelengths = SampledWavelengths::sample(0.5);
+        let lambdas = wavelengths.lambdas();
+        assert!((lambdas[1] - lambdas[0] - (LAMBDA_MAX - LAMBDA_MIN) / 3.0).abs() < 1e-9);
+        assert!(lambdas[2] < lambdas[0]);
+        assert_eq!(wavelengths.terminate_secondary(), Vec3::new(3.0, 0.0, 0.0));
+        assert_eq!(wavelengths.terminate_secondary(), Vec3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_dispersion() {
+        let bk7 = Ior::bk7();
+        assert!((bk7.at(587.6) - 1.5168).abs() < 1e-3);
+        assert!(bk7.at(450.0) > bk7.at(650.0));
+        assert!((Ior::diamond().at(589.0) - 2.417).abs() < 0.01);
+        let cauchy = Ior::Cauchy { a: 1.5, b: 0.004 };
+        assert!((cauchy.at(500.0) - 1.516).abs() < 1e-12);
+        assert!(!Ior::Constant(1.5).is_dispersive() && bk7.is_dispersive());
+    }
+}
@@ -1,12 +1,17 @@
 use crate::hit::HitRecord;
-use crate::material::Scatter;
-use crate::{Hit, Ray, Vec3};
+use crate::light::{cosine_emission, EmissionSample, Light, LightSample};
+use crate::light_sampler::LightBounds;
+use crate::material::{uniform_sphere, Scatter};
+use crate::{Aabb, Hit, Ray, Vec3};
+use std::f64::consts::PI;
 use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct Sphere {
     center: Vec3,
     radius: f64,
     material: Arc<dyn Scatter>,
+    light: Option<usize>,
 }
 
 impl Sphere {
@@ -15,28 +20,235 @@ impl Sphere {
             center,
             radius,
             material,
+            light: None,
+        }
+    }
+
+    fn surface_record(&self, point: Vec3) -> HitRecord<'_> {
+        let normal = (point - self.center) / self.radius;
+        HitRecord::with_uv(0.0, point, normal, sphere_uv(normal), &*self.material)
+    }
+
+    /// Axis towards the center, distance to it and cosine of the half-angle of
+    /// the cone subtended by the sphere, or `None` from inside the sphere.
+    fn cone(&self, point: Vec3) -> Option<(Vec3, f64, f64)> {
+        let to_center = self.center - point;
+        let distance2 = to_center.squared_length();
+        let radius2 = self.radius * self.radius;
+        if distance2 <= radius2 * (1.0 + 1e-9) {
+            return None;
+        }
+        let sin2_max = radius2 / distance2;
+        let distance = distance2.sqrt();
+        Some((to_center / distance, distance, (1.0 - sin2_max).sqrt()))
+    }
+
+    fn area_pdf_to_solid_angle(&self, point: Vec3, surface_point: Vec3) -> f64 {
+        let to_surface = surface_point - point;
+        let distance2 = to_surface.squared_length();
+        let normal = (surface_point - self.center) / self.radius;
+        let cos = normal.dot(to_surface).abs() / distance2.sqrt();
+        let area = 4.0 * PI * self.radius * self.radius;
+        if cos <= 0.0 {
+            0.0
+        } else {
+            distance2 / (cos * area)
         }
     }
 }
 
 impl Hit for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = ray.origin() - self.center;
-        let a = ray.direction().squared_length();
-        let b = oc.dot(ray.direction());
-        let c = oc.squared_length() - self.radius * self.radius;
-        let discriminant = b * b - a * c;
-        if discriminant > 0.0 {
-            let d_sqrt = discriminant.sqrt();
-            for &temp in &[(-b - d_sqrt) / a, (-b + d_sqrt) / a] {
-                if temp < t_max && temp > t_min {
-                    let t = temp;
-                    let point = ray.point_at_parameter(t);
-                    let normal = (point - self.center) / self.radius;
-                    return Some(HitRecord::new(t, point, normal, &*self.material));
-                }
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let hit = hit_sphere(self.center, self.radius, &*self.material, ray, t_min, t_max)?;
+        Some(match self.light {
+            Some(light) => hit.with_light(light),
+            None => hit,
+        })
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<Aabb> {
+        Some(sphere_box(self.center, self.radius))
+    }
+
+    fn register_lights(&mut self, lights: &mut Vec<Arc<dyn Light>>) {
+        if self.material.is_emissive() {
+            self.light = Some(lights.len());
+            lights.push(Arc::new(self.clone()));
+        }
+    }
+
+    fn collect_materials<'a>(&'a self, materials: &mut Vec<&'a dyn Scatter>) {
+        materials.push(&*self.material);
+    }
+}
+
+/// Emissive sphere sampled uniformly within the cone of directions it subtends,
+/// or uniformly by area from inside the sphere.
+impl Light for Sphere {
+    fn sample_li(&self, point: Vec3, u: (f64, f64)) -> Option<LightSample> {
+        let surface_point = match self.cone(point) {
+            Some((axis, distance, cos_max)) => {
+                let (u1, u2) = u;
+                let cos_theta = 1.0 - u1 * (1.0 - cos_max);
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * u2;
+                let (s, t) = axis.orthonormal_basis();
+                let wi =
+                    s * (sin_theta * phi.cos()) + t * (sin_theta * phi.sin()) + axis * cos_theta;
+                // Nearest intersection along wi, clamped to the silhouette.
+                let along = distance * cos_theta;
+                let discriminant =
+                    self.radius * self.radius - distance * distance * sin_theta * sin_theta;
+                point + wi * (along - discriminant.max(0.0).sqrt())
+            }
+            None => self.center + self.radius * uniform_sphere(u),
+        };
+        let to_light = surface_point - point;
+        let distance = to_light.length();
+        let wi = to_light / distance;
+        let pdf = self.pdf_li(point, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let record = self.surface_record(surface_point);
+        let radiance = self.material.emitted(&record, -wi);
+        Some(LightSample::new(wi, radiance, distance, pdf, false).with_normal(record.normal()))
+    }
+
+    fn pdf_li(&self, point: Vec3, wi: Vec3) -> f64 {
+        if let Some((axis, _, cos_max)) = self.cone(point) {
+            return if wi.dot(axis) >= cos_max {
+                1.0 / (2.0 * PI * (1.0 - cos_max))
+            } else {
+                0.0
+            };
+        }
+        let ray = Ray::new(point, wi, 0.0);
+        match hit_sphere(
+            self.center,
+            self.radius,
+            &*self.material,
+            &ray,
+            0.0,
+            f64::MAX,
+        ) {
+            Some(hit) => self.area_pdf_to_solid_angle(point, hit.point()),
+            None => 0.0,
+        }
+    }
+
+    fn sample_le(&self, u_position: (f64, f64), u_direction: (f64, f64)) -> Option<EmissionSample> {
+        let normal = uniform_sphere(u_position);
+        let record = self.surface_record(self.center + self.radius * normal);
+        let area = 4.0 * PI * self.radius * self.radius;
+        cosine_emission(
+            record.point(),
+            normal,
+            1.0 / area,
+            u_direction,
+            |direction| self.material.emitted(&record, direction),
+        )
+    }
+
+    fn pdf_le(&self, _point: Vec3, normal: Vec3, direction: Vec3) -> (f64, f64) {
+        let area = 4.0 * PI * self.radius * self.radius;
+        (1.0 / area, normal.dot(direction).max(0.0) / PI)
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let top = self.center + Vec3::new(0.0, self.radius, 0.0);
+        let radiance = self
+            .material
+            .emitted(&self.surface_record(top), Vec3::new(0.0, 1.0, 0.0));
+        let area = 4.0 * PI * self.radius * self.radius;
+        Some(LightBounds::omnidirectional(
+            sphere_box(self.center, self.radius),
+            PI * area * radiance.luminance(),
+        ))
+    }
+}
+
+pub(crate) fn hit_sphere<'a>(
+    center: Vec3,
+    radius: f64,
+    material: &'a dyn Scatter,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord<'a>> {
+    let oc = ray.origin() - center;
+    let a = ray.direction().squared_length();
+    let b = oc.dot(ray.direction());
+    let c = oc.squared_length() - radius * radius;
+    let discriminant = b * b - a * c;
+    if discriminant > 0.0 {
+        let d_sqrt = discriminant.sqrt();
+        for &temp in &[(-b - d_sqrt) / a, (-b + d_sqrt) / a] {
+            if temp < t_max && temp > t_min {
+                let t = temp;
+                let point = ray.point_at_parameter(t);
+                let normal = (point - center) / radius;
+                return Some(HitRecord::with_uv(
+                    t,
+                    point,
+                    normal,
+                    sphere_uv(normal),
+                    material,
+                ));
             }
         }
-        None
+    }
+    None
+}
+
+/// Spherical coordinates of a point on the unit sphere: `u` is the longitude
+/// measured from `-x` around `+y`, `v` the latitude from the south pole.
+pub(crate) fn sphere_uv(p: Vec3) -> (f64, f64) {
+    use std::f64::consts::PI;
+    let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+pub(crate) fn sphere_box(center: Vec3, radius: f64) -> Aabb {
+    let radius = Vec3::new(radius, radius, radius);
+    Aabb::new(center - radius, center + radius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sphere_uv() {
+        let close = |(u, v): (f64, f64), (eu, ev): (f64, f64)| {
+            (u - eu).abs() < 1e-12 && (v - ev).abs() < 1e-12
+        };
+        assert!(close(sphere_uv(Vec3::new(1.0, 0.0, 0.0)), (0.5, 0.5)));
+        assert!(close(sphere_uv(Vec3::new(0.0, 1.0, 0.0)), (0.5, 1.0)));
+        assert!(close(sphere_uv(Vec3::new(0.0, -1.0, 0.0)), (0.5, 0.0)));
+        assert!(close(sphere_uv(Vec3::new(0.0, 0.0, 1.0)), (0.25, 0.5)));
+        assert!(close(sphere_uv(Vec3::new(0.0, 0.0, -1.0)), (0.75, 0.5)));
+    }
+
+    #[test]
+    fn test_cone_sampling() {
+        let light = Sphere::new(
+            Vec3::new(0.0, 0.0, -4.0),
+            1.0,
+            Arc::new(crate::DiffuseLight::new(Vec3::new(1.0, 1.0, 1.0))),
+        );
+        let point = Vec3::new(0.5, 0.0, 0.0);
+        let cone_pdf = light.pdf_li(point, (light.center - point).normalize());
+        let cos_max = (1.0 - 1.0 / (light.center - point).squared_length()).sqrt();
+        assert!((cone_pdf - 1.0 / (2.0 * PI * (1.0 - cos_max))).abs() < 1e-9);
+        for &u in &[(0.0, 0.0), (0.3, 0.7), (0.999_999, 0.2)] {
+            let sample = light.sample_li(point, u).unwrap();
+            let surface_point = point + sample.wi() * sample.distance();
+            assert!(((surface_point - light.center).length() - 1.0).abs() < 1e-6);
+            assert_eq!(sample.pdf(), cone_pdf);
+            assert_eq!(sample.radiance(), Vec3::new(1.0, 1.0, 1.0));
+        }
+        assert_eq!(light.pdf_li(point, Vec3::new(0.0, 1.0, 0.0)), 0.0);
     }
 }
@@ -0,0 +1,192 @@
+use crate::error::invalid_data;
+use crate::material::Scatter;
+use crate::{Mesh, Vec3};
+use std::collections::HashMap;
+use std::io::{Error, Read};
+use std::sync::Arc;
+
+/// Reads an ASCII or binary STL file; identical vertices are merged into an indexed mesh.
+///
+/// STL only stores facet normals, so the resulting mesh is flat shaded.
+pub fn read_stl<R: Read>(input: &mut R, material: Arc<dyn Scatter>) -> Result<Mesh, Error> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    // Binary headers may start with "solid" too, so only text that is not
+    // laid out like a binary file is read as ASCII.
+    let facets = if data.starts_with(b"solid") && binary_facet_count(&data).is_none() {
+        read_ascii_facets(&data)?
+    } else {
+        let count =
+            binary_facet_count(&data).ok_or_else(|| invalid_data("Truncated binary STL"))?;
+        read_binary_facets(&data, count)
+    };
+
+    let mut indices = HashMap::new();
+    let mut positions = Vec::new();
+    let triangles = facets
+        .iter()
+        .map(|facet| {
+            let mut triangle = [0; 3];
+            for (idx, &vertex) in triangle.iter_mut().zip(facet) {
+                let key = (
+                    vertex.x().to_bits(),
+                    vertex.y().to_bits(),
+                    vertex.z().to_bits(),
+                );
+                *idx = *indices.entry(key).or_insert_with(|| {
+                    positions.push(vertex);
+                    positions.len() - 1
+                });
+            }
+            triangle
+        })
+        .collect();
+    Ok(Mesh::new(
+        positions,
+        Vec::new(),
+        Vec::new(),
+        Vec::new(),
+        triangles,
+        material,
+    ))
+}
+
+/// Facet count in the binary header, if the data is long enough to hold that
+/// many facets; some exporters append padding after them.
+fn binary_facet_count(data: &[u8]) -> Option<usize> {
+    let count = data.get(80..84)?;
+    let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+    let size = count.checked_mul(50)?.checked_add(84)?;
+    if data.len() >= size {
+        Some(count)
+    } else {
+        None
+    }
+}
+
+fn read_binary_facets(data: &[u8], count: usize) -> Vec<[Vec3; 3]> {
+    let read_vec = |bytes: &[u8]| {
+        let f = |i: usize| {
+            f64::from(f32::from_le_bytes([
+                bytes[4 * i],
+                bytes[4 * i + 1],
+                bytes[4 * i + 2],
+                bytes[4 * i + 3],
+            ]))
+        };
+        Vec3::new(f(0), f(1), f(2))
+    };
+    data[84..]
+        .chunks_exact(50)
+        .take(count)
+        .map(|facet| {
+            [
+                read_vec(&facet[12..24]),
+                read_vec(&facet[24..36]),
+                read_vec(&facet[36..48]),
+            ]
+        })
+        .collect()
+}
+
+fn read_ascii_facets(data: &[u8]) -> Result<Vec<[Vec3; 3]>, Error> {
+    let text = std::str::from_utf8(data).map_err(|_| invalid_data("STL is not valid text"))?;
+    let mut tokens = text.split_whitespace();
+    if tokens.next() != Some("solid") {
+        return Err(invalid_data("Not an STL file"));
+    }
+    let mut facets = Vec::new();
+    let mut vertices = Vec::with_capacity(3);
+    while let Some(token) = tokens.next() {
+        match token {
+            "vertex" => {
+                let mut coord = || -> Result<f64, Error> {
+                    tokens
+                        .next()
+                        .and_then(|t| t.parse().ok())
+                        .ok_or_else(|| invalid_data("Malformed STL vertex"))
+                };
+                vertices.push(Vec3::new(coord()?, coord()?, coord()?));
+            }
+            "endfacet" => {
+                if vertices.len() != 3 {
+                    return Err(invalid_data("STL facet must have three vertices"));
+                }
+                facets.push([vertices[0], vertices[1], vertices[2]]);
+                vertices.clear();
+            }
+            _ => {}
+        }
+    }
+    Ok(facets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+
+    fn material() -> Arc<dyn Scatter> {
+        Arc::new(Lambertian::new(Vec3::new(1.0, 1.0, 1.0)))
+    }
+
+    #[test]
+    fn test_read_ascii() {
+        let mut input: &[u8] = b"solid quad
+facet normal 0 0 1
+  outer loop
+    vertex 0 0 0
+    vertex 1 0 0
+    vertex 1 1 0
+  endloop
+endfacet
+facet normal 0 0 1
+  outer loop
+    vertex 0 0 0
+    vertex 1 1 0
+    vertex 0 1 0
+  endloop
+endfacet
+endsolid quad
+";
+        let mesh = read_stl(&mut input, material()).unwrap();
+        assert_eq!(mesh.positions().len(), 4);
+        assert_eq!(mesh.triangles(), &[[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn test_read_binary() {
+        let mut input = vec![0; 80];
+        input.extend_from_slice(&1u32.to_le_bytes());
+        for x in &[
+            0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+        ] {
+            input.extend_from_slice(&x.to_le_bytes());
+        }
+        input.extend_from_slice(&[0, 0]);
+        let mesh = read_stl(&mut input.as_slice(), material()).unwrap();
+        assert_eq!(mesh.positions()[2], Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(mesh.triangles(), &[[0, 1, 2]]);
+    }
+
+    #[test]
+    fn test_binary_header_starting_with_solid() {
+        let mut input = b"solid exported".to_vec();
+        input.resize(80, b' ');
+        input.extend_from_slice(&1u32.to_le_bytes());
+        for x in &[
+            0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+        ] {
+            input.extend_from_slice(&x.to_le_bytes());
+        }
+        input.extend_from_slice(&[0, 0]);
+        // Trailing padding after the facets.
+        input.extend_from_slice(&[0; 7]);
+        let mesh = read_stl(&mut input.as_slice(), material()).unwrap();
+        assert_eq!(mesh.triangles(), &[[0, 1, 2]]);
+
+        input.truncate(100);
+        input[..5].copy_from_slice(b"flat ");
+        assert!(read_stl(&mut input.as_slice(), material()).is_err());
+    }
+}
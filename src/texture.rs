@@ -0,0 +1,174 @@
+use crate::error::invalid_data;
+use crate::{Image, Vec3};
+use std::io::Error;
+use std::sync::Arc;
+
+/// Spatially varying color, evaluated at the surface parametrization and hit point.
+pub trait Texture: Send + Sync {
+    fn value(&self, uv: (f64, f64), point: Vec3) -> Vec3;
+}
+
+pub struct SolidColor {
+    color: Vec3,
+}
+
+impl SolidColor {
+    pub fn new(color: Vec3) -> Self {
+        Self { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _uv: (f64, f64), _point: Vec3) -> Vec3 {
+        self.color
+    }
+}
+
+/// 3D checkerboard alternating between two textures; `scale` is the number of
+/// cells per `π` units of length.
+pub struct Checker {
+    odd: Arc<dyn Texture>,
+    even: Arc<dyn Texture>,
+    scale: f64,
+}
+
+impl Checker {
+    pub fn new(odd: Arc<dyn Texture>, even: Arc<dyn Texture>, scale: f64) -> Self {
+        Self { odd, even, scale }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, uv: (f64, f64), point: Vec3) -> Vec3 {
+        let sines = (self.scale * point.x()).sin()
+            * (self.scale * point.y()).sin()
+            * (self.scale * point.z()).sin();
+        if sines < 0.0 {
+            self.odd.value(uv, point)
+        } else {
+            self.even.value(uv, point)
+        }
+    }
+}
+
+/// How texture lookups outside of `[0, 1]` are resolved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+}
+
+/// Bilinearly filtered image texture; `(0, 0)` is the bottom-left corner of the image.
+///
+/// Pixels are decoded with the same gamma 2 curve the renderer uses for output.
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    texels: Vec<Vec3>,
+    wrap: WrapMode,
+}
+
+impl ImageTexture {
+    /// Fails for an empty image.
+    pub fn new(image: &Image, wrap: WrapMode) -> Result<Self, Error> {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        if width == 0 || height == 0 {
+            return Err(invalid_data("Texture image is empty"));
+        }
+        let decode = |c: u8| {
+            let c = f64::from(c) / 255.0;
+            c * c
+        };
+        let texels = (0..width * height)
+            .map(|idx| {
+                let color = image[((idx % width) as u32, (idx / width) as u32)];
+                Vec3::new(
+                    decode(color.red()),
+                    decode(color.green()),
+                    decode(color.blue()),
+                )
+            })
+            .collect();
+        Ok(Self {
+            width,
+            height,
+            texels,
+            wrap,
+        })
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Vec3 {
+        let resolve = |i: i64, n: usize| match self.wrap {
+            WrapMode::Repeat => i.rem_euclid(n as i64) as usize,
+            WrapMode::Clamp => i.clamp(0, n as i64 - 1) as usize,
+        };
+        self.texels[resolve(y, self.height) * self.width + resolve(x, self.width)]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, (u, v): (f64, f64), _point: Vec3) -> Vec3 {
+        let x = u * self.width as f64 - 0.5;
+        let y = v * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        (1.0 - fy) * ((1.0 - fx) * self.texel(x0, y0) + fx * self.texel(x0 + 1, y0))
+            + fy * ((1.0 - fx) * self.texel(x0, y0 + 1) + fx * self.texel(x0 + 1, y0 + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Color;
+
+    fn make_image() -> Image {
+        let mut image = Image::new(2, 1);
+        image[(1, 0)] = Color::new(255, 255, 255);
+        image
+    }
+
+    #[test]
+    fn test_checker() {
+        let black: Arc<dyn Texture> = Arc::new(SolidColor::new(Vec3::default()));
+        let white: Arc<dyn Texture> = Arc::new(SolidColor::new(Vec3::new(1.0, 1.0, 1.0)));
+        let checker = Checker::new(black, white, 1.0);
+        let even = checker.value((0.0, 0.0), Vec3::new(1.0, 1.0, 1.0));
+        let odd = checker.value((0.0, 0.0), Vec3::new(-1.0, 1.0, 1.0));
+        assert_eq!(even, Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(odd, Vec3::default());
+    }
+
+    #[test]
+    fn test_image_bilinear() {
+        let texture = ImageTexture::new(&make_image(), WrapMode::Clamp).unwrap();
+        assert_eq!(texture.value((0.25, 0.5), Vec3::default()), Vec3::default());
+        assert_eq!(
+            texture.value((0.5, 0.5), Vec3::default()),
+            Vec3::new(0.5, 0.5, 0.5)
+        );
+        assert_eq!(
+            texture.value((1.5, 0.5), Vec3::default()),
+            Vec3::new(1.0, 1.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_reject_empty_image() {
+        assert!(ImageTexture::new(&Image::new(0, 0), WrapMode::Repeat).is_err());
+    }
+
+    #[test]
+    fn test_image_repeat() {
+        let texture = ImageTexture::new(&make_image(), WrapMode::Repeat).unwrap();
+        assert_eq!(
+            texture.value((1.25, 0.5), Vec3::default()),
+            texture.value((0.25, 0.5), Vec3::default())
+        );
+        assert_eq!(
+            texture.value((0.0, 0.5), Vec3::default()),
+            Vec3::new(0.5, 0.5, 0.5)
+        );
+    }
+}
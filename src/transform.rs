@@ -0,0 +1,459 @@
+use crate::{Aabb, Ray, Vec3};
+use std::ops::Mul;
+
+type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Affine transform stored together with its inverse.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    m: Matrix,
+    inv: Matrix,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            m: IDENTITY,
+            inv: IDENTITY,
+        }
+    }
+
+    pub fn translate(delta: Vec3) -> Self {
+        let m = [
+            [1.0, 0.0, 0.0, delta.x()],
+            [0.0, 1.0, 0.0, delta.y()],
+            [0.0, 0.0, 1.0, delta.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let inv = [
+            [1.0, 0.0, 0.0, -delta.x()],
+            [0.0, 1.0, 0.0, -delta.y()],
+            [0.0, 0.0, 1.0, -delta.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        Self { m, inv }
+    }
+
+    pub fn scale(factor: Vec3) -> Self {
+        let m = [
+            [factor.x(), 0.0, 0.0, 0.0],
+            [0.0, factor.y(), 0.0, 0.0],
+            [0.0, 0.0, factor.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let inv = [
+            [1.0 / factor.x(), 0.0, 0.0, 0.0],
+            [0.0, 1.0 / factor.y(), 0.0, 0.0],
+            [0.0, 0.0, 1.0 / factor.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        Self { m, inv }
+    }
+
+    /// Rotation by `degrees` counter-clockwise around `axis`.
+    pub fn rotate(axis: Vec3, degrees: f64) -> Self {
+        Quat::from_axis_angle(axis, degrees.to_radians()).to_transform()
+    }
+
+    pub fn inverse(self) -> Self {
+        Self {
+            m: self.inv,
+            inv: self.m,
+        }
+    }
+
+    pub fn point(&self, p: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3],
+            m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3],
+            m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3],
+        )
+    }
+
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+
+    /// Transforms a surface normal with the inverse transpose; the result is not normalized.
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        let inv = &self.inv;
+        Vec3::new(
+            inv[0][0] * n.x() + inv[1][0] * n.y() + inv[2][0] * n.z(),
+            inv[0][1] * n.x() + inv[1][1] * n.y() + inv[2][1] * n.z(),
+            inv[0][2] * n.x() + inv[1][2] * n.y() + inv[2][2] * n.z(),
+        )
+    }
+
+    pub fn ray(&self, ray: &Ray) -> Ray {
+        Ray::new(
+            self.point(ray.origin()),
+            self.vector(ray.direction()),
+            ray.time(),
+        )
+    }
+
+    pub fn bounds(&self, aabb: &Aabb) -> Aabb {
+        let corners = aabb.corners();
+        let first = self.point(corners[0]);
+        corners[1..]
+            .iter()
+            .fold(Aabb::new(first, first), |acc, &corner| {
+                let p = self.point(corner);
+                acc.surrounding(Aabb::new(p, p))
+            })
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+/// `a * b` applies `b` first and then `a`.
+impl Mul for Transform {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self {
+            m: mul_matrices(&self.m, &rhs.m),
+            inv: mul_matrices(&rhs.inv, &self.inv),
+        }
+    }
+}
+
+fn mul_matrices(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for (i, row) in result.iter_mut().enumerate() {
+        for (j, elem) in row.iter_mut().enumerate() {
+            *elem = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    result
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Quat {
+    w: f64,
+    v: Vec3,
+}
+
+impl Quat {
+    fn from_axis_angle(axis: Vec3, radians: f64) -> Self {
+        let half = 0.5 * radians;
+        Self {
+            w: half.cos(),
+            v: axis.normalize() * half.sin(),
+        }
+    }
+
+    fn dot(self, other: Self) -> f64 {
+        self.w * other.w + self.v.dot(other.v)
+    }
+
+    fn normalize(self) -> Self {
+        let length = self.dot(self).sqrt();
+        Self {
+            w: self.w / length,
+            v: self.v / length,
+        }
+    }
+
+    fn slerp(self, mut other: Self, t: f64) -> Self {
+        let mut cos_theta = self.dot(other);
+        if cos_theta < 0.0 {
+            other = Self {
+                w: -other.w,
+                v: -other.v,
+            };
+            cos_theta = -cos_theta;
+        }
+        if cos_theta > 0.9995 {
+            return Self {
+                w: (1.0 - t) * self.w + t * other.w,
+                v: (1.0 - t) * self.v + t * other.v,
+            }
+            .normalize();
+        }
+        let theta = cos_theta.acos();
+        let a = ((1.0 - t) * theta).sin() / theta.sin();
+        let b = (t * theta).sin() / theta.sin();
+        Self {
+            w: a * self.w + b * other.w,
+            v: a * self.v + b * other.v,
+        }
+    }
+
+    fn to_transform(self) -> Transform {
+        let (w, x, y, z) = (self.w, self.v.x(), self.v.y(), self.v.z());
+        let m = [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let mut inv = IDENTITY;
+        for (i, row) in inv.iter_mut().enumerate().take(3) {
+            for (j, elem) in row.iter_mut().enumerate().take(3) {
+                *elem = m[j][i];
+            }
+        }
+        Transform { m, inv }
+    }
+}
+
+/// Pose of an instance at a moment of time: scale, then rotation, then translation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    time: f64,
+    translation: Vec3,
+    rotation: Quat,
+    scale: Vec3,
+}
+
+impl Keyframe {
+    pub fn new(time: f64, translation: Vec3, axis: Vec3, degrees: f64, scale: Vec3) -> Self {
+        Self {
+            time,
+            translation,
+            rotation: Quat::from_axis_angle(axis, degrees.to_radians()),
+            scale,
+        }
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    fn to_transform(self) -> Transform {
+        Transform::translate(self.translation)
+            * self.rotation.to_transform()
+            * Transform::scale(self.scale)
+    }
+
+    fn lerp(&self, other: &Self, time: f64) -> Self {
+        let s = (time - self.time) / (other.time - self.time);
+        Self {
+            time,
+            translation: self.translation + s * (other.translation - self.translation),
+            rotation: self.rotation.slerp(other.rotation, s),
+            scale: self.scale + s * (other.scale - self.scale),
+        }
+    }
+}
+
+/// Transform interpolated between keyframes, held constant outside of them.
+#[derive(Clone, Debug)]
+pub struct AnimatedTransform {
+    keyframes: Vec<Keyframe>,
+}
+
+impl AnimatedTransform {
+    pub fn new(mut keyframes: Vec<Keyframe>) -> Self {
+        assert!(!keyframes.is_empty(), "At least one keyframe is required");
+        assert!(
+            keyframes.iter().all(|k| k.time.is_finite()),
+            "Keyframe times must be finite"
+        );
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { keyframes }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn at(&self, time: f64) -> Transform {
+        self.pose(time).to_transform()
+    }
+
+    fn pose(&self, time: f64) -> Keyframe {
+        let first = self.keyframes[0];
+        let last = self.keyframes[self.keyframes.len() - 1];
+        if time <= first.time {
+            return first;
+        }
+        if time >= last.time {
+            return last;
+        }
+        let next = self.keyframes.iter().position(|k| k.time > time).unwrap();
+        self.keyframes[next - 1].lerp(&self.keyframes[next], time)
+    }
+
+    /// Box holding `aabb` transformed at every time from `time0` to `time1`.
+    ///
+    /// Between two poses with the same rotation every point moves along a
+    /// line, so the boxes of the two poses bound the motion. While rotating,
+    /// the box instead stays within the sphere around the rotation center
+    /// that holds it scaled by either pose, swept along the translation.
+    pub fn bounds(&self, aabb: &Aabb, time0: f64, time1: f64) -> Aabb {
+        let mut times = vec![time0];
+        times.extend(
+            self.keyframes
+                .iter()
+                .map(|k| k.time)
+                .filter(|&t| t > time0 && t < time1),
+        );
+        times.push(time1);
+        times
+            .windows(2)
+            .map(|span| {
+                let (a, b) = (self.pose(span[0]), self.pose(span[1]));
+                if a.rotation == b.rotation {
+                    return a
+                        .to_transform()
+                        .bounds(aabb)
+                        .surrounding(b.to_transform().bounds(aabb));
+                }
+                let radius = aabb
+                    .corners()
+                    .iter()
+                    .flat_map(|&corner| [a.scale * corner, b.scale * corner])
+                    .map(|corner| corner.length())
+                    .fold(0.0, f64::max);
+                let reach = Vec3::new(radius, radius, radius);
+                Aabb::new(
+                    a.translation.min(b.translation) - reach,
+                    a.translation.max(b.translation) + reach,
+                )
+            })
+            .reduce(Aabb::surrounding)
+            .expect("at least two times")
+    }
+}
+
+impl From<Keyframe> for AnimatedTransform {
+    fn from(keyframe: Keyframe) -> Self {
+        Self::new(vec![keyframe])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).length() < 1e-9,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_translate_point_and_vector() {
+        let transform = Transform::translate(Vec3::new(1.0, 2.0, 3.0));
+        let v = Vec3::new(1.0, 1.0, 1.0);
+        assert_eq!(transform.point(v), Vec3::new(2.0, 3.0, 4.0));
+        assert_eq!(transform.vector(v), v);
+    }
+
+    #[test]
+    fn test_rotate() {
+        let transform = Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 90.0);
+        assert_close(
+            transform.point(Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn test_compose_with_inverse() {
+        let transform = Transform::translate(Vec3::new(1.0, -2.0, 0.5))
+            * Transform::rotate(Vec3::new(1.0, 1.0, 0.0), 33.0)
+            * Transform::scale(Vec3::new(2.0, 3.0, 4.0));
+        let p = Vec3::new(0.3, -0.7, 1.1);
+        assert_close(transform.inverse().point(transform.point(p)), p);
+    }
+
+    #[test]
+    fn test_normal_stays_perpendicular() {
+        let transform = Transform::scale(Vec3::new(1.0, 4.0, 1.0));
+        let tangent = transform.vector(Vec3::new(1.0, -1.0, 0.0));
+        let normal = transform.normal(Vec3::new(1.0, 1.0, 0.0));
+        assert!(tangent.dot(normal).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_animated_transform_interpolates() {
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let unit = Vec3::new(1.0, 1.0, 1.0);
+        let animated = AnimatedTransform::new(vec![
+            Keyframe::new(1.0, Vec3::new(2.0, 0.0, 0.0), axis, 90.0, unit),
+            Keyframe::new(0.0, Vec3::default(), axis, 0.0, unit),
+        ]);
+        let origin = Vec3::default();
+        assert_close(animated.at(-1.0).point(origin), origin);
+        assert_close(animated.at(0.5).point(origin), Vec3::new(1.0, 0.0, 0.0));
+        assert_close(animated.at(2.0).point(origin), Vec3::new(2.0, 0.0, 0.0));
+        let half_turn = 0.5f64.sqrt();
+        assert_close(
+            animated.at(0.5).vector(Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(half_turn, 0.0, -half_turn),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "finite")]
+    fn test_rejects_nan_keyframe_time() {
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let unit = Vec3::new(1.0, 1.0, 1.0);
+        AnimatedTransform::new(vec![
+            Keyframe::new(0.0, Vec3::default(), axis, 0.0, unit),
+            Keyframe::new(f64::NAN, Vec3::default(), axis, 0.0, unit),
+        ]);
+    }
+
+    #[test]
+    fn test_motion_bounds_hold_every_pose() {
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let animated = AnimatedTransform::new(vec![
+            Keyframe::new(0.0, Vec3::default(), axis, 0.0, Vec3::new(1.0, 1.0, 1.0)),
+            Keyframe::new(
+                1.0,
+                Vec3::new(0.0, 1.0, 0.0),
+                axis,
+                170.0,
+                Vec3::new(2.0, 1.0, 1.0),
+            ),
+        ]);
+        let local = Aabb::new(Vec3::new(1.0, 0.0, 0.0), Vec3::new(2.0, 0.1, 0.1));
+        let bounds = animated.bounds(&local, 0.2, 0.9);
+        for i in 0..=1000 {
+            let posed = animated
+                .at(0.2 + 0.7 * f64::from(i) / 1000.0)
+                .bounds(&local);
+            assert!(posed.min().min(bounds.min()) == bounds.min(), "{}", i);
+            assert!(posed.max().max(bounds.max()) == bounds.max(), "{}", i);
+        }
+    }
+}
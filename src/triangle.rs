@@ -0,0 +1,56 @@
+use crate::{Ray, Vec3};
+
+/// Möller–Trumbore ray/triangle intersection.
+///
+/// Returns the ray parameter and the barycentric coordinates of `v1` and `v2`.
+pub(crate) fn intersect_triangle(
+    ray: &Ray,
+    v0: Vec3,
+    v1: Vec3,
+    v2: Vec3,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64, f64)> {
+    const EPSILON: f64 = 1e-12;
+    let edge1 = v1 - v0;
+    let edge2 = v2 - v0;
+    let p = ray.direction().cross(edge2);
+    let det = edge1.dot(p);
+    if det.abs() < EPSILON {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = ray.origin() - v0;
+    let b1 = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let q = s.cross(edge1);
+    let b2 = ray.direction().dot(q) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(q) * inv_det;
+    if t > t_min && t < t_max {
+        Some((t, b1, b2))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intersect_triangle() {
+        let v0 = Vec3::new(0.0, 0.0, 0.0);
+        let v1 = Vec3::new(1.0, 0.0, 0.0);
+        let v2 = Vec3::new(0.0, 1.0, 0.0);
+        let ray = Ray::new(Vec3::new(0.25, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let (t, b1, b2) = intersect_triangle(&ray, v0, v1, v2, 0.0, f64::MAX).unwrap();
+        assert_eq!((t, b1, b2), (1.0, 0.25, 0.5));
+        let miss = Ray::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        assert!(intersect_triangle(&miss, v0, v1, v2, 0.0, f64::MAX).is_none());
+    }
+}
@@ -47,6 +47,38 @@ impl Vec3 {
             z: self.x * other.y - self.y * other.x,
         }
     }
+
+    /// Relative luminance when the vector holds linear Rec. 709 RGB.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
+
+    /// Two unit vectors that together with this unit vector form an orthonormal basis.
+    pub fn orthonormal_basis(self) -> (Self, Self) {
+        let sign = 1.0f64.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            Self::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Self::new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
+
+    pub fn min(self, other: Self) -> Self {
+        Self {
+            x: self.x.min(other.x),
+            y: self.y.min(other.y),
+            z: self.z.min(other.z),
+        }
+    }
+
+    pub fn max(self, other: Self) -> Self {
+        Self {
+            x: self.x.max(other.x),
+            y: self.y.max(other.y),
+            z: self.z.max(other.z),
+        }
+    }
 }
 
 impl Neg for Vec3 {
@@ -220,6 +252,29 @@ mod tests {
         assert_eq!((k * 2.0).cross(i * 2.0), j * 4.0);
     }
 
+    #[test]
+    fn test_orthonormal_basis() {
+        for &n in &[
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 2.0, -3.0).normalize(),
+        ] {
+            let (t, b) = n.orthonormal_basis();
+            assert!((t.length() - 1.0).abs() < 1e-12);
+            assert!((b.length() - 1.0).abs() < 1e-12);
+            assert!(t.dot(n).abs() < 1e-12 && b.dot(n).abs() < 1e-12 && t.dot(b).abs() < 1e-12);
+            assert!((t.cross(b) - n).length() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_min_max() {
+        let i = Vec3::new(1.0, -2.0, 3.0);
+        let j = Vec3::new(-1.0, 2.0, 3.0);
+        assert_eq!(i.min(j), Vec3::new(-1.0, -2.0, 3.0));
+        assert_eq!(i.max(j), Vec3::new(1.0, 2.0, 3.0));
+    }
+
     #[test]
     fn test_neg() {
         let i = Vec3::new(0.0, 1.0, -1.0);
@@ -0,0 +1,289 @@
+use crate::{Camera, HdrImage, Lobe, Ray, RenderSettings, Scene, Vec3};
+use rayon::prelude::*;
+
+/// Whitted-style recursive ray tracer: direct light from point-like lights
+/// with hard shadows, plus recursion along perfect mirror reflection and
+/// refraction. Glossy and diffuse interreflections and area lights, other
+/// than when seen directly, are left out, so the result is deterministic.
+///
+/// `max_depth` counts specular bounces, as for the other integrators.
+pub struct WhittedTracer {
+    max_depth: u32,
+    ambient: Vec3,
+}
+
+impl WhittedTracer {
+    pub fn new(max_depth: u32) -> Self {
+        Self {
+            max_depth,
+            ambient: Vec3::default(),
+        }
+    }
+
+    /// Unshadowed light reaching non-specular surfaces from every direction,
+    /// reflected as if they were Lambertian with the BSDF value towards the
+    /// normal; black by default.
+    pub fn set_ambient(&mut self, ambient: Vec3) {
+        self.ambient = ambient;
+    }
+
+    /// Radiance arriving along `ray`.
+    pub fn li(&self, ray: &Ray, scene: &Scene) -> Vec3 {
+        self.trace(ray, scene, 0)
+    }
+
+    fn trace(&self, ray: &Ray, scene: &Scene, depth: u32) -> Vec3 {
+        let mut ray = *ray;
+        let hit = loop {
+            match scene.world().hit(&ray, 0.001, f64::MAX) {
+                Some(hit) if hit.material().is_interface() => {
+                    ray = Ray::new(hit.point(), ray.direction(), ray.time());
+                }
+                Some(hit) => break hit,
+                None => {
+                    let direction = ray.direction().normalize();
+                    let lights = scene.lights();
+                    return scene
+                        .infinite_lights()
+                        .iter()
+                        .map(|&idx| lights[idx].le(direction))
+                        .fold(Vec3::default(), |acc, le| acc + le);
+                }
+            }
+        };
+        let material = hit.material();
+        let wo = -ray.direction().normalize();
+        let normal = hit.normal();
+        let mut radiance = material.emitted(&hit, wo);
+
+        if !material.is_specular() {
+            for light in scene.lights() {
+                let sample = match light.sample_li(hit.point(), (0.5, 0.5)) {
+                    Some(sample) if sample.is_delta() && sample.pdf() > 0.0 => sample,
+                    _ => continue,
+                };
+                let wi = sample.wi();
+                let f = material.eval(&hit, wo, wi);
+                if f == Vec3::default()
+                    || !scene.unoccluded(hit.point(), wi, sample.distance(), ray.time())
+                {
+                    continue;
+                }
+                radiance += f * sample.radiance() * (wi.dot(normal).abs() / sample.pdf());
+            }
+            let facing = if normal.dot(wo) < 0.0 {
+                -normal
+            } else {
+                normal
+            };
+            radiance += std::f64::consts::PI * material.eval(&hit, wo, facing) * self.ambient;
+            return radiance;
+        }
+
+        if depth >= self.max_depth {
+            return radiance;
+        }
+        // Follow every specular lobe rather than picking one: the first
+        // choice reflects whenever the surface reflects at all, the last
+        // refracts unless light is totally reflected.
+        let mut followed = None;
+        for &uc in &[0.0, 1.0 - f64::EPSILON] {
+            let sample = match material.sample(&hit, wo, uc, (0.5, 0.5)) {
+                Some(sample) => sample,
+                None => continue,
+            };
+            let transmitted = sample.lobe().contains(Lobe::TRANSMISSION);
+            if followed == Some(transmitted) {
+                continue;
+            }
+            followed = Some(transmitted);
+            let weight = sample.value() * sample.wi().dot(normal).abs();
+            let next = Ray::new(hit.point(), sample.wi(), ray.time());
+            radiance += weight * self.trace(&next, scene, depth + 1);
+        }
+        radiance
+    }
+
+    /// Renders the radiance through a regular grid of points in each pixel,
+    /// the samples per pixel rounded down to a square number; a single sample
+    /// goes through the pixel center. The samples are spread evenly over the
+    /// shutter interval, so moving objects blur the same way on every run.
+    pub fn render(&self, scene: &Scene, camera: &Camera, settings: &RenderSettings) -> HdrImage {
+        let (width, height) = (settings.width(), settings.height());
+        let grid = (f64::from(settings.samples_per_pixel().max(1)).sqrt() as u32).max(1);
+        let rows: Vec<Vec<Vec3>> = (0..height)
+            .into_par_iter()
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        let mut acc = Vec3::default();
+                        for j in 0..grid {
+                            for i in 0..grid {
+                                let du = (f64::from(i) + 0.5) / f64::from(grid);
+                                let dv = (f64::from(j) + 0.5) / f64::from(grid);
+                                let u = (f64::from(x) + du) / f64::from(width);
+                                let v = (f64::from(y) + dv) / f64::from(height);
+                                let shutter =
+                                    (f64::from(j * grid + i) + 0.5) / f64::from(grid * grid);
+                                let time =
+                                    camera.time0() + shutter * (camera.time1() - camera.time0());
+                                acc += self.li(&camera.get_ray_at(u, v, time), scene);
+                            }
+                        }
+                        acc / f64::from(grid * grid)
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut image = HdrImage::new(width, height);
+        for (y, row) in (0..height).zip(rows) {
+            for (x, color) in (0..width).zip(row) {
+                image[(x, y)] = color;
+            }
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ConstantEnvironment, Dielectric, HitList, Integrator, Lambertian, Metal, MovingSphere,
+        Phong, PointLight, Sphere,
+    };
+    use std::f64::consts::PI;
+    use std::sync::Arc;
+
+    /// Lit by a point light above, under a black sky.
+    fn lit_scene(world: HitList, light: Vec3) -> Scene {
+        let black = Arc::new(ConstantEnvironment::new(Vec3::default()));
+        let mut scene = Scene::with_environment(world, black);
+        scene.add_light(Arc::new(PointLight::new(
+            light,
+            Vec3::new(10.0, 10.0, 10.0),
+        )));
+        scene
+    }
+
+    fn floor(center: Vec3) -> Sphere {
+        Sphere::new(
+            center - Vec3::new(0.0, 100.5, 0.0),
+            100.0,
+            Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        )
+    }
+
+    #[test]
+    fn test_direct_light_and_hard_shadow() {
+        let mut world = HitList::new();
+        world.push(floor(Vec3::new(0.0, 0.0, -2.0)));
+        world.push(Sphere::new(
+            Vec3::new(-1.0, 0.0, -2.0),
+            0.5,
+            Arc::new(Phong::new(
+                Vec3::new(0.6, 0.2, 0.2),
+                Vec3::new(0.3, 0.3, 0.3),
+                50.0,
+            )),
+        ));
+        let scene = lit_scene(world, Vec3::new(0.0, 3.0, -2.0));
+        let tracer = WhittedTracer::new(5);
+        // Floor point straight below the light: albedo / pi * intensity / r^2.
+        let lit = Ray::new(Vec3::new(0.0, 1.0, -2.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let expected = 0.5 / PI * 10.0 / (3.5 * 3.5);
+        let radiance = tracer.li(&lit, &scene);
+        assert!((radiance.x() - expected).abs() < 1e-9, "{:?}", radiance);
+        // Floor point next to the sphere, which blocks the light.
+        let shadowed = Ray::new(Vec3::new(-3.0, 0.5, -2.0), Vec3::new(1.7, -1.0, 0.0), 0.0);
+        assert_eq!(tracer.li(&shadowed, &scene), Vec3::default());
+
+        let mut ambient = WhittedTracer::new(5);
+        ambient.set_ambient(Vec3::new(1.0, 1.0, 1.0));
+        let radiance = ambient.li(&shadowed, &scene);
+        assert!((radiance - Vec3::new(0.5, 0.5, 0.5)).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_mirror_and_glass_recursion() {
+        let mut world = HitList::new();
+        world.push(floor(Vec3::default()));
+        world.push(Sphere::new(
+            Vec3::new(0.0, 0.0, -2.0),
+            0.5,
+            Arc::new(Metal::new(Vec3::new(0.9, 0.8, 0.7), 0.0)),
+        ));
+        let scene = lit_scene(world, Vec3::new(0.0, 3.0, 0.0));
+        let tracer = WhittedTracer::new(5);
+        // Looking down at the front of the mirror sphere, which reflects the
+        // lit floor point under the camera.
+        let at_mirror = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, -1.5), 0.0);
+        let hit = scene.world().hit(&at_mirror, 0.001, f64::MAX).unwrap();
+        let direction = at_mirror.direction().normalize();
+        let reflected = direction - 2.0 * direction.dot(hit.normal()) * hit.normal();
+        let expected =
+            Vec3::new(0.9, 0.8, 0.7) * tracer.li(&Ray::new(hit.point(), reflected, 0.0), &scene);
+        let radiance = tracer.li(&at_mirror, &scene);
+        assert!(expected.x() > 0.0);
+        assert!((radiance - expected).length() < 1e-9, "{:?}", radiance);
+        // One bounce reaches the floor, none stops at the mirror.
+        let one_bounce = WhittedTracer::new(1).li(&at_mirror, &scene);
+        assert!((one_bounce - expected).length() < 1e-9, "{:?}", one_bounce);
+        assert_eq!(
+            WhittedTracer::new(0).li(&at_mirror, &scene),
+            Vec3::default()
+        );
+
+        // Light reaches a floor point seen through a glass ball, minus what
+        // is reflected at each face; the depth limit cuts off paths bouncing
+        // inside the ball.
+        let mut world = HitList::new();
+        world.push(floor(Vec3::default()));
+        world.push(Sphere::new(
+            Vec3::new(0.0, 1.0, 0.0),
+            0.3,
+            Arc::new(Dielectric::new(1.5)),
+        ));
+        let glass = lit_scene(world, Vec3::new(2.0, 1.0, 0.0));
+        let tracer = WhittedTracer::new(2);
+        let through = Ray::new(Vec3::new(0.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let bare = Ray::new(Vec3::default(), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let direct = tracer.li(&bare, &glass);
+        let expected = direct * (1.0 - 0.04) * (1.0 - 0.04);
+        let radiance = tracer.li(&through, &glass);
+        assert!(direct.x() > 0.0);
+        assert!((radiance - expected).length() < 1e-9, "{:?}", radiance);
+    }
+
+    #[test]
+    fn test_motion_blur_is_deterministic() {
+        let mut world = HitList::new();
+        world.push(floor(Vec3::new(0.0, 0.0, -2.0)));
+        world.push(MovingSphere::new(
+            Vec3::new(-0.5, 0.0, -2.0),
+            Vec3::new(0.5, 0.0, -2.0),
+            0.0,
+            1.0,
+            0.4,
+            Arc::new(Lambertian::new(Vec3::new(0.8, 0.3, 0.3))),
+        ));
+        let scene = lit_scene(world, Vec3::new(0.0, 3.0, -1.0));
+        let camera = Camera::with_shutter(
+            Vec3::new(-1.0, -1.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::default(),
+            0.0,
+            1.0,
+        );
+        let settings = RenderSettings::new(4, 4, 16, Integrator::Whitted(WhittedTracer::new(5)));
+        let tracer = WhittedTracer::new(5);
+        let first = tracer.render(&scene, &camera, &settings);
+        let second = tracer.render(&scene, &camera, &settings);
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(first[(x, y)], second[(x, y)]);
+            }
+        }
+    }
+}